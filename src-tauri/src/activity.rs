@@ -0,0 +1,149 @@
+use crate::clipboard::CopyTextKind;
+use crate::screenshot::{unix_millis, LockExt, TriggerSource};
+use crate::settings::SettingsState;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+/// Number of events kept in memory; the oldest are dropped first
+const MAX_EVENTS: usize = 1000;
+
+/// File in the app data directory the log is appended to with the
+/// `activity_log_file` setting, one JSON event per line
+const LOG_FILE: &str = "activity.jsonl";
+
+/// Previous log file, replaced whenever `LOG_FILE` is rotated
+const ROTATED_LOG_FILE: &str = "activity.1.jsonl";
+
+/// Size past which `LOG_FILE` is rotated
+const MAX_LOG_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Something done with a screenshot
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum Activity {
+    #[serde(rename_all = "camelCase")]
+    Captured {
+        /// Kind of capture, see `CaptureRequest::kind`
+        capture_mode: String,
+        width: u32,
+        height: u32,
+        /// Size of the encoded image
+        bytes: u64,
+        trigger: TriggerSource,
+    },
+    #[serde(rename_all = "camelCase")]
+    Saved {
+        path: String,
+        bytes: u64,
+        /// Written by auto-save rather than a save command
+        auto: bool,
+    },
+    CopiedImage,
+    CopiedPath {
+        path: String,
+    },
+    CopiedText {
+        kind: CopyTextKind,
+    },
+    Deleted,
+    /// Quit went ahead after waiting too long on a running capture or on
+    /// auto-save and recovery writes
+    #[serde(rename_all = "camelCase")]
+    QuitWithPendingWork {
+        capturing: bool,
+        pending_writes: usize,
+    },
+}
+
+/// An entry of the activity log
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityEvent {
+    /// Time of the activity in milliseconds since the Unix epoch
+    pub at: u64,
+    /// Screenshot acted on, `None` when it couldn't be determined
+    pub screenshot_id: Option<u64>,
+    #[serde(flatten)]
+    pub activity: Activity,
+}
+
+/// Log of what was captured, saved and copied during this session
+pub struct ActivityLog {
+    events: Mutex<VecDeque<ActivityEvent>>,
+}
+
+impl ActivityLog {
+    /// Creates an empty log
+    pub fn new() -> Self {
+        Self {
+            events: Mutex::new(VecDeque::new()),
+        }
+    }
+}
+
+/// Records `activity` on screenshot `screenshot_id`, and appends it to the log
+/// file with the `activity_log_file` setting
+pub fn log_event(app: &AppHandle, screenshot_id: Option<u64>, activity: Activity) {
+    let event = ActivityEvent {
+        at: unix_millis(),
+        screenshot_id,
+        activity,
+    };
+
+    if app.state::<SettingsState>().get().activity_log_file {
+        if let Ok(dir) = app.path().app_data_dir() {
+            if let Err(error) = append_to_file(&dir, &event) {
+                eprintln!("Could not write the activity log: {}", error);
+            }
+        }
+    }
+
+    let log = app.state::<ActivityLog>();
+    let mut events = log.events.locked();
+    if events.len() == MAX_EVENTS {
+        events.pop_front();
+    }
+    events.push_back(event);
+}
+
+/// Appends `event` to the log file in `dir`, first rotating the file when
+/// it's grown past `MAX_LOG_FILE_BYTES`
+fn append_to_file(dir: &Path, event: &ActivityEvent) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let path = dir.join(LOG_FILE);
+    if fs::metadata(&path).is_ok_and(|metadata| metadata.len() > MAX_LOG_FILE_BYTES) {
+        fs::rename(&path, dir.join(ROTATED_LOG_FILE)).map_err(|e| e.to_string())?;
+    }
+
+    let mut line = serde_json::to_string(event).map_err(|e| e.to_string())?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(|e| e.to_string())
+}
+
+/// Tauri command: Lists logged activity, newest first. Skips the `offset`
+/// newest events and returns at most `limit`, all by default.
+#[tauri::command]
+pub fn get_activity_log(
+    log: tauri::State<ActivityLog>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> Vec<ActivityEvent> {
+    log.events
+        .locked()
+        .iter()
+        .rev()
+        .skip(offset.unwrap_or(0))
+        .take(limit.unwrap_or(usize::MAX))
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,429 @@
+use crate::activity::{self, Activity};
+use crate::save::{self, SaveError};
+use crate::screenshot::{
+    self, decode_image, encode_to_data_url, local_time, newest_with_image, trigger_screenshot_then,
+    CaptureSource, LockExt, OutputFormat, ScreenshotPayload, ScreenshotState, TriggerSource,
+};
+use crate::settings::SettingsState;
+use screenshots::image::{ImageFormat, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tauri::image::Image;
+use tauri::{AppHandle, Emitter, Manager, Url};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+
+/// Copied text above this size gets flagged as large, some editors stall
+/// when pasting several megabytes
+const LARGE_PASTE_BYTES: usize = 1024 * 1024;
+
+/// Hash identifying an image's size and pixels
+fn image_hash(width: u32, height: u32, rgba: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (width, height, rgba).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Clears the clipboard after the `clipboard_clear_after_secs` setting, if
+/// set, unless it no longer holds the image with `hash` or a newer copy
+/// replaced this timer
+fn schedule_clear(app: &AppHandle, hash: u64) {
+    let Some(secs) = app
+        .state::<SettingsState>()
+        .get()
+        .clipboard_clear_after_secs
+    else {
+        return;
+    };
+    let state = app.state::<ScreenshotState>();
+    let generation = state
+        .clipboard_clear_generation
+        .fetch_add(1, Ordering::SeqCst)
+        + 1;
+    let app = app.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_secs(secs));
+        let state = app.state::<ScreenshotState>();
+        if state.clipboard_clear_generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+
+        let clipboard = app.clipboard();
+        let unchanged = clipboard
+            .read_image()
+            .is_ok_and(|image| image_hash(image.width(), image.height(), image.rgba()) == hash);
+        if unchanged {
+            let _ = clipboard.clear();
+        }
+    });
+}
+
+/// Puts an image on the system clipboard. On Windows it's placed both as PNG
+/// and as a DIB for older apps such as Paint, falling back to the clipboard
+/// plugin when that fails. Starts the `clipboard_clear_after_secs` timer.
+pub fn copy_image(app: &AppHandle, image: &RgbaImage) -> Result<(), String> {
+    let hash = image_hash(image.width(), image.height(), image.as_raw());
+
+    #[cfg(target_os = "windows")]
+    let copied = win32::copy_png_and_dib(app, image).is_ok();
+    #[cfg(not(target_os = "windows"))]
+    let copied = false;
+
+    if !copied {
+        let image = Image::new(image.as_raw(), image.width(), image.height());
+        app.clipboard()
+            .write_image(&image)
+            .map_err(|e| e.to_string())?;
+    }
+    schedule_clear(app, hash);
+    Ok(())
+}
+
+/// Clipboard access through the Win32 API, which the clipboard plugin only
+/// offers one image format of
+#[cfg(target_os = "windows")]
+mod win32 {
+    use crate::screenshot::flatten_alpha;
+    use crate::settings::SettingsState;
+    use clipboard_win::formats::CF_DIB;
+    use clipboard_win::raw::set_without_clear;
+    use clipboard_win::{empty, register_format, Clipboard};
+    use screenshots::image::codecs::png::{CompressionType, FilterType, PngEncoder};
+    use screenshots::image::{ColorType, ImageEncoder, RgbImage, RgbaImage};
+    use tauri::{AppHandle, Manager};
+
+    /// Attempts at opening the clipboard while another app holds it
+    const OPEN_ATTEMPTS: usize = 10;
+
+    /// Size of a `BITMAPINFOHEADER`
+    const BITMAP_INFO_HEADER_SIZE: u32 = 40;
+
+    /// Bottom-up 24-bit `CF_DIB`: a `BITMAPINFOHEADER` followed by BGR rows
+    /// padded to four bytes, last row first
+    fn dib(image: &RgbImage) -> Vec<u8> {
+        let (width, height) = image.dimensions();
+        let stride = (width as usize * 3).next_multiple_of(4);
+        let pixels_size = stride * height as usize;
+
+        let mut dib = Vec::with_capacity(BITMAP_INFO_HEADER_SIZE as usize + pixels_size);
+        dib.extend_from_slice(&BITMAP_INFO_HEADER_SIZE.to_le_bytes());
+        dib.extend_from_slice(&(width as i32).to_le_bytes());
+        dib.extend_from_slice(&(height as i32).to_le_bytes());
+        dib.extend_from_slice(&1u16.to_le_bytes()); // planes
+        dib.extend_from_slice(&24u16.to_le_bytes()); // bits per pixel
+        dib.extend_from_slice(&0u32.to_le_bytes()); // BI_RGB, uncompressed
+        dib.extend_from_slice(&(pixels_size as u32).to_le_bytes());
+        dib.extend_from_slice(&[0; 16]); // resolution and palette, unused
+
+        for row in image.rows().rev() {
+            let start = dib.len();
+            for pixel in row {
+                let [r, g, b] = pixel.0;
+                dib.extend_from_slice(&[b, g, r]);
+            }
+            dib.resize(start + stride, 0);
+        }
+        dib
+    }
+
+    /// Replaces the clipboard contents with `image` as PNG, keeping its alpha
+    /// channel, and as a DIB flattened onto the `alpha_background` setting
+    pub fn copy_png_and_dib(app: &AppHandle, image: &RgbaImage) -> Result<(), String> {
+        let mut png = Vec::new();
+        PngEncoder::new_with_quality(&mut png, CompressionType::Fast, FilterType::Adaptive)
+            .write_image(
+                image.as_raw(),
+                image.width(),
+                image.height(),
+                ColorType::Rgba8,
+            )
+            .map_err(|e| e.to_string())?;
+        let background = app.state::<SettingsState>().get().alpha_background;
+        let dib = dib(&flatten_alpha(image, background));
+        let png_format = register_format("PNG").ok_or("Could not register the PNG format")?;
+
+        let _clipboard = Clipboard::new_attempts(OPEN_ATTEMPTS).map_err(|e| e.to_string())?;
+        empty().map_err(|e| e.to_string())?;
+        // PNG first, apps take the first format they understand
+        set_without_clear(png_format.get(), &png).map_err(|e| e.to_string())?;
+        set_without_clear(CF_DIB, &dib).map_err(|e| e.to_string())
+    }
+}
+
+/// Decodes the current screenshot from its stored bytes
+fn current_image(state: &ScreenshotState) -> Result<RgbaImage, String> {
+    let bytes = state.with_current(|stored| stored.bytes.clone())?;
+
+    decode_image(&bytes)
+}
+
+/// Tauri command: Copies the current screenshot to the clipboard as an image
+#[tauri::command(async)]
+pub fn copy_screenshot_to_clipboard(
+    app_handle: AppHandle,
+    state: tauri::State<ScreenshotState>,
+) -> Result<(), String> {
+    let image = current_image(&state)?;
+    copy_image(&app_handle, &image)?;
+    activity::log_event(&app_handle, state.current().ok(), Activity::CopiedImage);
+    Ok(())
+}
+
+/// Copies the current screenshot like `copy_screenshot_to_clipboard`, off the
+/// main thread, and emits `screenshot-copied` with its id. Like
+/// `copy_last_screenshot`, any failure, including there being no current
+/// screenshot, is emitted as `screenshot-copy-failed` with the reason.
+pub fn copy_current_screenshot(app: &AppHandle) {
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = app_handle
+            .state::<ScreenshotState>()
+            .current()
+            .and_then(|id| copy_screenshot_by_id(&app_handle, id));
+        if let Err(message) = result {
+            app_handle.emit("screenshot-copy-failed", message);
+        }
+    });
+}
+
+/// Copies screenshot `id` to the clipboard as an image and emits
+/// `screenshot-copied` with its id
+fn copy_screenshot_by_id(app: &AppHandle, id: u64) -> Result<(), String> {
+    let bytes = app
+        .state::<ScreenshotState>()
+        .screenshots
+        .locked()
+        .get(&id)
+        .map(|stored| stored.bytes.clone())
+        .ok_or_else(|| "Screenshot not found".to_string())?;
+    copy_image(app, &decode_image(&bytes)?)?;
+    activity::log_event(app, Some(id), Activity::CopiedImage);
+    app.emit("screenshot-copied", id);
+    Ok(())
+}
+
+/// Copies the most recent screenshot to the clipboard without opening any
+/// window, emitting `screenshot-copied` with its id. When there's none, one is
+/// captured and copied first with the `capture_before_copy` setting; otherwise,
+/// or when copying fails, `screenshot-copy-failed` is emitted with the reason.
+pub fn copy_last_screenshot(app: &AppHandle, trigger: TriggerSource) {
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app_handle.state::<ScreenshotState>();
+        let result = match newest_with_image(&app_handle, &state) {
+            Some(id) => copy_screenshot_by_id(&app_handle, id),
+            None if app_handle
+                .state::<SettingsState>()
+                .get()
+                .capture_before_copy =>
+            {
+                trigger_screenshot_then(&app_handle, trigger, |app, id| {
+                    if let Err(message) = copy_screenshot_by_id(app, id) {
+                        app.emit("screenshot-copy-failed", message);
+                    }
+                });
+                return;
+            }
+            None => Err("No screenshot to copy".to_string()),
+        };
+        if let Err(message) = result {
+            app_handle.emit("screenshot-copy-failed", message);
+        }
+    });
+}
+
+/// Puts a reference to the file at `path` on the clipboard, so it can be pasted
+/// into Explorer or Finder. Replaces what the clipboard held.
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+fn write_file_reference(path: &str) -> Result<(), arboard::Error> {
+    arboard::Clipboard::new()?.set().file_list(&[path])
+}
+
+/// The clipboard writes `after_capture_copy` makes, so which one it picks can
+/// be tested without a system clipboard
+pub trait CopyTarget {
+    fn copy_image(&self, image: &RgbaImage) -> Result<(), String>;
+    fn copy_path(&self, path: &str) -> Result<(), String>;
+}
+
+impl CopyTarget for AppHandle {
+    fn copy_image(&self, image: &RgbaImage) -> Result<(), String> {
+        copy_image(self, image)
+    }
+
+    fn copy_path(&self, path: &str) -> Result<(), String> {
+        copy_path(self, path)
+    }
+}
+
+/// Puts `path` on the clipboard as a file reference on macOS and Windows, or as
+/// text elsewhere and when the file reference can't be written
+pub fn copy_path(app: &AppHandle, path: &str) -> Result<(), String> {
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    if write_file_reference(path).is_ok() {
+        return Ok(());
+    }
+    app.clipboard().write_text(path).map_err(|e| e.to_string())
+}
+
+/// Tauri command: Copies the absolute path of the current screenshot's file to
+/// the clipboard with `copy_path` and returns it. A screenshot not saved yet is
+/// written first, see `save::saved_path`.
+#[tauri::command(async)]
+pub fn copy_screenshot_path_to_clipboard(
+    app_handle: AppHandle,
+    state: tauri::State<ScreenshotState>,
+) -> Result<String, SaveError> {
+    let id = state.current()?;
+    let path = save::saved_path(&app_handle, &state, id)?;
+    copy_path(&app_handle, &path)?;
+    activity::log_event(
+        &app_handle,
+        Some(id),
+        Activity::CopiedPath { path: path.clone() },
+    );
+    Ok(path)
+}
+
+/// Errors returned by `paste_image_from_clipboard`.
+/// Serialized with a `kind` tag like `WindowError`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "camelCase")]
+pub enum PasteError {
+    /// The clipboard holds neither an image nor the path of an image file
+    ClipboardEmpty,
+    Failed(String),
+}
+
+impl From<String> for PasteError {
+    fn from(message: String) -> Self {
+        Self::Failed(message)
+    }
+}
+
+/// Image file named by clipboard text, either a plain path or a `file://` URL
+/// as file managers copy them. Only the first line is considered.
+fn image_path_from_text(text: &str) -> Option<PathBuf> {
+    let line = text.lines().next()?.trim();
+    let path = match Url::parse(line) {
+        Ok(url) if url.scheme() == "file" => url.to_file_path().ok()?,
+        _ => PathBuf::from(line),
+    };
+    (path.is_file() && ImageFormat::from_path(&path).is_ok()).then_some(path)
+}
+
+/// Tauri command: Imports the image on the clipboard as the current screenshot
+/// and opens it in the editor. When the clipboard holds the path of an image
+/// file instead, that file is imported like `load_screenshot_from_file`.
+#[tauri::command(async)]
+pub fn paste_image_from_clipboard(
+    app_handle: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    format: Option<OutputFormat>,
+) -> Result<ScreenshotPayload, PasteError> {
+    let clipboard = app_handle.clipboard();
+    let image = clipboard.read_image().ok().and_then(|image| {
+        RgbaImage::from_raw(image.width(), image.height(), image.rgba().to_vec())
+    });
+
+    let Some(image) = image else {
+        let path = clipboard
+            .read_text()
+            .ok()
+            .and_then(|text| image_path_from_text(&text))
+            .ok_or(PasteError::ClipboardEmpty)?;
+        let path = path.to_string_lossy().into_owned();
+        return Ok(screenshot::load_screenshot_from_file(
+            app_handle, state, path, format,
+        )?);
+    };
+
+    let (data_url, payload) = screenshot::publish_capture(
+        &app_handle,
+        &state,
+        &image,
+        None,
+        screenshot::unix_millis(),
+        format.unwrap_or_default(),
+        "pasted",
+        CaptureSource::Clipboard,
+        TriggerSource::Ipc,
+    )?;
+    screenshot::show_editor_window(app_handle)?;
+    Ok(ScreenshotPayload {
+        data_url: Some(data_url),
+        ..payload
+    })
+}
+
+/// Text form of a screenshot for `copy_screenshot_as`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CopyTextKind {
+    /// `data:image/png;base64,...`
+    DataUrl,
+    /// `![Screenshot <time>](data:...)`
+    Markdown,
+    /// `<img src="data:..." alt="Screenshot <time>" width=".." height="..">`
+    HtmlImgTag,
+}
+
+/// Result of `copy_screenshot_as`
+#[derive(Clone, Debug, Serialize)]
+pub struct CopiedText {
+    /// Length of the copied text in bytes
+    pub bytes: usize,
+    /// Whether the text is over `LARGE_PASTE_BYTES`, so pasting it may be slow
+    pub large: bool,
+}
+
+/// Tauri command: Copies the current screenshot to the clipboard as text
+/// embedding it as a base64 data URL, optionally wrapped in Markdown or an
+/// HTML `img` tag with the capture time as alt text.
+#[tauri::command(async)]
+pub fn copy_screenshot_as(
+    app_handle: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    kind: CopyTextKind,
+) -> Result<CopiedText, String> {
+    let (bytes, format, captured_at, width, height) = state.with_current(|stored| {
+        (
+            stored.bytes.clone(),
+            stored.format,
+            stored.captured_at,
+            stored.width,
+            stored.height,
+        )
+    })?;
+    let data_url = encode_to_data_url(&bytes, format.mime());
+
+    let alt = format!(
+        "Screenshot {}",
+        local_time(captured_at).format("%Y-%m-%d %H:%M:%S")
+    );
+    let text = match kind {
+        CopyTextKind::DataUrl => data_url,
+        CopyTextKind::Markdown => format!("![{}]({})", alt, data_url),
+        CopyTextKind::HtmlImgTag => format!(
+            "<img src=\"{}\" alt=\"{}\" width=\"{}\" height=\"{}\">",
+            data_url, alt, width, height
+        ),
+    };
+
+    let copied = CopiedText {
+        bytes: text.len(),
+        large: text.len() > LARGE_PASTE_BYTES,
+    };
+    app_handle
+        .clipboard()
+        .write_text(text)
+        .map_err(|e| e.to_string())?;
+    activity::log_event(
+        &app_handle,
+        state.current().ok(),
+        Activity::CopiedText { kind },
+    );
+    Ok(copied)
+}
@@ -0,0 +1,47 @@
+/// ICC profile of the display with the given CoreGraphics display id, e.g.
+/// Display P3 on wide-gamut Macs. `None` when the display has no profile.
+#[cfg(target_os = "macos")]
+pub fn display_icc_profile(display_id: u32) -> Option<Vec<u8>> {
+    use std::ffi::c_void;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGDisplayCopyColorSpace(display: u32) -> *const c_void;
+        fn CGColorSpaceCopyICCData(space: *const c_void) -> *const c_void;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFDataGetLength(data: *const c_void) -> isize;
+        fn CFDataGetBytePtr(data: *const c_void) -> *const u8;
+        fn CFRelease(object: *const c_void);
+    }
+
+    // SAFETY: both Copy functions return owned references (or null) that are
+    // released below, and the byte pointer is only read while `data` is alive
+    unsafe {
+        let space = CGDisplayCopyColorSpace(display_id);
+        if space.is_null() {
+            return None;
+        }
+        let data = CGColorSpaceCopyICCData(space);
+        CFRelease(space);
+        if data.is_null() {
+            return None;
+        }
+
+        let length = CFDataGetLength(data).max(0) as usize;
+        let bytes = CFDataGetBytePtr(data);
+        let profile = (!bytes.is_null() && length > 0)
+            .then(|| std::slice::from_raw_parts(bytes, length).to_vec());
+        CFRelease(data);
+        profile
+    }
+}
+
+/// ICC profile of the display with the given id. Only macOS exposes display
+/// profiles, other platforms are tagged sRGB instead.
+#[cfg(not(target_os = "macos"))]
+pub fn display_icc_profile(_display_id: u32) -> Option<Vec<u8>> {
+    None
+}
@@ -0,0 +1,371 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+
+/// Template used when the settings don't set one
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "Screenshot_{date}_{time}";
+
+/// Format of `{date}` without a format string
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Format of `{time}` without a format string
+const DEFAULT_TIME_FORMAT: &str = "%H-%M-%S";
+
+/// Widest zero-padded `{counter:N}`
+const MAX_COUNTER_DIGITS: u32 = 9;
+
+/// Characters not allowed in file names on some platform, replaced with `-`
+const RESERVED_CHARS: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// One piece of a parsed filename template
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    /// Capture date, with a chrono format string
+    Date(String),
+    /// Capture time, with a chrono format string
+    Time(String),
+    /// Index of the captured screen, `all` when several screens were combined
+    Screen,
+    /// Kind of capture, e.g. `region`
+    Mode,
+    Width,
+    Height,
+    /// Auto-incrementing counter, zero-padded to and wrapping at the given number of digits
+    Counter(Option<u32>),
+    /// Label given to the screenshot, empty without one
+    Label,
+}
+
+/// Piece of a pattern recognizing rendered file names
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Matcher {
+    Literal(String),
+    /// One or more digits, possibly space-padded
+    Digits,
+    /// One or more letters, digits, `+` or `-`
+    Word,
+    /// Any text, including none
+    Any,
+}
+
+/// What a filename template is rendered for
+pub struct FilenameContext<'a> {
+    pub time: DateTime<Local>,
+    pub screen_index: Option<usize>,
+    pub capture_mode: &'a str,
+    pub width: u32,
+    pub height: u32,
+    pub counter: u64,
+    pub label: Option<&'a str>,
+}
+
+/// A filename template such as `Screenshot_{date:%Y-%m-%d}_{counter:3}`.
+///
+/// Tokens are `{date}`, `{time}` (both optionally with a chrono format after a
+/// colon), `{screen}`, `{mode}`, `{width}`, `{height}`, `{label}` and
+/// `{counter}` or `{counter:N}` for N zero-padded digits. `{{` and `}}` are
+/// literal braces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilenameTemplate {
+    parts: Vec<Part>,
+}
+
+impl FilenameTemplate {
+    /// Parses a template, rejecting unknown tokens, unbalanced braces and invalid date formats
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut token = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => token.push(c),
+                            None => return Err(format!("Unclosed token {{{}", token)),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(parse_token(&token)?);
+                }
+                '}' => return Err("Unmatched } in file name template, use }} for a brace".into()),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        if parts.is_empty() {
+            return Err("File name template is empty".into());
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// Renders a file name without extension. Characters some platforms
+    /// reject in file names are replaced with `-`.
+    pub fn render(&self, context: &FilenameContext) -> String {
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => name.push_str(text),
+                Part::Date(format) | Part::Time(format) => {
+                    name.push_str(&context.time.format(format).to_string())
+                }
+                Part::Screen => match context.screen_index {
+                    Some(index) => name.push_str(&index.to_string()),
+                    None => name.push_str("all"),
+                },
+                Part::Mode => name.push_str(context.capture_mode),
+                Part::Width => name.push_str(&context.width.to_string()),
+                Part::Height => name.push_str(&context.height.to_string()),
+                Part::Counter(None) => name.push_str(&context.counter.to_string()),
+                Part::Counter(Some(digits)) => {
+                    let wrapped = context.counter % 10u64.pow(*digits);
+                    name.push_str(&format!("{:0width$}", wrapped, width = *digits as usize));
+                }
+                Part::Label => name.push_str(context.label.unwrap_or_default()),
+            }
+        }
+
+        sanitize(&name)
+    }
+
+    /// Whether `stem` could have been rendered from this template, optionally
+    /// followed by the `_N` suffix added to avoid overwriting an existing file
+    pub fn matches(&self, stem: &str) -> bool {
+        let mut matchers = Vec::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => matchers.push(Matcher::Literal(sanitize(text))),
+                Part::Date(format) | Part::Time(format) => {
+                    for item in StrftimeItems::new(format) {
+                        matchers.push(match item {
+                            Item::Literal(text) => Matcher::Literal(sanitize(text)),
+                            Item::OwnedLiteral(text) => Matcher::Literal(sanitize(&text)),
+                            Item::Space(text) => Matcher::Literal(text.to_string()),
+                            Item::OwnedSpace(text) => Matcher::Literal(text.to_string()),
+                            Item::Numeric(..) => Matcher::Digits,
+                            _ => Matcher::Word,
+                        });
+                    }
+                }
+                Part::Width | Part::Height | Part::Counter(_) => matchers.push(Matcher::Digits),
+                Part::Screen | Part::Mode => matchers.push(Matcher::Word),
+                Part::Label => matchers.push(Matcher::Any),
+            }
+        }
+        matches_from(&matchers, stem)
+    }
+}
+
+/// Matches `text` against `matchers` followed by an optional `_N` suffix, backtracking
+/// over how many characters each `Digits` and `Word` matcher consumes
+fn matches_from(matchers: &[Matcher], text: &str) -> bool {
+    let Some((matcher, rest)) = matchers.split_first() else {
+        return text.is_empty()
+            || text
+                .strip_prefix('_')
+                .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+    };
+
+    let accepts: fn(char) -> bool = match matcher {
+        Matcher::Literal(literal) => {
+            return text
+                .strip_prefix(literal.as_str())
+                .is_some_and(|text| matches_from(rest, text));
+        }
+        Matcher::Any => {
+            return (0..=text.len())
+                .filter(|&end| text.is_char_boundary(end))
+                .any(|end| matches_from(rest, &text[end..]));
+        }
+        Matcher::Digits => |c| c.is_ascii_digit() || c == ' ',
+        Matcher::Word => |c| c.is_alphanumeric() || c == '+' || c == '-',
+    };
+    let run = text
+        .char_indices()
+        .find(|&(_, c)| !accepts(c))
+        .map_or(text.len(), |(index, _)| index);
+    (1..=run)
+        .rev()
+        .filter(|&end| text.is_char_boundary(end))
+        .any(|end| matches_from(rest, &text[end..]))
+}
+
+/// Replaces characters some platforms reject in file names with `-`
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if RESERVED_CHARS.contains(&c) || c.is_control() {
+                '-'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Parses the inside of a `{...}` token
+fn parse_token(token: &str) -> Result<Part, String> {
+    let (name, argument) = match token.split_once(':') {
+        Some((name, argument)) => (name, Some(argument)),
+        None => (token, None),
+    };
+
+    match (name, argument) {
+        ("date", format) => Ok(Part::Date(date_format(format, DEFAULT_DATE_FORMAT)?)),
+        ("time", format) => Ok(Part::Time(date_format(format, DEFAULT_TIME_FORMAT)?)),
+        ("screen", None) => Ok(Part::Screen),
+        ("mode", None) => Ok(Part::Mode),
+        ("width", None) => Ok(Part::Width),
+        ("height", None) => Ok(Part::Height),
+        ("label", None) => Ok(Part::Label),
+        ("counter", None) => Ok(Part::Counter(None)),
+        ("counter", Some(digits)) => match digits.parse::<u32>() {
+            Ok(digits @ 1..=MAX_COUNTER_DIGITS) => Ok(Part::Counter(Some(digits))),
+            _ => Err(format!(
+                "Counter width must be 1 to {} digits, got {{counter:{}}}",
+                MAX_COUNTER_DIGITS, digits
+            )),
+        },
+        ("screen" | "mode" | "width" | "height" | "label", Some(_)) => {
+            Err(format!("{{{}}} takes no format", name))
+        }
+        _ => Err(format!("Unknown file name token {{{}}}", token)),
+    }
+}
+
+/// Checks a chrono format string, falling back to `default` when none is given
+fn date_format(format: Option<&str>, default: &str) -> Result<String, String> {
+    let format = format.unwrap_or(default);
+    if format.is_empty() || StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(format!("Invalid date format {:?}", format));
+    }
+    Ok(format.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn context(counter: u64, label: Option<&str>) -> FilenameContext<'_> {
+        FilenameContext {
+            time: Local.with_ymd_and_hms(2024, 3, 5, 14, 7, 9).unwrap(),
+            screen_index: Some(1),
+            capture_mode: "region",
+            width: 800,
+            height: 600,
+            counter,
+            label,
+        }
+    }
+
+    fn render(template: &str, context: &FilenameContext) -> String {
+        FilenameTemplate::parse(template).unwrap().render(context)
+    }
+
+    #[test]
+    fn renders_default_template() {
+        assert_eq!(
+            render(DEFAULT_FILENAME_TEMPLATE, &context(0, None)),
+            "Screenshot_2024-03-05_14-07-09"
+        );
+    }
+
+    #[test]
+    fn renders_every_token() {
+        assert_eq!(
+            render(
+                "{date:%d.%m}-{time:%H%M} {screen} {mode} {width}x{height} {counter} [{label}]",
+                &context(42, Some("bug")),
+            ),
+            "05.03-1407 1 region 800x600 42 [bug]"
+        );
+        let all_screens = FilenameContext {
+            screen_index: None,
+            ..context(0, None)
+        };
+        assert_eq!(render("{screen}", &all_screens), "all");
+        assert_eq!(render("a{label}b", &context(0, None)), "ab");
+    }
+
+    #[test]
+    fn counter_is_padded_and_wraps() {
+        assert_eq!(render("{counter:3}", &context(7, None)), "007");
+        assert_eq!(render("{counter:3}", &context(1234, None)), "234");
+        assert_eq!(render("{counter}", &context(1234, None)), "1234");
+    }
+
+    #[test]
+    fn escapes_braces_and_sanitizes() {
+        assert_eq!(render("{{x}}", &context(0, None)), "{x}");
+        assert_eq!(render("{time:%H:%M}", &context(0, None)), "14-07");
+        assert_eq!(render("{label}", &context(0, Some("a/b?"))), "a-b-");
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        for template in [
+            "",
+            "{unknown}",
+            "{date",
+            "a}b",
+            "{counter:0}",
+            "{counter:10}",
+            "{counter:x}",
+            "{width:5}",
+            "{date:%Q}",
+            "{date:}",
+        ] {
+            assert!(
+                FilenameTemplate::parse(template).is_err(),
+                "{:?} should be rejected",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn matches_rendered_names() {
+        for template in [
+            DEFAULT_FILENAME_TEMPLATE,
+            "{mode}_{screen}_{width}x{height}_{counter:4}",
+            "shot {label} {date:%b %e}",
+        ] {
+            let parsed = FilenameTemplate::parse(template).unwrap();
+            let name = parsed.render(&context(12, Some("some label")));
+            assert!(
+                parsed.matches(&name),
+                "{:?} should match {:?}",
+                template,
+                name
+            );
+            assert!(parsed.matches(&format!("{}_2", name)));
+            assert!(!parsed.matches(&format!("{}_", name)));
+            assert!(!parsed.matches(&format!("_{}", name)));
+        }
+    }
+
+    #[test]
+    fn rejects_unrelated_names() {
+        let parsed = FilenameTemplate::parse(DEFAULT_FILENAME_TEMPLATE).unwrap();
+        assert!(!parsed.matches("Screenshot_2024-03-05"));
+        assert!(!parsed.matches("Screenshot_2024-03-05_14-07-09_copy"));
+        assert!(!parsed.matches("holiday"));
+    }
+}
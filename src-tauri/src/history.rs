@@ -0,0 +1,517 @@
+use crate::screenshot::{
+    CaptureSource, HistoryEntry, LockExt, OutputFormat, ScreenshotState, StoredScreenshot,
+    TriggerSource,
+};
+use crate::settings::SettingsState;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Folder inside the app data directory holding the persisted history
+const HISTORY_DIR: &str = "history";
+
+/// File listing the persisted screenshots, inside `HISTORY_DIR`
+const INDEX_FILE: &str = "index.json";
+
+/// Extension of the files holding a screenshot's thumbnail data URL
+const THUMBNAIL_EXTENSION: &str = "thumbnail";
+
+/// Payload of the `history-persist-failed` event
+#[derive(Clone, Debug, Serialize)]
+pub struct PersistFailed {
+    /// Screenshot whose files couldn't be written, `None` for the index
+    pub id: Option<u64>,
+    pub error: String,
+}
+
+/// A screenshot as listed in the index file. Images and thumbnails are kept
+/// in files of their own, so the index stays small.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexEntry {
+    id: u64,
+    format: OutputFormat,
+    width: u32,
+    height: u32,
+    screen_id: Option<u32>,
+    screen_index: Option<usize>,
+    captured_at: u64,
+    capture_mode: String,
+    saved_path: Option<String>,
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default)]
+    promoted_at: Option<u64>,
+    #[serde(default)]
+    label: Option<String>,
+    #[serde(default)]
+    source: Option<CaptureSource>,
+    #[serde(default)]
+    trigger: Option<TriggerSource>,
+    /// Whether the full image file is kept, not only the thumbnail
+    full_image: bool,
+}
+
+impl From<&HistoryEntry> for IndexEntry {
+    fn from(entry: &HistoryEntry) -> Self {
+        Self {
+            id: entry.id,
+            format: entry.format,
+            width: entry.width,
+            height: entry.height,
+            screen_id: entry.screen_id,
+            screen_index: entry.screen_index,
+            captured_at: entry.captured_at,
+            capture_mode: entry.capture_mode.clone(),
+            saved_path: entry.saved_path.clone(),
+            pinned: entry.pinned,
+            promoted_at: entry.promoted_at,
+            label: entry.label.clone(),
+            source: entry.source.clone(),
+            trigger: entry.trigger,
+            full_image: entry.full_image,
+        }
+    }
+}
+
+/// Folder the history is persisted to, `None` when persisting is off or
+/// there's no app data directory
+fn history_dir(app: &AppHandle) -> Option<PathBuf> {
+    if !app.state::<SettingsState>().get().persist_history {
+        return None;
+    }
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join(HISTORY_DIR))
+}
+
+/// File holding the full image of screenshot `id`
+fn image_path(dir: &Path, id: u64, format: OutputFormat) -> PathBuf {
+    dir.join(format!("{}.{}", id, format.extension()))
+}
+
+/// File holding the thumbnail data URL of screenshot `id`
+fn thumbnail_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{}.{}", id, THUMBNAIL_EXTENSION))
+}
+
+/// Writes the image and thumbnail of a new screenshot. It's listed in the
+/// index by the next `write_index`.
+pub fn save(app: &AppHandle, id: u64, stored: &StoredScreenshot) -> Result<(), String> {
+    match history_dir(app) {
+        Some(dir) => save_in(&dir, id, stored),
+        None => Ok(()),
+    }
+}
+
+/// `save` into the history folder `dir`
+fn save_in(dir: &Path, id: u64, stored: &StoredScreenshot) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    fs::write(image_path(dir, id, stored.format), stored.bytes.as_slice())
+        .map_err(|e| e.to_string())?;
+    fs::write(thumbnail_path(dir, id), &stored.thumbnail_data_url).map_err(|e| e.to_string())
+}
+
+/// Rewrites the index from the screenshots currently in the history
+pub fn write_index(app: &AppHandle, state: &ScreenshotState) -> Result<(), String> {
+    match history_dir(app) {
+        Some(dir) => write_index_in(&dir, state),
+        None => Ok(()),
+    }
+}
+
+/// `write_index` into the history folder `dir`
+fn write_index_in(dir: &Path, state: &ScreenshotState) -> Result<(), String> {
+    let entries: Vec<IndexEntry> = {
+        let screenshots = state.screenshots.locked();
+        let thumbnail_history = state.thumbnail_history.locked();
+        screenshots
+            .iter()
+            .map(|(id, stored)| stored.history_entry(*id))
+            .chain(thumbnail_history.values().cloned())
+            .filter(|entry| !entry.memory_only)
+            .map(|entry| IndexEntry::from(&entry))
+            .collect()
+    };
+
+    // Written next to the index and renamed over it, so a crash never leaves half an index
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let json = serde_json::to_string(&entries).map_err(|e| e.to_string())?;
+    let temp = dir.join(format!("{}.tmp", INDEX_FILE));
+    fs::write(&temp, json).map_err(|e| e.to_string())?;
+    fs::rename(&temp, dir.join(INDEX_FILE)).map_err(|e| e.to_string())
+}
+
+/// Reports a failed `save` or `write_index` that no command can return,
+/// e.g. on capture, by emitting `history-persist-failed`
+pub fn report(app: &AppHandle, id: Option<u64>, result: Result<(), String>) {
+    if let Err(error) = result {
+        eprintln!("Could not persist the screenshot history: {}", error);
+        app.emit("history-persist-failed", PersistFailed { id, error });
+    }
+}
+
+/// Deletes the full image of screenshot `id`, keeping its thumbnail
+pub fn remove_image(app: &AppHandle, id: u64, format: OutputFormat) {
+    if let Some(dir) = history_dir(app) {
+        let _ = fs::remove_file(image_path(&dir, id, format));
+    }
+}
+
+/// Deletes every file of screenshot `id`
+pub fn remove(app: &AppHandle, id: u64, format: OutputFormat) {
+    if let Some(dir) = history_dir(app) {
+        remove_in(&dir, id, format);
+    }
+}
+
+/// `remove` from the history folder `dir`
+fn remove_in(dir: &Path, id: u64, format: OutputFormat) {
+    let _ = fs::remove_file(image_path(dir, id, format));
+    let _ = fs::remove_file(thumbnail_path(dir, id));
+}
+
+/// Lists the persisted screenshots in the history without loading their full
+/// images, which `rehydrate` does on demand. Entries whose files are missing,
+/// or that can't be parsed, are dropped from the index. Run once on startup,
+/// before anything is captured.
+pub fn load(app: &AppHandle) {
+    let Some(dir) = history_dir(app) else {
+        return;
+    };
+    let state = app.state::<ScreenshotState>();
+    if load_from(&dir, &state) {
+        report(app, None, write_index(app, &state));
+    }
+}
+
+/// `load` from the history folder `dir`. Returns whether entries were pruned,
+/// so the index needs rewriting.
+fn load_from(dir: &Path, state: &ScreenshotState) -> bool {
+    let Ok(json) = fs::read_to_string(dir.join(INDEX_FILE)) else {
+        return false;
+    };
+    let Ok(values) = serde_json::from_str::<Vec<serde_json::Value>>(&json) else {
+        eprintln!("Screenshot history index is corrupt, starting with an empty history");
+        return false;
+    };
+
+    let mut pruned = false;
+    let mut entries = Vec::new();
+    for value in values {
+        let Ok(entry) = serde_json::from_value::<IndexEntry>(value) else {
+            eprintln!("Dropping unreadable screenshot history entry");
+            pruned = true;
+            continue;
+        };
+        let Ok(thumbnail_data_url) = fs::read_to_string(thumbnail_path(dir, entry.id)) else {
+            eprintln!(
+                "Dropping screenshot {} from history, its thumbnail is missing",
+                entry.id
+            );
+            remove_in(dir, entry.id, entry.format);
+            pruned = true;
+            continue;
+        };
+        let full_image = entry.full_image && image_path(dir, entry.id, entry.format).is_file();
+        if entry.full_image && !full_image {
+            eprintln!("Screenshot {} in history lost its image file", entry.id);
+            pruned = true;
+        }
+
+        entries.push(HistoryEntry {
+            id: entry.id,
+            thumbnail_data_url,
+            format: entry.format,
+            width: entry.width,
+            height: entry.height,
+            screen_id: entry.screen_id,
+            screen_index: entry.screen_index,
+            captured_at: entry.captured_at,
+            capture_mode: entry.capture_mode,
+            saved_path: entry.saved_path,
+            pinned: entry.pinned,
+            promoted_at: entry.promoted_at,
+            label: entry.label,
+            source: entry.source,
+            trigger: entry.trigger,
+            full_image,
+            memory_only: false,
+        });
+    }
+
+    if let Some(last) = entries.iter().map(|entry| entry.id).max() {
+        state.next_id.fetch_max(last + 1, Ordering::SeqCst);
+    }
+    state
+        .thumbnail_history
+        .locked()
+        .extend(entries.into_iter().map(|entry| (entry.id, entry)));
+    pruned
+}
+
+/// Loads the full image of a persisted screenshot back into memory. Does
+/// nothing when it's already there or wasn't persisted; a screenshot whose
+/// file is missing or corrupt is dropped from the history.
+pub fn rehydrate(app: &AppHandle, state: &ScreenshotState, id: u64) {
+    let Some(dir) = history_dir(app) else {
+        return;
+    };
+    if !rehydrate_from(&dir, state, id) {
+        report(app, None, write_index(app, state));
+    }
+}
+
+/// `rehydrate` from the history folder `dir`. Returns `false` when the
+/// screenshot was dropped, so the index needs rewriting.
+fn rehydrate_from(dir: &Path, state: &ScreenshotState, id: u64) -> bool {
+    let Some(entry) = state
+        .thumbnail_history
+        .locked()
+        .get(&id)
+        .filter(|entry| entry.full_image)
+        .cloned()
+    else {
+        return true;
+    };
+
+    let bytes = fs::read(image_path(dir, id, entry.format))
+        .ok()
+        .filter(|bytes| screenshots::image::guess_format(bytes).is_ok());
+    let Some(bytes) = bytes else {
+        eprintln!(
+            "Dropping screenshot {} from history, its image file is unreadable",
+            id
+        );
+        state.thumbnail_history.locked().remove(&id);
+        remove_in(dir, id, entry.format);
+        return false;
+    };
+
+    let stored = StoredScreenshot {
+        bytes: Arc::new(bytes),
+        format: entry.format,
+        width: entry.width,
+        height: entry.height,
+        screen_id: entry.screen_id,
+        captured_at: entry.captured_at,
+        thumbnail_data_url: entry.thumbnail_data_url,
+        screen_index: entry.screen_index,
+        capture_mode: entry.capture_mode,
+        saved_path: entry.saved_path,
+        pinned: entry.pinned,
+        promoted_at: entry.promoted_at,
+        label: entry.label,
+        source: entry.source,
+        trigger: entry.trigger,
+        memory_only: false,
+    };
+    let mut screenshots = state.screenshots.locked();
+    if state.thumbnail_history.locked().remove(&id).is_some() {
+        screenshots.insert(id, stored);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names() {
+        let dir = Path::new("/data/history");
+        assert_eq!(
+            image_path(dir, 7, OutputFormat::Jpeg { quality: 80 }),
+            Path::new("/data/history/7.jpg")
+        );
+        assert_eq!(
+            thumbnail_path(dir, 7),
+            Path::new("/data/history/7.thumbnail")
+        );
+    }
+
+    #[test]
+    fn reads_index_entries_written_before_later_fields() {
+        let entry: IndexEntry = serde_json::from_value(serde_json::json!({
+            "id": 3,
+            "format": { "kind": "png" },
+            "width": 800,
+            "height": 600,
+            "screenId": null,
+            "screenIndex": 0,
+            "capturedAt": 1700000000000u64,
+            "captureMode": "fullScreen",
+            "savedPath": null,
+            "fullImage": true,
+        }))
+        .unwrap();
+        assert_eq!(entry.id, 3);
+        assert_eq!(entry.format, OutputFormat::Png);
+        assert!(entry.full_image);
+        assert!(!entry.pinned);
+        assert_eq!(entry.promoted_at, None);
+        assert_eq!(entry.label, None);
+        assert!(entry.source.is_none());
+        assert_eq!(entry.trigger, None);
+    }
+
+    #[test]
+    fn index_entry_round_trips() {
+        let json = serde_json::json!({
+            "id": 9,
+            "format": { "kind": "jpeg", "quality": 85 },
+            "width": 1920,
+            "height": 1080,
+            "screenId": 42,
+            "screenIndex": 1,
+            "capturedAt": 1700000000000u64,
+            "captureMode": "region",
+            "savedPath": "/shots/a.jpg",
+            "pinned": true,
+            "promotedAt": 1700000001000u64,
+            "label": "bug",
+            "source": null,
+            "trigger": "hotkey",
+            "fullImage": false,
+        });
+        let entry: IndexEntry = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&entry).unwrap(), json);
+    }
+
+    /// Empty history folder for a test, removed by the test when it's done
+    fn temp_history_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "screenshot-maker-history-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// A screenshot whose image is just the PNG signature, enough for
+    /// `rehydrate` to recognize its format
+    fn stored(captured_at: u64) -> StoredScreenshot {
+        StoredScreenshot {
+            bytes: Arc::new(b"\x89PNG\r\n\x1a\n".to_vec()),
+            format: OutputFormat::Png,
+            width: 4,
+            height: 3,
+            screen_id: None,
+            captured_at,
+            thumbnail_data_url: format!("data:image/webp;base64,{}", captured_at),
+            screen_index: Some(0),
+            capture_mode: "fullScreen".to_string(),
+            saved_path: None,
+            pinned: false,
+            promoted_at: None,
+            label: None,
+            source: None,
+            trigger: Some(TriggerSource::Hotkey),
+            memory_only: false,
+        }
+    }
+
+    /// Persists screenshots 1 and 2 into `dir` and writes their index
+    fn persist_two(dir: &Path) {
+        let state = ScreenshotState::new();
+        for id in [1, 2] {
+            save_in(dir, id, &stored(id * 100)).unwrap();
+            state.screenshots.locked().insert(id, stored(id * 100));
+        }
+        write_index_in(dir, &state).unwrap();
+    }
+
+    #[test]
+    fn history_round_trips_through_the_folder() {
+        let dir = temp_history_dir("round-trip");
+        persist_two(&dir);
+
+        let state = ScreenshotState::new();
+        assert!(!load_from(&dir, &state));
+        {
+            let thumbnail_history = state.thumbnail_history.locked();
+            assert_eq!(
+                thumbnail_history.keys().copied().collect::<Vec<_>>(),
+                [1, 2]
+            );
+            assert!(thumbnail_history.values().all(|entry| entry.full_image));
+            assert_eq!(thumbnail_history[&2].captured_at, 200);
+            assert_eq!(
+                thumbnail_history[&2].thumbnail_data_url,
+                stored(200).thumbnail_data_url
+            );
+        }
+        assert_eq!(state.next_id.load(Ordering::SeqCst), 3);
+
+        assert!(rehydrate_from(&dir, &state, 2));
+        let rehydrated = state
+            .screenshots
+            .locked()
+            .get(&2)
+            .map(|stored| stored.bytes.clone());
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rehydrated, Some(stored(200).bytes));
+        assert!(!state.thumbnail_history.locked().contains_key(&2));
+    }
+
+    #[test]
+    fn load_prunes_entries_without_thumbnail() {
+        let dir = temp_history_dir("missing-thumbnail");
+        persist_two(&dir);
+        fs::remove_file(thumbnail_path(&dir, 1)).unwrap();
+
+        let state = ScreenshotState::new();
+        let pruned = load_from(&dir, &state);
+        let image_left = image_path(&dir, 1, OutputFormat::Png).exists();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(pruned);
+        assert_eq!(
+            state
+                .thumbnail_history
+                .locked()
+                .keys()
+                .copied()
+                .collect::<Vec<_>>(),
+            [2]
+        );
+        assert!(!image_left);
+    }
+
+    #[test]
+    fn load_keeps_thumbnail_of_missing_image() {
+        let dir = temp_history_dir("missing-image");
+        persist_two(&dir);
+        fs::remove_file(image_path(&dir, 1, OutputFormat::Png)).unwrap();
+
+        let state = ScreenshotState::new();
+        let pruned = load_from(&dir, &state);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(pruned);
+        let thumbnail_history = state.thumbnail_history.locked();
+        assert!(!thumbnail_history[&1].full_image);
+        assert!(thumbnail_history[&2].full_image);
+    }
+
+    #[test]
+    fn rehydrate_drops_corrupt_image() {
+        let dir = temp_history_dir("corrupt-image");
+        persist_two(&dir);
+        fs::write(image_path(&dir, 1, OutputFormat::Png), b"not an image").unwrap();
+
+        let state = ScreenshotState::new();
+        load_from(&dir, &state);
+        let rehydrated = rehydrate_from(&dir, &state, 1);
+        let files_left =
+            image_path(&dir, 1, OutputFormat::Png).exists() || thumbnail_path(&dir, 1).exists();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!rehydrated);
+        assert!(!files_left);
+        assert!(!state.screenshots.locked().contains_key(&1));
+        assert!(!state.thumbnail_history.locked().contains_key(&1));
+    }
+}
@@ -0,0 +1,92 @@
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use screenshots::image::RgbaImage;
+use std::io::Write;
+
+/// Bytes per RGBA8 pixel, which is also the distance the Sub filter looks back
+const BYTES_PER_PIXEL: usize = 4;
+
+/// Adam7 passes as (first column, first row, column step, row step)
+const ADAM7_PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// Builds the zlib-compressed IDAT contents of an Adam7-interlaced RGBA8 PNG.
+///
+/// The png crate can only write non-interlaced images, so the seven reduced
+/// images are assembled here, each row Sub-filtered as in the PNG spec.
+/// Passes that contain no pixels are omitted entirely.
+pub fn adam7_image_data(image: &RgbaImage, level: Compression) -> Result<Vec<u8>, String> {
+    let (width, height) = image.dimensions();
+    let mut encoder = ZlibEncoder::new(Vec::new(), level);
+    let mut row = Vec::new();
+
+    for (first_x, first_y, step_x, step_y) in ADAM7_PASSES {
+        if first_x >= width || first_y >= height {
+            continue;
+        }
+        for y in (first_y..height).step_by(step_y as usize) {
+            row.clear();
+            for x in (first_x..width).step_by(step_x as usize) {
+                row.extend_from_slice(&image.get_pixel(x, y).0);
+            }
+            // Sub filter: each byte minus the same channel of the pixel to its left
+            for i in (BYTES_PER_PIXEL..row.len()).rev() {
+                row[i] = row[i].wrapping_sub(row[i - BYTES_PER_PIXEL]);
+            }
+            encoder.write_all(&[1]).map_err(|e| e.to_string())?;
+            encoder.write_all(&row).map_err(|e| e.to_string())?;
+        }
+    }
+
+    encoder.finish().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use screenshots::image::Rgba;
+    use std::io::Read;
+
+    fn inflate(data: &[u8]) -> Vec<u8> {
+        let mut raw = Vec::new();
+        ZlibDecoder::new(data).read_to_end(&mut raw).unwrap();
+        raw
+    }
+
+    #[test]
+    fn single_pixel_has_only_first_pass() {
+        let image = RgbaImage::from_pixel(1, 1, Rgba([1, 2, 3, 4]));
+        let data = adam7_image_data(&image, Compression::default()).unwrap();
+        assert_eq!(inflate(&data), [1, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn rows_are_sub_filtered_per_pass() {
+        // 2x2: pass 6 holds (1, 0) and pass 7 holds row 1, two pixels Sub-filtered
+        let image = RgbaImage::from_vec(
+            2,
+            2,
+            vec![
+                10, 10, 10, 10, 20, 20, 20, 20, 30, 30, 30, 30, 35, 40, 25, 30,
+            ],
+        )
+        .unwrap();
+        let data = adam7_image_data(&image, Compression::default()).unwrap();
+        assert_eq!(
+            inflate(&data),
+            [
+                1, 10, 10, 10, 10, // pass 1: (0, 0)
+                1, 20, 20, 20, 20, // pass 6: (1, 0)
+                1, 30, 30, 30, 30, 5, 10, 251, 0, // pass 7: row 1
+            ]
+        );
+    }
+}
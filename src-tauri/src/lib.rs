@@ -1,21 +1,72 @@
 #![allow(unused_must_use)]
 
+mod activity;
+mod clipboard;
+mod color_profile;
+mod filename;
+mod history;
+mod interlace;
+mod metadata;
+mod pdf;
+mod postprocess;
+mod recovery;
+mod save;
 mod screenshot;
+mod settings;
+mod shortcuts;
+mod stitch;
+mod storage;
 mod tray;
+mod window_list;
 
+use activity::{ActivityLog, get_activity_log};
+use clipboard::{
+    copy_screenshot_as, copy_screenshot_path_to_clipboard, copy_screenshot_to_clipboard,
+    paste_image_from_clipboard,
+};
+use metadata::read_screenshot_metadata;
+use pdf::export_screenshot_pdf;
+use save::{
+    export_history, export_to_temp_file, reveal_in_file_manager, save_region_to_file,
+    save_screenshot_to_file, save_screenshot_with_dialog,
+};
 use screenshot::{
-    ScreenshotState, get_current_screenshot, hide_preview_window, show_editor_window,
-    take_screenshot, trigger_screenshot,
+    LockExt, SCREENSHOT_PROTOCOL, ScreenshotState, append_scroll_frame, cancel_delayed_screenshot,
+    cancel_frozen_capture, cancel_screenshot_burst, capture_window, clear_capture_masks,
+    confirm_frozen_selection, delete_screenshot, estimate_encoded_sizes, finish_scrolling_capture,
+    get_burst_frame, get_current_screenshot, get_current_screenshot_raw, get_current_thumbnail,
+    get_frozen_frame, get_full_screenshot, get_memory_usage, get_paused, get_screenshot_by_id,
+    get_screenshot_history, get_thumbnail, hide_preview_window, list_screens, list_windows,
+    load_screenshot_from_file, repeat_last_capture, retake_screenshot, serve_screenshot,
+    set_capture_masks, set_current_screenshot, set_paused, set_screenshot_label,
+    set_screenshot_pinned, show_editor_window, start_frozen_capture, start_scrolling_capture,
+    take_screenshot, take_screenshot_active_window, take_screenshot_all_screens,
+    take_screenshot_burst, take_screenshot_delayed, take_screenshot_of_focused_monitor,
+    take_screenshot_of_screen, take_screenshot_region, trigger_capture,
 };
-use tray::initialize_tray;
+use settings::{SettingsState, get_settings, update_settings};
+use shortcuts::{
+    ShortcutRegistry, get_shortcut_status, get_shortcuts, get_shortcuts_suspended,
+    refresh_screen_shortcuts, resume_shortcuts, set_shortcut, suspend_shortcuts,
+};
+use storage::get_default_save_dir;
+use tauri::Manager;
+use tray::{get_tray_status, initialize_tray, retry_tray_init, TrayStatus};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_clipboard_manager::init())
+        .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .plugin(tauri_plugin_opener::init())
         .manage(ScreenshotState::new())
+        .manage(ActivityLog::new())
+        .manage(ShortcutRegistry::new())
+        .manage(TrayStatus::default())
+        .register_uri_scheme_protocol(SCREENSHOT_PROTOCOL, |ctx, request| {
+            serve_screenshot(ctx.app_handle(), &request)
+        })
         .setup(|app| {
             // Set macOS activation policy to accessory (menu bar app, no dock)
             #[cfg(target_os = "macos")]
@@ -23,25 +74,200 @@ pub fn run() {
                 app.set_activation_policy(tauri::ActivationPolicy::Accessory);
             }
 
-            // Initialize tray icon
-            initialize_tray(app)?;
+            // Load persisted settings before anything that depends on them
+            let settings = SettingsState::load(app.handle());
+            *app.state::<ScreenshotState>().capture_masks.locked() =
+                settings.get().capture_masks;
+            app.manage(settings);
 
-            // Register global shortcut Ctrl+Alt+S
+            // List the screenshots of earlier sessions, and those a crash left
+            // unsaved, before anything is captured
+            history::load(app.handle());
+            recovery::recover(app.handle());
+
+            // Delete day-old drag-and-drop exports and auto-saved files over the
+            // retention limits without delaying startup
             let app_handle = app.handle().clone();
-            use tauri_plugin_global_shortcut::GlobalShortcutExt;
+            std::thread::spawn(move || {
+                save::clean_temp_exports(&app_handle);
+                storage::enforce_retention(&app_handle);
+            });
+
+            // Initialize tray icon. Without one, e.g. on Linux without a StatusNotifier
+            // host, startup goes on with the main window shown, as the tray can't reveal it.
+            // `tray-unavailable` is emitted with the error, also kept for `get_tray_status`
+            if let Err(error) = initialize_tray(app.handle()) {
+                eprintln!("Could not create the tray icon: {}", String::from(error));
+                if let Some(window) = app.get_webview_window("main") {
+                    let _ = window.show();
+                }
+            }
 
-            app.global_shortcut().on_shortcut("ctrl+alt+s", move |_app, _shortcut, _event| {
-                trigger_screenshot(&app_handle);
-            })?;
+            // Register the global shortcuts chosen in the settings; startup goes on
+            // without those another application owns, leaving the tray usable
+            shortcuts::register_all(app.handle());
 
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             take_screenshot,
+            take_screenshot_of_screen,
+            take_screenshot_region,
+            take_screenshot_all_screens,
+            take_screenshot_active_window,
+            take_screenshot_delayed,
+            cancel_delayed_screenshot,
             get_current_screenshot,
+            list_screens,
             show_editor_window,
-            hide_preview_window
+            hide_preview_window,
+            repeat_last_capture,
+            take_screenshot_burst,
+            cancel_screenshot_burst,
+            get_burst_frame,
+            start_frozen_capture,
+            get_frozen_frame,
+            confirm_frozen_selection,
+            cancel_frozen_capture,
+            list_windows,
+            capture_window,
+            start_scrolling_capture,
+            append_scroll_frame,
+            finish_scrolling_capture,
+            take_screenshot_of_focused_monitor,
+            get_settings,
+            update_settings,
+            retake_screenshot,
+            get_full_screenshot,
+            get_current_thumbnail,
+            set_capture_masks,
+            clear_capture_masks,
+            get_current_screenshot_raw,
+            read_screenshot_metadata,
+            get_thumbnail,
+            estimate_encoded_sizes,
+            save_screenshot_to_file,
+            save_screenshot_with_dialog,
+            export_to_temp_file,
+            reveal_in_file_manager,
+            export_screenshot_pdf,
+            load_screenshot_from_file,
+            export_history,
+            get_default_save_dir,
+            save_region_to_file,
+            copy_screenshot_to_clipboard,
+            copy_screenshot_path_to_clipboard,
+            paste_image_from_clipboard,
+            copy_screenshot_as,
+            get_screenshot_history,
+            get_screenshot_by_id,
+            delete_screenshot,
+            set_screenshot_pinned,
+            set_current_screenshot,
+            get_memory_usage,
+            get_activity_log,
+            set_screenshot_label,
+            get_shortcuts,
+            set_shortcut,
+            get_shortcut_status,
+            suspend_shortcuts,
+            resume_shortcuts,
+            get_shortcuts_suspended,
+            trigger_capture,
+            refresh_screen_shortcuts,
+            get_paused,
+            set_paused,
+            retry_tray_init,
+            get_tray_status
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Screenshots not saved by now are discarded on purpose
+            if let tauri::RunEvent::Exit = event {
+                recovery::clear(app);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    /// Sources of the modules that may define commands
+    const MODULE_SOURCES: [&str; 19] = [
+        include_str!("activity.rs"),
+        include_str!("clipboard.rs"),
+        include_str!("color_profile.rs"),
+        include_str!("filename.rs"),
+        include_str!("history.rs"),
+        include_str!("interlace.rs"),
+        include_str!("metadata.rs"),
+        include_str!("pdf.rs"),
+        include_str!("postprocess.rs"),
+        include_str!("recovery.rs"),
+        include_str!("save.rs"),
+        include_str!("screenshot.rs"),
+        include_str!("settings.rs"),
+        include_str!("shortcuts.rs"),
+        include_str!("stitch.rs"),
+        include_str!("storage.rs"),
+        include_str!("tray.rs"),
+        include_str!("window_list.rs"),
+        include_str!("lib.rs"),
+    ];
+
+    /// Names of the functions marked `#[tauri::command]` in `source`
+    fn commands(source: &str) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut lines = source.lines();
+        while let Some(line) = lines.next() {
+            if !line.trim_start().starts_with("#[tauri::command") {
+                continue;
+            }
+            let signature = lines.by_ref().find(|line| line.contains("fn ")).unwrap();
+            let name = signature.split("fn ").nth(1).unwrap();
+            let end = name
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap();
+            names.push(name[..end].to_string());
+        }
+        names
+    }
+
+    /// Names listed in `run`'s `generate_handler!`
+    fn handlers() -> Vec<String> {
+        let source = include_str!("lib.rs");
+        let start = source.find("generate_handler![").unwrap() + "generate_handler![".len();
+        let end = start + source[start..].find(']').unwrap();
+        source[start..end]
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn generate_handler_lists_every_command() {
+        let commands: Vec<String> = MODULE_SOURCES
+            .iter()
+            .flat_map(|source| commands(source))
+            .collect();
+        let unique: BTreeSet<&String> = commands.iter().collect();
+        assert_eq!(
+            unique.len(),
+            commands.len(),
+            "a command name is defined twice"
+        );
+
+        let handlers = handlers();
+        let registered: BTreeSet<&String> = handlers.iter().collect();
+        assert_eq!(
+            registered.len(),
+            handlers.len(),
+            "a command is registered twice"
+        );
+        assert_eq!(registered, unique);
+    }
 }
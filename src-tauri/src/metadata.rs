@@ -0,0 +1,101 @@
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::fs::File;
+use std::io::BufReader;
+use tauri::AppHandle;
+
+/// Registered PNG keyword for the capture time
+const CREATION_TIME_KEYWORD: &str = "Creation Time";
+/// Registered PNG keyword for the app that wrote the file
+const SOFTWARE_KEYWORD: &str = "Software";
+const SCREEN_KEYWORD: &str = "Screen";
+const CAPTURE_MODE_KEYWORD: &str = "Capture Mode";
+const SOURCE_FILE_KEYWORD: &str = "Source File";
+
+/// Capture details embedded in PNG output as tEXt chunks.
+/// Every field is optional since files read back may come from other tools.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CaptureMetadata {
+    /// Capture time in RFC 3339 format, in local time
+    pub captured_at: Option<String>,
+    /// OS display id of the captured screen
+    pub screen_id: Option<u32>,
+    /// Name and version of the app that took the capture
+    pub software: Option<String>,
+    /// What was captured, e.g. `fullScreen` or `region`
+    pub capture_mode: Option<String>,
+    /// Original path of an imported image
+    pub source_file: Option<String>,
+}
+
+impl CaptureMetadata {
+    /// Describes a capture taken now by this app, or an image imported from `source_file`
+    pub fn new(
+        app: &AppHandle,
+        captured_at_ms: u64,
+        screen_id: Option<u32>,
+        capture_mode: &str,
+        source_file: Option<&str>,
+    ) -> Self {
+        let package = app.package_info();
+        Self {
+            captured_at: DateTime::from_timestamp_millis(captured_at_ms as i64)
+                .map(|time| time.with_timezone(&Local).to_rfc3339()),
+            screen_id,
+            software: Some(format!("{} {}", package.name, package.version)),
+            capture_mode: Some(capture_mode.to_string()),
+            source_file: source_file.map(str::to_string),
+        }
+    }
+
+    /// Keyword and text pairs to write as PNG tEXt chunks
+    pub fn text_chunks(&self) -> Vec<(&'static str, String)> {
+        [
+            (CREATION_TIME_KEYWORD, self.captured_at.clone()),
+            (SCREEN_KEYWORD, self.screen_id.map(|id| id.to_string())),
+            (SOFTWARE_KEYWORD, self.software.clone()),
+            (CAPTURE_MODE_KEYWORD, self.capture_mode.clone()),
+            (SOURCE_FILE_KEYWORD, self.source_file.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(keyword, text)| Some((keyword, text?)))
+        .collect()
+    }
+}
+
+/// Reads capture metadata from the tEXt and iTXt chunks of a PNG file
+pub fn read_png_metadata(path: &str) -> Result<CaptureMetadata, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let reader = png::Decoder::new(BufReader::new(file))
+        .read_info()
+        .map_err(|e| e.to_string())?;
+    let info = reader.info();
+
+    let latin1 = info
+        .uncompressed_latin1_text
+        .iter()
+        .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()));
+    let utf8 = info
+        .utf8_text
+        .iter()
+        .filter_map(|chunk| Some((chunk.keyword.clone(), chunk.get_text().ok()?)));
+
+    let mut metadata = CaptureMetadata::default();
+    for (keyword, text) in latin1.chain(utf8) {
+        match keyword.as_str() {
+            CREATION_TIME_KEYWORD => metadata.captured_at = Some(text),
+            SCREEN_KEYWORD => metadata.screen_id = text.parse().ok(),
+            SOFTWARE_KEYWORD => metadata.software = Some(text),
+            CAPTURE_MODE_KEYWORD => metadata.capture_mode = Some(text),
+            SOURCE_FILE_KEYWORD => metadata.source_file = Some(text),
+            _ => {}
+        }
+    }
+    Ok(metadata)
+}
+
+/// Tauri command: Reads the capture metadata embedded in a PNG file, e.g. one re-imported from disk
+#[tauri::command]
+pub fn read_screenshot_metadata(path: String) -> Result<CaptureMetadata, String> {
+    read_png_metadata(&path)
+}
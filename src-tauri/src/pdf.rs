@@ -0,0 +1,256 @@
+use crate::save::{record_saved, remember_saved_dir, write_file, SaveError, SavedFile};
+use crate::screenshot::{decode_image, flatten_alpha, local_time, ScreenshotState};
+use crate::settings::SettingsState;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use screenshots::image::RgbImage;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+use tauri::{AppHandle, Manager};
+
+/// PDF units per inch
+const POINTS_PER_INCH: f32 = 72.0;
+
+/// A4 portrait size in points
+const A4_SIZE: (f32, f32) = (595.28, 841.89);
+
+/// US Letter portrait size in points
+const LETTER_SIZE: (f32, f32) = (612.0, 792.0);
+
+/// Font size of the header line in points
+const HEADER_FONT_SIZE: f32 = 10.0;
+
+/// Space taken by the header line above the image, in points
+const HEADER_HEIGHT: f32 = 16.0;
+
+/// Size of the PDF page
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PageSize {
+    /// The image's size at the chosen DPI, plus margins and header
+    #[default]
+    Image,
+    /// A4, rotated to landscape for wide images; large images are scaled down to fit
+    A4,
+    /// US Letter, rotated to landscape for wide images; large images are scaled down to fit
+    Letter,
+}
+
+/// Layout of an exported PDF
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PdfOptions {
+    /// Resolution the image is placed at; 96 shows it at its on-screen size
+    pub dpi: f32,
+    /// Blank space around the page content, in points
+    pub margin: f32,
+    pub page_size: PageSize,
+    /// Print the capture time in the header line
+    pub timestamp: bool,
+    /// Text printed in the header line after the timestamp
+    pub caption: Option<String>,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        Self {
+            dpi: 96.0,
+            margin: 0.0,
+            page_size: PageSize::default(),
+            timestamp: false,
+            caption: None,
+        }
+    }
+}
+
+/// Escapes text for a PDF string literal in WinAnsi encoding. Characters
+/// outside Latin-1 are replaced with `?`.
+fn pdf_text(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '(' | ')' => bytes.extend_from_slice(&[b'\\', c as u8]),
+            c if c.is_control() => bytes.push(b' '),
+            c if (c as u32) <= 0xFF => bytes.push(c as u32 as u8),
+            _ => bytes.push(b'?'),
+        }
+    }
+    bytes
+}
+
+/// Builds a one-page PDF showing `image` below an optional header line
+fn build_pdf(
+    image: &RgbImage,
+    options: &PdfOptions,
+    header: Option<&str>,
+) -> Result<Vec<u8>, String> {
+    let image_width = image.width() as f32 * POINTS_PER_INCH / options.dpi;
+    let image_height = image.height() as f32 * POINTS_PER_INCH / options.dpi;
+    let margin = options.margin;
+    let header_height = if header.is_some() { HEADER_HEIGHT } else { 0.0 };
+
+    let paper = match options.page_size {
+        PageSize::Image => None,
+        PageSize::A4 => Some(A4_SIZE),
+        PageSize::Letter => Some(LETTER_SIZE),
+    };
+    let (page_width, page_height, scale) = match paper {
+        None => (
+            image_width + 2.0 * margin,
+            image_height + 2.0 * margin + header_height,
+            1.0,
+        ),
+        Some((short, long)) => {
+            let (width, height) = if image_width > image_height {
+                (long, short)
+            } else {
+                (short, long)
+            };
+            let available_width = width - 2.0 * margin;
+            let available_height = height - 2.0 * margin - header_height;
+            if available_width <= 0.0 || available_height <= 0.0 {
+                return Err("Margin leaves no room for the image".to_string());
+            }
+            let scale = (available_width / image_width)
+                .min(available_height / image_height)
+                .min(1.0);
+            (width, height, scale)
+        }
+    };
+
+    // Centered horizontally, directly below the header
+    let drawn_width = image_width * scale;
+    let drawn_height = image_height * scale;
+    let image_x = (page_width - drawn_width) / 2.0;
+    let image_y = page_height - margin - header_height - drawn_height;
+
+    let mut content = format!(
+        "q\n{:.2} 0 0 {:.2} {:.2} {:.2} cm\n/Im0 Do\nQ\n",
+        drawn_width, drawn_height, image_x, image_y
+    )
+    .into_bytes();
+    if let Some(header) = header {
+        let baseline = page_height - margin - HEADER_FONT_SIZE;
+        content.extend_from_slice(
+            format!(
+                "BT\n/F1 {} Tf\n{:.2} {:.2} Td\n(",
+                HEADER_FONT_SIZE, margin, baseline
+            )
+            .as_bytes(),
+        );
+        content.extend_from_slice(&pdf_text(header));
+        content.extend_from_slice(b") Tj\nET\n");
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(image.as_raw())
+        .map_err(|e| e.to_string())?;
+    let pixels = encoder.finish().map_err(|e| e.to_string())?;
+
+    let objects: Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] \
+             /Resources << /XObject << /Im0 5 0 R >> /Font << /F1 6 0 R >> >> \
+             /Contents 4 0 R >>",
+            page_width, page_height
+        )
+        .into_bytes(),
+        stream_object(format!("<< /Length {} >>", content.len()), &content),
+        stream_object(
+            format!(
+                "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB \
+                 /BitsPerComponent 8 /Filter /FlateDecode /Length {} >>",
+                image.width(),
+                image.height(),
+                pixels.len()
+            ),
+            &pixels,
+        ),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+            .to_vec(),
+    ];
+
+    let mut pdf = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", index + 1).as_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+
+    let xref_offset = pdf.len();
+    pdf.extend_from_slice(
+        format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes(),
+    );
+    for offset in offsets {
+        pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    pdf.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref_offset
+        )
+        .as_bytes(),
+    );
+    Ok(pdf)
+}
+
+/// A PDF stream object with the given dictionary
+fn stream_object(dictionary: String, data: &[u8]) -> Vec<u8> {
+    let mut object = dictionary.into_bytes();
+    object.extend_from_slice(b"\nstream\n");
+    object.extend_from_slice(data);
+    object.extend_from_slice(b"\nendstream");
+    object
+}
+
+/// Tauri command: Writes the current screenshot to `path` as a one-page PDF,
+/// laid out by `options`. Existing files are only replaced with `overwrite`.
+#[tauri::command(async)]
+pub fn export_screenshot_pdf(
+    app_handle: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    path: String,
+    options: Option<PdfOptions>,
+    overwrite: Option<bool>,
+) -> Result<SavedFile, SaveError> {
+    let options = options.unwrap_or_default();
+    if !options.dpi.is_finite() || options.dpi <= 0.0 {
+        return Err("DPI must be positive".to_string().into());
+    }
+    if !options.margin.is_finite() || options.margin < 0.0 {
+        return Err("Margin must not be negative".to_string().into());
+    }
+
+    let (bytes, captured_at) =
+        state.with_current(|stored| (stored.bytes.clone(), stored.captured_at))?;
+
+    let image = decode_image(&bytes)?;
+    let background = app_handle.state::<SettingsState>().get().alpha_background;
+    let image = flatten_alpha(&image, background);
+
+    let timestamp = options.timestamp.then(|| {
+        local_time(captured_at)
+            .format("%Y-%m-%d %H:%M:%S %:z")
+            .to_string()
+    });
+    let header = [timestamp.as_deref(), options.caption.as_deref()]
+        .into_iter()
+        .flatten()
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(" - ");
+    let header = (!header.is_empty()).then_some(header);
+
+    let pdf = build_pdf(&image, &options, header.as_deref())?;
+    let saved = write_file(Path::new(&path), &pdf, false, overwrite.unwrap_or(false))?;
+    remember_saved_dir(&state, &saved);
+    record_saved(&app_handle, state.current().ok(), &saved, false);
+    Ok(saved)
+}
@@ -0,0 +1,42 @@
+use color_quant::NeuQuant;
+use screenshots::image::{imageops, DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Sampling factor passed to NeuQuant; 1 is slowest and best, 30 fastest
+const NEUQUANT_SAMPLE_FACTOR: i32 = 10;
+
+/// Color reduction applied to a capture before it is encoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum PostProcess {
+    /// Convert to grayscale, keeping transparency
+    Grayscale,
+    /// Reduce to a palette of `colors` colors, clamped to 2–256, picked with NeuQuant
+    Quantize { colors: u16 },
+}
+
+/// Applies `post_process` to a captured image
+pub fn apply(image: RgbaImage, post_process: PostProcess) -> RgbaImage {
+    match post_process {
+        PostProcess::Grayscale => {
+            DynamicImage::ImageLumaA8(imageops::grayscale_alpha(&image)).to_rgba8()
+        }
+        PostProcess::Quantize { colors } => quantize(image, colors.clamp(2, 256) as usize),
+    }
+}
+
+/// Maps every pixel to the nearest color of a NeuQuant palette built from the image
+fn quantize(mut image: RgbaImage, colors: usize) -> RgbaImage {
+    let quantizer = NeuQuant::new(NEUQUANT_SAMPLE_FACTOR, colors, image.as_raw());
+    // Screenshots repeat a few colors over large areas, so remember each lookup
+    let mut mapped: HashMap<[u8; 4], [u8; 4]> = HashMap::new();
+    for pixel in image.pixels_mut() {
+        pixel.0 = *mapped.entry(pixel.0).or_insert_with(|| {
+            quantizer
+                .lookup(quantizer.index_of(&pixel.0))
+                .unwrap_or(pixel.0)
+        });
+    }
+    image
+}
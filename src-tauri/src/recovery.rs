@@ -0,0 +1,198 @@
+use crate::screenshot::{
+    encode_thumbnail, LockExt, OutputFormat, ScreenshotState, StoredScreenshot,
+};
+use crate::settings::SettingsState;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Folder inside the app data directory holding screenshots not saved yet
+const RECOVERY_DIR: &str = "recovery";
+
+/// Size the recovery folder is kept under; the oldest files are deleted first
+const MAX_RECOVERY_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Folder recovery files are written to, `None` without an app data directory
+fn recovery_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join(RECOVERY_DIR))
+}
+
+/// Recovery files in `dir` with the screenshot id and format their names encode
+fn recovery_files(dir: &Path) -> Vec<(u64, OutputFormat, PathBuf)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<_> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            let id = path.file_stem()?.to_str()?.parse().ok()?;
+            let format = OutputFormat::from_extension(path.extension()?.to_str()?)?;
+            Some((id, format, path))
+        })
+        .collect();
+    files.sort_by_key(|(id, _, _)| *id);
+    files
+}
+
+/// Writes the image of a new screenshot to the recovery folder in the
+/// background, so it survives a crash before it's saved. Skipped when the
+/// history is persisted, which already survives crashes.
+pub fn write(app: &AppHandle, id: u64, bytes: Arc<Vec<u8>>, format: OutputFormat) {
+    if app.state::<SettingsState>().get().persist_history {
+        return;
+    }
+    let Some(dir) = recovery_dir(app) else {
+        return;
+    };
+
+    // Counted until written, so quitting doesn't leave a file behind `clear`
+    app.state::<ScreenshotState>()
+        .pending_writes
+        .fetch_add(1, Ordering::SeqCst);
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        write_file(&dir, id, &bytes, format);
+        app_handle
+            .state::<ScreenshotState>()
+            .pending_writes
+            .fetch_sub(1, Ordering::SeqCst);
+    });
+}
+
+/// Writes a recovery file into `dir`, then deletes the oldest ones over
+/// `MAX_RECOVERY_BYTES`
+fn write_file(dir: &Path, id: u64, bytes: &[u8], format: OutputFormat) {
+    let path = dir.join(format!("{}.{}", id, format.extension()));
+    let result = fs::create_dir_all(dir).and_then(|()| fs::write(&path, bytes));
+    if let Err(error) = result {
+        eprintln!(
+            "Could not write recovery file for screenshot {}: {}",
+            id, error
+        );
+        return;
+    }
+
+    let files = recovery_files(dir);
+    let mut total: u64 = files
+        .iter()
+        .filter_map(|(_, _, path)| fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    for (_, _, path) in files {
+        if total <= MAX_RECOVERY_BYTES {
+            break;
+        }
+        let size = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+        if fs::remove_file(&path).is_ok() {
+            total = total.saturating_sub(size);
+        }
+    }
+}
+
+/// Deletes the recovery file of screenshot `id`, once it's saved, deleted or
+/// dropped from the history
+pub fn remove(app: &AppHandle, id: u64) {
+    let Some(dir) = recovery_dir(app) else {
+        return;
+    };
+    for (file_id, _, path) in recovery_files(&dir) {
+        if file_id == id {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Deletes every recovery file, on a clean exit
+pub fn clear(app: &AppHandle) {
+    if let Some(dir) = recovery_dir(app) {
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+/// Loads the screenshots left in the recovery folder by a crash into the
+/// history, keeping their ids, and emits `recovered-screenshots` with their
+/// count. Unreadable files are deleted. Run once on startup, before anything
+/// is captured.
+pub fn recover(app: &AppHandle) {
+    let Some(dir) = recovery_dir(app) else {
+        return;
+    };
+    let files = recovery_files(&dir);
+    if files.is_empty() {
+        return;
+    }
+
+    let state = app.state::<ScreenshotState>();
+    let thumbnail_max_edge = app.state::<SettingsState>().get().thumbnail_max_edge;
+    let mut recovered = 0;
+    for (id, format, path) in files {
+        let captured_at = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let loaded = fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| {
+                let image = screenshots::image::load_from_memory(&bytes)
+                    .map_err(|e| e.to_string())?
+                    .to_rgba8();
+                Ok((bytes, image))
+            });
+        let (bytes, image) = match loaded {
+            Ok(loaded) => loaded,
+            Err(error) => {
+                eprintln!(
+                    "Dropping unreadable recovery file {}: {}",
+                    path.display(),
+                    error
+                );
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+        };
+        let Ok(thumbnail_data_url) = encode_thumbnail(&image, thumbnail_max_edge) else {
+            continue;
+        };
+
+        state.next_id.fetch_max(id + 1, Ordering::SeqCst);
+        state.screenshots.locked().insert(
+            id,
+            StoredScreenshot {
+                bytes: Arc::new(bytes),
+                format,
+                width: image.width(),
+                height: image.height(),
+                screen_id: None,
+                captured_at,
+                thumbnail_data_url,
+                screen_index: None,
+                capture_mode: "recovered".to_string(),
+                saved_path: None,
+                pinned: false,
+                promoted_at: None,
+                label: None,
+                source: None,
+                trigger: None,
+                memory_only: false,
+            },
+        );
+        recovered += 1;
+    }
+
+    if recovered > 0 {
+        let mut current_id = state.current_id.locked();
+        if current_id.is_none() {
+            *current_id = state.screenshots.locked().keys().max().copied();
+        }
+        drop(current_id);
+        app.emit("recovered-screenshots", recovered);
+    }
+}
@@ -0,0 +1,769 @@
+use crate::activity::{self, Activity};
+use crate::filename::{FilenameContext, FilenameTemplate};
+use crate::recovery;
+use crate::screenshot::{
+    convert_encoded, decode_image, encode_for_saving, LockExt, OutputFormat, Rect, ScreenshotState,
+};
+use crate::settings::{AutoSave, Settings, SettingsState};
+use crate::storage;
+use screenshots::image::imageops;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{self, Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_dialog::DialogExt;
+use tauri_plugin_opener::OpenerExt;
+
+/// Attempts at finding a free auto-save file name before giving up
+const MAX_NAME_ATTEMPTS: u32 = 1000;
+
+/// Folder inside the app cache directory holding `export_to_temp_file` files
+const TEMP_EXPORT_DIR: &str = "exports";
+
+/// Age after which temporary exports are deleted on startup
+const TEMP_EXPORT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A screenshot written to disk
+#[derive(Clone, Debug, Serialize)]
+pub struct SavedFile {
+    /// Absolute path of the written file
+    pub path: String,
+    /// Size of the written file in bytes
+    pub bytes: u64,
+}
+
+/// A region of a screenshot written to disk
+#[derive(Clone, Debug, Serialize)]
+pub struct SavedRegion {
+    pub file: SavedFile,
+    /// Region actually saved, in image pixels, after clamping to the image
+    pub rect: Rect,
+}
+
+/// Outcome of `save_screenshot_with_dialog`
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum DialogSave {
+    Saved(SavedFile),
+    /// The user closed the dialog without choosing a file
+    Cancelled,
+}
+
+/// Errors returned by `reveal_in_file_manager`.
+/// Serialized with a `kind` tag like `WindowError`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "camelCase")]
+pub enum RevealError {
+    /// The path doesn't exist
+    NotFound,
+    /// The path isn't inside a folder the app saved screenshots to
+    NotAllowed,
+    /// The file manager couldn't be opened
+    Failed(String),
+}
+
+/// Errors returned by the save commands and emitted with `screenshot-save-error`.
+/// IO failures are classified so the frontend can suggest a fix, e.g. choosing
+/// another folder. Serialized with a `kind` tag like `WindowError`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "camelCase")]
+pub enum SaveError {
+    /// The folder doesn't exist or can't be reached, e.g. a disconnected network share
+    FolderUnavailable(String),
+    PermissionDenied(String),
+    DiskFull(String),
+    ReadOnly(String),
+    /// The file exists and overwriting wasn't requested
+    AlreadyExists(String),
+    /// Anything else, e.g. no screenshot to save or an encoding failure
+    Failed(String),
+}
+
+impl SaveError {
+    /// Classifies an IO error that occurred while writing `path`
+    fn io(error: io::Error, path: &Path) -> Self {
+        let message = format!("{}: {}", path.display(), error);
+        match error.kind() {
+            ErrorKind::NotFound | ErrorKind::NotADirectory => Self::FolderUnavailable(message),
+            ErrorKind::PermissionDenied => Self::PermissionDenied(message),
+            ErrorKind::StorageFull | ErrorKind::QuotaExceeded => Self::DiskFull(message),
+            ErrorKind::ReadOnlyFilesystem => Self::ReadOnly(message),
+            ErrorKind::AlreadyExists => Self::AlreadyExists(message),
+            _ => Self::Failed(message),
+        }
+    }
+}
+
+impl From<String> for SaveError {
+    fn from(message: String) -> Self {
+        Self::Failed(message)
+    }
+}
+
+/// Picks the format for saving to `path`: `format` when given, otherwise the
+/// one matching the file extension, preferring the stored screenshot's own
+/// format so it's written without re-encoding
+fn format_for_path(
+    path: &Path,
+    format: Option<OutputFormat>,
+    stored: OutputFormat,
+) -> Result<OutputFormat, String> {
+    if let Some(format) = format {
+        return Ok(format);
+    }
+
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .ok_or_else(|| "File name has no extension, specify a format".to_string())?;
+    if extension.eq_ignore_ascii_case(stored.extension()) {
+        return Ok(stored);
+    }
+    OutputFormat::from_extension(extension)
+        .ok_or_else(|| format!("Unsupported file extension: .{}", extension))
+}
+
+/// Writes `bytes` to `path`, creating missing parent directories when
+/// `create_dirs` is set and failing if the file exists unless `overwrite` is set
+pub fn write_file(
+    path: &Path,
+    bytes: &[u8],
+    create_dirs: bool,
+    overwrite: bool,
+) -> Result<SavedFile, SaveError> {
+    if create_dirs {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| SaveError::io(e, dir))?;
+        }
+    }
+
+    let mut file = open_for_write(path, overwrite).map_err(|e| SaveError::io(e, path))?;
+    file.write_all(bytes).map_err(|e| SaveError::io(e, path))?;
+    saved_file(path, bytes)
+}
+
+/// Records the folder of a saved file, so `reveal_in_file_manager` may show it
+pub fn remember_saved_dir(state: &ScreenshotState, saved: &SavedFile) {
+    let Some(dir) = Path::new(&saved.path)
+        .parent()
+        .and_then(|dir| fs::canonicalize(dir).ok())
+    else {
+        return;
+    };
+
+    let mut dirs = state.saved_dirs.locked();
+    if !dirs.contains(&dir) {
+        dirs.push(dir);
+    }
+}
+
+/// Records a saved screenshot in the activity log and deletes its recovery
+/// file, which is no longer needed
+pub fn record_saved(app: &AppHandle, id: Option<u64>, saved: &SavedFile, auto: bool) {
+    if let Some(id) = id {
+        recovery::remove(app, id);
+    }
+    activity::log_event(
+        app,
+        id,
+        Activity::Saved {
+            path: saved.path.clone(),
+            bytes: saved.bytes,
+            auto,
+        },
+    );
+}
+
+/// Opens `path` for writing, failing if it exists unless `overwrite` is set
+fn open_for_write(path: &Path, overwrite: bool) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if overwrite {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    options.open(path)
+}
+
+/// Describes `bytes` just written to `path`
+fn saved_file(path: &Path, bytes: &[u8]) -> Result<SavedFile, SaveError> {
+    let absolute = path::absolute(path).map_err(|e| SaveError::io(e, path))?;
+    Ok(SavedFile {
+        path: absolute.to_string_lossy().into_owned(),
+        bytes: bytes.len() as u64,
+    })
+}
+
+/// Folder auto-saved captures go to: the configured one, or the default
+/// screenshot folder
+pub fn auto_save_dir(app: &AppHandle, auto_save: &AutoSave) -> Result<PathBuf, String> {
+    match &auto_save.directory {
+        Some(directory) => Ok(directory.clone()),
+        None => storage::default_screenshot_dir(app),
+    }
+}
+
+/// Writes `bytes` into `dir` as `<stem>.<extension>`, appending `_2`, `_3`, …
+/// to the stem until the name is free
+fn write_unique(
+    dir: &Path,
+    stem: &str,
+    extension: &str,
+    bytes: &[u8],
+) -> Result<SavedFile, SaveError> {
+    for attempt in 1..=MAX_NAME_ATTEMPTS {
+        let name = match attempt {
+            1 => format!("{}.{}", stem, extension),
+            _ => format!("{}_{}.{}", stem, attempt, extension),
+        };
+        let path = dir.join(name);
+        match open_for_write(&path, false) {
+            Ok(mut file) => {
+                file.write_all(bytes).map_err(|e| SaveError::io(e, &path))?;
+                return saved_file(&path, bytes);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(SaveError::io(e, &path)),
+        }
+    }
+    Err(SaveError::AlreadyExists(format!(
+        "No free file name for {} in {}",
+        stem,
+        dir.display()
+    )))
+}
+
+/// Writes `bytes` into the auto-save folder as `<stem>.<extension>`, creating
+/// the folder when missing and never overwriting an existing file
+fn write_auto_save(
+    app: &AppHandle,
+    state: &ScreenshotState,
+    auto_save: &AutoSave,
+    stem: &str,
+    format: OutputFormat,
+    bytes: &[u8],
+) -> Result<SavedFile, SaveError> {
+    let dir = auto_save_dir(app, auto_save)?;
+    fs::create_dir_all(&dir).map_err(|e| SaveError::io(e, &dir))?;
+    let saved = write_unique(&dir, stem, format.extension(), bytes)?;
+    remember_saved_dir(state, &saved);
+    storage::enforce_retention(app);
+    Ok(saved)
+}
+
+/// Saves a new capture into the auto-save folder, named by the file name
+/// template and creating the folder when missing. Returns the saved file's path;
+/// failures are emitted as `screenshot-save-error` instead, so the capture
+/// itself still succeeds.
+pub fn auto_save(
+    app: &AppHandle,
+    state: &ScreenshotState,
+    settings: &Settings,
+    bytes: &[u8],
+    format: OutputFormat,
+    context: &FilenameContext,
+) -> Option<String> {
+    let result = FilenameTemplate::parse(&settings.filename_template)
+        .map_err(SaveError::from)
+        .and_then(|template| {
+            let stem = template.render(context);
+            write_auto_save(app, state, &settings.auto_save, &stem, format, bytes)
+        });
+
+    match result {
+        Ok(saved) => Some(saved.path),
+        Err(error) => {
+            app.emit("screenshot-save-error", error);
+            None
+        }
+    }
+}
+
+/// Tauri command: Writes the current screenshot to `path`. The format is
+/// inferred from the extension unless given. Missing directories are only
+/// created with `create_dirs`, and existing files only replaced with `overwrite`.
+#[tauri::command(async)]
+pub fn save_screenshot_to_file(
+    app_handle: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    path: String,
+    format: Option<OutputFormat>,
+    create_dirs: Option<bool>,
+    overwrite: Option<bool>,
+) -> Result<SavedFile, SaveError> {
+    let (bytes, stored_format) =
+        state.with_current(|stored| (stored.bytes.clone(), stored.format))?;
+
+    let path = Path::new(&path);
+    let format = format_for_path(path, format, stored_format)?;
+    let bytes = convert_encoded(&app_handle, &bytes, stored_format, format)?;
+    let saved = write_file(
+        path,
+        &bytes,
+        create_dirs.unwrap_or(false),
+        overwrite.unwrap_or(false),
+    )?;
+    remember_saved_dir(&state, &saved);
+    record_saved(&app_handle, state.current().ok(), &saved, false);
+    Ok(saved)
+}
+
+/// Tauri command: Asks for a destination with the native save dialog, pre-filled
+/// with the templated file name, and writes the current screenshot there in the
+/// format matching the chosen extension. The folder is remembered for next time.
+#[tauri::command(async)]
+pub fn save_screenshot_with_dialog(
+    app_handle: AppHandle,
+    state: tauri::State<ScreenshotState>,
+) -> Result<DialogSave, SaveError> {
+    let id = state.current()?;
+    let settings_state = app_handle.state::<SettingsState>();
+    let settings = settings_state.get();
+    let template = FilenameTemplate::parse(&settings.filename_template)?;
+    let (bytes, stored_format, file_name) = state
+        .screenshots
+        .locked()
+        .get(&id)
+        .map(|stored| {
+            let context = stored.filename_context(state.next_filename_counter());
+            let file_name = format!(
+                "{}.{}",
+                template.render(&context),
+                stored.format.extension()
+            );
+            (stored.bytes.clone(), stored.format, file_name)
+        })
+        .ok_or_else(|| "No screenshot taken".to_string())?;
+
+    let mut dialog = app_handle.dialog().file().set_file_name(file_name);
+    for (name, extensions) in OutputFormat::FILE_TYPES {
+        dialog = dialog.add_filter(*name, extensions);
+    }
+    if let Some(dir) = settings
+        .last_save_dir
+        .clone()
+        .or_else(|| storage::default_screenshot_dir(&app_handle).ok())
+        .filter(|dir| dir.is_dir())
+    {
+        dialog = dialog.set_directory(dir);
+    }
+    let Some(chosen) = dialog.blocking_save_file() else {
+        return Ok(DialogSave::Cancelled);
+    };
+
+    let mut path = chosen.into_path().map_err(|e| e.to_string())?;
+    if path.extension().is_none() {
+        path.set_extension(stored_format.extension());
+    }
+    let format = format_for_path(&path, None, stored_format)?;
+    let bytes = convert_encoded(&app_handle, &bytes, stored_format, format)?;
+    let saved = write_file(&path, &bytes, false, true)?;
+    remember_saved_dir(&state, &saved);
+    record_saved(&app_handle, Some(id), &saved, false);
+
+    // Re-read the settings, they may have changed while the dialog was open
+    let mut settings = settings_state.get();
+    settings.last_save_dir = path.parent().map(Path::to_path_buf);
+    settings_state.set(settings)?;
+    Ok(DialogSave::Saved(saved))
+}
+
+/// Folder temporary exports are written to
+fn temp_export_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_cache_dir()
+        .map(|dir| dir.join(TEMP_EXPORT_DIR))
+        .map_err(|e| e.to_string())
+}
+
+/// Deletes temporary exports older than `TEMP_EXPORT_MAX_AGE`. Run once on startup.
+pub fn clean_temp_exports(app: &AppHandle) {
+    let Ok(entries) =
+        temp_export_dir(app).and_then(|dir| fs::read_dir(dir).map_err(|e| e.to_string()))
+    else {
+        return;
+    };
+
+    let now = SystemTime::now();
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > TEMP_EXPORT_MAX_AGE);
+        if expired {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Writes screenshot `id` to a uniquely named file in the app cache directory,
+/// or returns the one already written for the same screenshot and format
+fn temp_export(
+    app_handle: &AppHandle,
+    state: &ScreenshotState,
+    id: u64,
+    format: Option<OutputFormat>,
+) -> Result<String, SaveError> {
+    let template =
+        FilenameTemplate::parse(&app_handle.state::<SettingsState>().get().filename_template)?;
+    let (bytes, stored_format, stem) = state
+        .screenshots
+        .locked()
+        .get(&id)
+        .map(|stored| {
+            // Exports don't advance the counter, they aren't new captures
+            let context = stored.filename_context(state.filename_counter.load(Ordering::SeqCst));
+            (
+                stored.bytes.clone(),
+                stored.format,
+                template.render(&context),
+            )
+        })
+        .ok_or_else(|| "No screenshot taken".to_string())?;
+    let format = format.unwrap_or(stored_format);
+
+    let existing = state
+        .temp_exports
+        .locked()
+        .iter()
+        .find(|(export_id, export_format, path)| {
+            *export_id == id && *export_format == format && path.exists()
+        })
+        .map(|(_, _, path)| path.clone());
+    if let Some(path) = existing {
+        return Ok(path.to_string_lossy().into_owned());
+    }
+
+    let dir = temp_export_dir(app_handle)?;
+    fs::create_dir_all(&dir).map_err(|e| SaveError::io(e, &dir))?;
+    let bytes = convert_encoded(app_handle, &bytes, stored_format, format)?;
+    let saved = write_unique(&dir, &stem, format.extension(), &bytes)?;
+    remember_saved_dir(state, &saved);
+
+    let mut exports = state.temp_exports.locked();
+    exports.retain(|(_, _, path)| path.exists());
+    exports.push((id, format, PathBuf::from(&saved.path)));
+    Ok(saved.path)
+}
+
+/// Tauri command: Writes the current screenshot to a uniquely named file in the
+/// app cache directory and returns its absolute path, e.g. for dragging it into
+/// another app. Repeated calls for the same screenshot and format return the
+/// file already written.
+#[tauri::command(async)]
+pub fn export_to_temp_file(
+    app_handle: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    format: Option<OutputFormat>,
+) -> Result<String, SaveError> {
+    let id = state.current()?;
+    temp_export(&app_handle, &state, id, format)
+}
+
+/// Absolute path of a file holding screenshot `id` in its own format: the file
+/// it was auto-saved to while that still exists, otherwise a new file in the
+/// auto-save folder when auto-save is on, or else a temporary export
+pub fn saved_path(
+    app_handle: &AppHandle,
+    state: &ScreenshotState,
+    id: u64,
+) -> Result<String, SaveError> {
+    let existing = state
+        .screenshots
+        .locked()
+        .get(&id)
+        .ok_or_else(|| "No screenshot taken".to_string())?
+        .saved_path
+        .clone()
+        .filter(|path| Path::new(path).is_file());
+    if let Some(path) = existing {
+        return Ok(path);
+    }
+
+    let settings = app_handle.state::<SettingsState>().get();
+    if !settings.auto_save.enabled {
+        return temp_export(app_handle, state, id, None);
+    }
+    let template = FilenameTemplate::parse(&settings.filename_template)?;
+    let (bytes, format, stem) = state
+        .screenshots
+        .locked()
+        .get(&id)
+        .map(|stored| {
+            let context = stored.filename_context(state.next_filename_counter());
+            (
+                stored.bytes.clone(),
+                stored.format,
+                template.render(&context),
+            )
+        })
+        .ok_or_else(|| "No screenshot taken".to_string())?;
+
+    let saved = write_auto_save(
+        app_handle,
+        state,
+        &settings.auto_save,
+        &stem,
+        format,
+        &bytes,
+    )?;
+    if let Some(stored) = state.screenshots.locked().get_mut(&id) {
+        stored.saved_path = Some(saved.path.clone());
+    }
+    record_saved(app_handle, Some(id), &saved, true);
+    Ok(saved.path)
+}
+
+/// Tauri command: Shows a saved screenshot selected in Finder, Explorer or the
+/// Linux file manager. Only paths inside folders the app saved screenshots to,
+/// including the auto-save folder, are allowed.
+#[tauri::command]
+pub fn reveal_in_file_manager(
+    app_handle: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    path: String,
+) -> Result<(), RevealError> {
+    let path = fs::canonicalize(&path).map_err(|_| RevealError::NotFound)?;
+
+    let settings = app_handle.state::<SettingsState>().get();
+    let auto_save_dir = settings
+        .auto_save
+        .enabled
+        .then(|| auto_save_dir(&app_handle, &settings.auto_save).ok())
+        .flatten()
+        .and_then(|dir| fs::canonicalize(dir).ok());
+    let allowed = state
+        .saved_dirs
+        .locked()
+        .iter()
+        .chain(auto_save_dir.as_ref())
+        .any(|dir| path.starts_with(dir));
+    if !allowed {
+        return Err(RevealError::NotAllowed);
+    }
+
+    app_handle
+        .opener()
+        .reveal_item_in_dir(&path)
+        .map_err(|e| RevealError::Failed(e.to_string()))
+}
+
+/// Summary returned by `export_history`
+#[derive(Clone, Debug, Serialize)]
+pub struct HistoryExport {
+    pub exported: usize,
+    /// Id and error of each screenshot that couldn't be written
+    pub failed: Vec<(u64, SaveError)>,
+}
+
+/// Payload of the `history-export-progress` event, emitted once per screenshot
+#[derive(Clone, Debug, Serialize)]
+pub struct HistoryExportProgress {
+    pub id: u64,
+    /// Number of screenshots handled so far, including this one
+    pub done: usize,
+    pub total: usize,
+    /// Path written, `None` when writing failed
+    pub path: Option<String>,
+    pub error: Option<SaveError>,
+}
+
+/// Tauri command: Writes every stored screenshot into `dir`, named by the
+/// filename template, in `format` or each screenshot's own format. Emits
+/// `history-export-progress` per file; a failed file doesn't stop the rest.
+#[tauri::command(async)]
+pub fn export_history(
+    app_handle: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    dir: String,
+    format: Option<OutputFormat>,
+) -> Result<HistoryExport, SaveError> {
+    let dir = PathBuf::from(dir);
+    fs::create_dir_all(&dir).map_err(|e| SaveError::io(e, &dir))?;
+    let template =
+        FilenameTemplate::parse(&app_handle.state::<SettingsState>().get().filename_template)?;
+
+    // Copy the entries out so captures aren't blocked while writing
+    let counter = state.filename_counter.load(Ordering::SeqCst);
+    let entries: Vec<(u64, Arc<Vec<u8>>, OutputFormat, String)> = state
+        .screenshots
+        .locked()
+        .iter()
+        .map(|(id, stored)| {
+            let stem = template.render(&stored.filename_context(counter));
+            (*id, stored.bytes.clone(), stored.format, stem)
+        })
+        .collect();
+
+    let total = entries.len();
+    let mut summary = HistoryExport {
+        exported: 0,
+        failed: Vec::new(),
+    };
+    for (index, (id, bytes, stored_format, stem)) in entries.into_iter().enumerate() {
+        let format = format.unwrap_or(stored_format);
+        let result = convert_encoded(&app_handle, &bytes, stored_format, format)
+            .map_err(SaveError::from)
+            .and_then(|bytes| write_unique(&dir, &stem, format.extension(), &bytes));
+
+        let (path, error) = match result {
+            Ok(saved) => {
+                remember_saved_dir(&state, &saved);
+                summary.exported += 1;
+                (Some(saved.path), None)
+            }
+            Err(error) => {
+                summary.failed.push((id, error.clone()));
+                (None, Some(error))
+            }
+        };
+        app_handle.emit(
+            "history-export-progress",
+            HistoryExportProgress {
+                id,
+                done: index + 1,
+                total,
+                path,
+                error,
+            },
+        );
+    }
+    Ok(summary)
+}
+
+/// Tauri command: Crops the current screenshot at full resolution to `rect`,
+/// in image pixels, and writes only that region to `path`. The format is
+/// inferred from the extension unless given. `rect` is clamped to the image;
+/// an empty result is an error.
+#[tauri::command(async)]
+pub fn save_region_to_file(
+    app_handle: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    rect: Rect,
+    path: String,
+    format: Option<OutputFormat>,
+    overwrite: Option<bool>,
+) -> Result<SavedRegion, SaveError> {
+    let (bytes, stored_format, bounds) = state.with_current(|stored| {
+        let bounds = Rect {
+            x: 0,
+            y: 0,
+            width: stored.width,
+            height: stored.height,
+        };
+        (stored.bytes.clone(), stored.format, bounds)
+    })?;
+    let rect = rect
+        .intersection(&bounds)
+        .ok_or_else(|| "The region lies outside the screenshot".to_string())?;
+
+    let path = Path::new(&path);
+    let format = format_for_path(path, format, stored_format)?;
+    let image = decode_image(&bytes)?;
+    let cropped = imageops::crop_imm(
+        &image,
+        rect.x as u32,
+        rect.y as u32,
+        rect.width,
+        rect.height,
+    )
+    .to_image();
+    let bytes = encode_for_saving(&app_handle, &cropped, format)?;
+
+    let file = write_file(path, &bytes, false, overwrite.unwrap_or(false))?;
+    remember_saved_dir(&state, &file);
+    record_saved(&app_handle, state.current().ok(), &file, false);
+    Ok(SavedRegion { file, rect })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(kind: ErrorKind) -> SaveError {
+        SaveError::io(io::Error::new(kind, "boom"), Path::new("/shots/a.png"))
+    }
+
+    #[test]
+    fn io_errors_are_classified() {
+        assert!(matches!(
+            classify(ErrorKind::NotFound),
+            SaveError::FolderUnavailable(_)
+        ));
+        assert!(matches!(
+            classify(ErrorKind::NotADirectory),
+            SaveError::FolderUnavailable(_)
+        ));
+        assert!(matches!(
+            classify(ErrorKind::PermissionDenied),
+            SaveError::PermissionDenied(_)
+        ));
+        assert!(matches!(
+            classify(ErrorKind::StorageFull),
+            SaveError::DiskFull(_)
+        ));
+        assert!(matches!(
+            classify(ErrorKind::QuotaExceeded),
+            SaveError::DiskFull(_)
+        ));
+        assert!(matches!(
+            classify(ErrorKind::ReadOnlyFilesystem),
+            SaveError::ReadOnly(_)
+        ));
+        assert!(matches!(
+            classify(ErrorKind::AlreadyExists),
+            SaveError::AlreadyExists(_)
+        ));
+        assert!(matches!(
+            classify(ErrorKind::Interrupted),
+            SaveError::Failed(_)
+        ));
+    }
+
+    #[test]
+    fn save_error_names_the_path() {
+        let SaveError::DiskFull(message) = classify(ErrorKind::StorageFull) else {
+            panic!("expected DiskFull");
+        };
+        assert_eq!(message, "/shots/a.png: boom");
+    }
+
+    #[test]
+    fn save_error_wire_shape() {
+        assert_eq!(
+            serde_json::to_value(classify(ErrorKind::ReadOnlyFilesystem)).unwrap(),
+            serde_json::json!({ "kind": "readOnly", "message": "/shots/a.png: boom" })
+        );
+        assert_eq!(
+            serde_json::to_value(SaveError::from("No screenshot".to_string())).unwrap(),
+            serde_json::json!({ "kind": "failed", "message": "No screenshot" })
+        );
+    }
+
+    #[test]
+    fn write_file_refuses_to_overwrite() {
+        let dir =
+            std::env::temp_dir().join(format!("screenshot-maker-save-{}", std::process::id()));
+        let path = dir.join("nested").join("a.png");
+        assert!(matches!(
+            write_file(&path, b"one", false, false),
+            Err(SaveError::FolderUnavailable(_))
+        ));
+        write_file(&path, b"one", true, false).unwrap();
+        assert!(matches!(
+            write_file(&path, b"two", false, false),
+            Err(SaveError::AlreadyExists(_))
+        ));
+        write_file(&path, b"three", false, true).unwrap();
+        let written = fs::read(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(written, b"three");
+    }
+}
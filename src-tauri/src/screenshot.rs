@@ -1,22 +1,319 @@
+use crate::activity::{self, Activity};
+use crate::clipboard::CopyTarget;
+use crate::color_profile;
+use crate::filename::FilenameContext;
+use crate::history;
+use crate::interlace;
+use crate::metadata::CaptureMetadata;
+use crate::postprocess::{self, PostProcess};
+use crate::recovery;
+use crate::save::{self, SaveError};
+use crate::settings::{Settings, SettingsState, MAX_PRE_CAPTURE_DELAY_MS};
+use crate::stitch;
+use crate::tray::{self, TrayState};
+use crate::window_list::{self, find_window, WindowError, WindowInfo};
 use base64::{engine::general_purpose::STANDARD, Engine};
-use screenshots::image::ImageFormat;
+use chrono::{DateTime, Local, TimeZone};
+use screenshots::image::codecs::bmp::BmpEncoder;
+use screenshots::image::codecs::jpeg::JpegEncoder;
+use screenshots::image::codecs::tiff::TiffEncoder;
+use screenshots::image::imageops::{self, FilterType};
+use screenshots::image::io::Reader as ImageReader;
+use screenshots::image::{ColorType, ImageEncoder, Rgb, RgbImage, Rgba, RgbaImage};
 use screenshots::Screen;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
 use std::io::Cursor;
-use std::sync::Mutex;
-use tauri::{AppHandle, Emitter, Manager, WebviewUrl, WebviewWindowBuilder};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::http::{self, header, Request, StatusCode};
+use tauri::ipc::Response;
+use tauri::{AppHandle, Emitter, Manager, WebviewUrl, WebviewWindow, WebviewWindowBuilder};
+
+/// WebP quality of thumbnails, low enough to keep `screenshot-taken` events small
+const THUMBNAIL_QUALITY: u8 = 75;
+
+/// Longest edge screenshots are downscaled to before estimating encoded sizes
+const ESTIMATE_MAX_EDGE: u32 = 1024;
+
+/// URI scheme serving stored screenshots, see `serve_screenshot`
+pub const SCREENSHOT_PROTOCOL: &str = "screenshot";
 
 /// Manages the state of the current screenshot
 pub struct ScreenshotState {
-    pub current_screenshot: Mutex<Option<String>>,
+    /// Stored screenshots keyed by id, at most the `history_size` setting;
+    /// the oldest are dropped first
+    pub screenshots: Mutex<BTreeMap<u64, StoredScreenshot>>,
+    /// Screenshots dropped from `screenshots` whose thumbnails are still listed
+    /// in the history, at most the `history_thumbnails_kept` setting
+    pub thumbnail_history: Mutex<BTreeMap<u64, HistoryEntry>>,
+    /// Id of the most recent screenshot
+    pub current_id: Mutex<Option<u64>>,
+    /// Source of screenshot ids
+    pub next_id: AtomicU64,
+    /// The most recent successful capture request and its options, replayed by
+    /// `repeat_last_capture` and `retake_screenshot`
+    pub last_capture: Mutex<Option<(CaptureRequest, CaptureOptions)>>,
+    /// Bumped whenever a delayed capture starts or is cancelled, so stale countdowns stop
+    pub countdown_generation: AtomicU64,
+    /// Frames of the most recent burst capture as data URLs, indexed by frame id
+    pub burst_frames: Mutex<Vec<String>>,
+    /// Bumped whenever a burst starts or is cancelled, so stale bursts stop
+    pub burst_generation: AtomicU64,
+    /// Screen frozen for region selection, held until the selection is confirmed or cancelled
+    pub frozen_frame: Mutex<Option<FrozenFrame>>,
+    /// Scrolling capture in progress, if any
+    pub scroll_session: Mutex<Option<ScrollSession>>,
+    /// Areas blacked out in every capture, mirrored to the settings file
+    pub capture_masks: Mutex<Vec<CaptureMask>>,
+    /// Last value of the `{counter}` file name token
+    pub filename_counter: AtomicU64,
+    /// Files written by `export_to_temp_file`, as screenshot id, format and path
+    pub temp_exports: Mutex<Vec<(u64, OutputFormat, PathBuf)>>,
+    /// Canonical folders screenshots were saved to, which `reveal_in_file_manager` may show
+    pub saved_dirs: Mutex<Vec<PathBuf>>,
+    /// Bumped whenever an image is copied, so stale clipboard clear timers stop
+    pub clipboard_clear_generation: AtomicU64,
+    /// Held while a capture runs, so concurrent captures take turns instead of
+    /// hiding and restoring windows over each other
+    pub capture_lock: Mutex<()>,
+    /// Entered while a shortcut, tray or retake capture is running, see `COALESCE_TRIGGERS`
+    pub trigger_in_progress: TriggerGate,
+    /// Recovery files being written in the background, waited for when quitting
+    pub pending_writes: AtomicUsize,
+    /// Captures taken since the editor was last shown, badged on the tray icon
+    pub unviewed: AtomicUsize,
+    /// Set by `set_paused`: captures skip auto-save, the after-capture copy,
+    /// the preview and persisting. Never persisted, so the app always starts unpaused.
+    pub paused: AtomicBool,
+}
+
+/// An area blacked out in every capture of a screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureMask {
+    /// OS display id of the screen the mask applies to
+    pub screen_id: u32,
+    /// Masked area in physical pixels, relative to the screen's top-left corner
+    pub rect: Rect,
+}
+
+/// A captured screenshot kept for later retrieval
+pub struct StoredScreenshot {
+    /// Full-resolution image in its output format, shared so readers can
+    /// release the history lock before decoding or writing it
+    pub bytes: Arc<Vec<u8>>,
+    /// Format `bytes` are encoded in
+    pub format: OutputFormat,
+    pub width: u32,
+    pub height: u32,
+    /// OS display id of the captured screen, `None` when several screens were combined
+    pub screen_id: Option<u32>,
+    /// Capture time in milliseconds since the Unix epoch
+    pub captured_at: u64,
+    /// Downscaled copy for the preview window as a data URL
+    pub thumbnail_data_url: String,
+    /// Index into `Screen::all()` of the captured screen, `None` when several screens were combined
+    pub screen_index: Option<usize>,
+    /// Kind of capture, see `CaptureRequest::kind`
+    pub capture_mode: String,
+    /// Absolute path of the file this screenshot was auto-saved to, or written
+    /// to by `copy_screenshot_path_to_clipboard`
+    pub saved_path: Option<String>,
+    /// Kept out of the `history_size` eviction, see `set_screenshot_pinned`
+    pub pinned: bool,
+    /// When `set_current_screenshot` last moved this screenshot to the back of
+    /// the eviction order, in milliseconds since the Unix epoch
+    pub promoted_at: Option<u64>,
+    /// Name given with `set_screenshot_label`
+    pub label: Option<String>,
+    /// How the screenshot was produced, `None` for screenshots persisted
+    /// before sources were recorded
+    pub source: Option<CaptureSource>,
+    /// What started the capture, `None` for screenshots persisted before
+    /// triggers were recorded
+    pub trigger: Option<TriggerSource>,
+    /// Captured while paused: never written to the history folder or a
+    /// recovery file, so it's lost on quit
+    pub memory_only: bool,
+}
+
+impl StoredScreenshot {
+    /// Describes the screenshot stored under `id`, without a data URL, see
+    /// `ScreenshotState::payload`
+    fn payload(&self, id: u64) -> ScreenshotPayload {
+        ScreenshotPayload {
+            id,
+            url: screenshot_url(id),
+            data_url: None,
+            extension: self.format.extension(),
+            width: self.width,
+            height: self.height,
+            screen_id: self.screen_id,
+            captured_at: self.captured_at,
+        }
+    }
+
+    /// Describes the screenshot stored under `id` for the history list
+    pub(crate) fn history_entry(&self, id: u64) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            thumbnail_data_url: self.thumbnail_data_url.clone(),
+            format: self.format,
+            width: self.width,
+            height: self.height,
+            screen_id: self.screen_id,
+            screen_index: self.screen_index,
+            captured_at: self.captured_at,
+            capture_mode: self.capture_mode.clone(),
+            saved_path: self.saved_path.clone(),
+            pinned: self.pinned,
+            promoted_at: self.promoted_at,
+            label: self.label.clone(),
+            source: self.source.clone(),
+            trigger: self.trigger,
+            full_image: true,
+            memory_only: self.memory_only,
+        }
+    }
+
+    /// Context for naming this screenshot's file with a filename template
+    pub fn filename_context(&self, counter: u64) -> FilenameContext<'_> {
+        FilenameContext {
+            time: local_time(self.captured_at),
+            screen_index: self.screen_index,
+            capture_mode: &self.capture_mode,
+            width: self.width,
+            height: self.height,
+            counter,
+            label: self.label.as_deref(),
+        }
+    }
+}
+
+/// Frames collected by a scrolling capture, all taken of the same region
+pub struct ScrollSession {
+    pub region: Rect,
+    pub frames: Vec<RgbaImage>,
+    /// Index of the captured screen, `None` when the region spans several screens
+    pub screen_index: Option<usize>,
+    /// Time the first frame was captured in milliseconds since the Unix epoch
+    pub started_at: u64,
+}
+
+/// A full-screen capture kept in memory while the user selects a region on it
+pub struct FrozenFrame {
+    /// Shared so the overlay's copy can be encoded without holding the lock
+    pub image: Arc<RgbaImage>,
+    /// Index of the frozen screen in `Screen::all()`
+    pub screen_index: usize,
+    /// Bounds of the frozen screen on the virtual desktop, in logical units
+    pub bounds: Rect,
+    pub scale_factor: f32,
+    /// Time the screen was frozen in milliseconds since the Unix epoch
+    pub captured_at: u64,
+    /// Options of the region capture, applied to the selection once it's
+    /// confirmed, with what started it
+    pub options: CaptureOptions,
 }
 
 impl ScreenshotState {
+    /// Id of the most recent screenshot
+    pub fn current(&self) -> Result<u64, String> {
+        (*self.current_id.locked()).ok_or_else(|| "No screenshot taken".to_string())
+    }
+
+    /// Reads from the most recent screenshot with `read`, which runs while the
+    /// history is locked, so it should only copy out what it needs
+    pub fn with_current<T>(&self, read: impl FnOnce(&StoredScreenshot) -> T) -> Result<T, String> {
+        let id = self.current()?;
+        self.screenshots
+            .locked()
+            .get(&id)
+            .map(read)
+            .ok_or_else(|| "No screenshot taken".to_string())
+    }
+
+    /// Describes screenshot `id`, embedding the image as a data URL only when
+    /// `include_data_url` is set. The image is encoded after the history lock
+    /// is released, so captures don't wait on it.
+    pub fn payload(&self, id: u64, include_data_url: bool) -> Option<ScreenshotPayload> {
+        let (payload, bytes, format) = {
+            let screenshots = self.screenshots.locked();
+            let stored = screenshots.get(&id)?;
+            (stored.payload(id), stored.bytes.clone(), stored.format)
+        };
+        Some(ScreenshotPayload {
+            data_url: include_data_url.then(|| encode_to_data_url(&bytes, format.mime())),
+            ..payload
+        })
+    }
+
+    /// Describes the current screenshot like `payload`, `None` when there's
+    /// none or only its thumbnail is left
+    pub fn current_payload(&self, include_data_url: bool) -> Option<ScreenshotPayload> {
+        let id = (*self.current_id.locked())?;
+        self.payload(id, include_data_url)
+    }
+
+    /// Advances the `{counter}` file name token and returns its new value
+    pub fn next_filename_counter(&self) -> u64 {
+        self.filename_counter.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
     /// Creates a new empty screenshot state
     pub fn new() -> Self {
         Self {
-            current_screenshot: Mutex::new(None),
+            screenshots: Mutex::new(BTreeMap::new()),
+            thumbnail_history: Mutex::new(BTreeMap::new()),
+            current_id: Mutex::new(None),
+            next_id: AtomicU64::new(1),
+            last_capture: Mutex::new(None),
+            countdown_generation: AtomicU64::new(0),
+            burst_frames: Mutex::new(Vec::new()),
+            burst_generation: AtomicU64::new(0),
+            frozen_frame: Mutex::new(None),
+            scroll_session: Mutex::new(None),
+            capture_masks: Mutex::new(Vec::new()),
+            filename_counter: AtomicU64::new(0),
+            temp_exports: Mutex::new(Vec::new()),
+            saved_dirs: Mutex::new(Vec::new()),
+            clipboard_clear_generation: AtomicU64::new(0),
+            capture_lock: Mutex::new(()),
+            trigger_in_progress: TriggerGate::default(),
+            pending_writes: AtomicUsize::new(0),
+            unviewed: AtomicUsize::new(0),
+            paused: AtomicBool::new(false),
         }
     }
+
+    /// Returns a copy of the capture masks
+    pub fn capture_masks(&self) -> Vec<CaptureMask> {
+        self.capture_masks.locked().clone()
+    }
+
+    /// Returns the most recent capture request and its options, or the
+    /// defaults if nothing was captured yet
+    pub fn last_capture(&self) -> (CaptureRequest, CaptureOptions) {
+        self.last_capture.locked().unwrap_or_default()
+    }
+}
+
+/// Locking that survives a thread panicking while it held the lock. The state
+/// behind every lock is valid between statements, so a poisoned lock is used
+/// as is rather than failing every later command.
+pub trait LockExt<T> {
+    fn locked(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> LockExt<T> for Mutex<T> {
+    fn locked(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
 }
 
 impl Default for ScreenshotState {
@@ -25,133 +322,4233 @@ impl Default for ScreenshotState {
     }
 }
 
-/// Captures a screenshot from the primary screen and converts it to a base64 data URL
-pub fn capture_screenshot_as_data_url() -> Result<Vec<u8>, String> {
+/// Payload emitted with the `screenshot-taken` event. Carries only a thumbnail;
+/// the full image is fetched with `get_full_screenshot(id)`.
+#[derive(Clone, Serialize)]
+pub struct ScreenshotTaken {
+    /// Reference to the full image
+    pub id: u64,
+    /// `screenshot://` URL serving the full image
+    pub url: String,
+    /// Downscaled copy for the preview window
+    pub thumbnail_data_url: String,
+    /// Index into `Screen::all()` of the captured screen, `None` when several screens were combined
+    pub screen_index: Option<usize>,
+    /// OS display id of the captured screen, `None` when several screens were combined
+    pub screen_id: Option<u32>,
+    /// Width of the captured image in pixels
+    pub width: u32,
+    /// Height of the captured image in pixels
+    pub height: u32,
+    /// Capture time in milliseconds since the Unix epoch
+    pub captured_at: u64,
+    /// Absolute path the capture was auto-saved to, `None` when auto-save is off or failed
+    pub saved_path: Option<String>,
+    pub source: CaptureSource,
+    pub trigger: TriggerSource,
+}
+
+/// A full-resolution screenshot together with its dimensions, so the editor
+/// can lay out its canvas before loading the image
+#[derive(Clone, Serialize)]
+pub struct ScreenshotPayload {
+    pub id: u64,
+    /// `screenshot://` URL serving the image, loadable directly by the webviews
+    pub url: String,
+    /// The image as a base64 data URL, only included when requested
+    pub data_url: Option<String>,
+    /// File extension matching the image's format, without the dot
+    pub extension: &'static str,
+    /// Width of the image in pixels
+    pub width: u32,
+    /// Height of the image in pixels
+    pub height: u32,
+    /// OS display id of the captured screen, `None` when several screens were combined
+    pub screen_id: Option<u32>,
+    /// Capture time in milliseconds since the Unix epoch
+    pub captured_at: u64,
+}
+
+/// A screenshot in the history list returned by `get_screenshot_history`
+#[derive(Clone, Debug, Serialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    /// Downscaled copy for the history list
+    pub thumbnail_data_url: String,
+    /// Format the full image is encoded in
+    pub format: OutputFormat,
+    pub width: u32,
+    pub height: u32,
+    /// OS display id of the captured screen, `None` when several screens were combined
+    pub screen_id: Option<u32>,
+    /// Index into `Screen::all()` of the captured screen, `None` when several screens were combined
+    pub screen_index: Option<usize>,
+    /// Capture time in milliseconds since the Unix epoch
+    pub captured_at: u64,
+    /// Kind of capture, see `CaptureRequest::kind`
+    pub capture_mode: String,
+    /// Absolute path the capture was saved to, if any
+    pub saved_path: Option<String>,
+    pub pinned: bool,
+    /// See `StoredScreenshot::promoted_at`
+    pub promoted_at: Option<u64>,
+    pub label: Option<String>,
+    /// See `StoredScreenshot::source`
+    pub source: Option<CaptureSource>,
+    /// See `StoredScreenshot::trigger`
+    pub trigger: Option<TriggerSource>,
+    /// Whether the full-resolution image is still kept, in memory or persisted;
+    /// `false` when only the thumbnail is left
+    pub full_image: bool,
+    /// See `StoredScreenshot::memory_only`
+    #[serde(skip)]
+    pub memory_only: bool,
+}
+
+impl HistoryEntry {
+    /// Position in the eviction order, lowest evicted first: the capture or
+    /// last promotion time, then the id
+    fn eviction_key(&self) -> (u64, u64) {
+        (self.promoted_at.unwrap_or(self.captured_at), self.id)
+    }
+}
+
+/// Describes a connected display. Consumed by the monitor picker UI, so field
+/// names are part of the frontend contract and must stay stable.
+#[derive(Clone, Debug, Serialize)]
+pub struct ScreenInfo {
+    /// Display identifier assigned by the OS
+    pub id: u32,
+    /// Horizontal position of the top-left corner on the virtual desktop
+    pub x: i32,
+    /// Vertical position of the top-left corner on the virtual desktop
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f32,
+    /// Whether the OS reports this display as the primary one
+    pub is_primary: bool,
+}
+
+impl From<&Screen> for ScreenInfo {
+    fn from(screen: &Screen) -> Self {
+        let info = &screen.display_info;
+        Self {
+            id: info.id,
+            x: info.x,
+            y: info.y,
+            width: info.width,
+            height: info.height,
+            scale_factor: info.scale_factor,
+            is_primary: info.is_primary,
+        }
+    }
+}
+
+/// A rectangle in virtual-desktop coordinates
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// Returns the overlapping area of two rectangles, or `None` if they don't overlap
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let left = self.x.max(other.x);
+        let top = self.y.max(other.y);
+        let right = (self.x as i64 + self.width as i64).min(other.x as i64 + other.width as i64);
+        let bottom = (self.y as i64 + self.height as i64).min(other.y as i64 + other.height as i64);
+
+        if right <= left as i64 || bottom <= top as i64 {
+            return None;
+        }
+
+        Some(Rect {
+            x: left,
+            y: top,
+            width: (right - left as i64) as u32,
+            height: (bottom - top as i64) as u32,
+        })
+    }
+
+    /// Returns the smallest rectangle containing both rectangles
+    pub fn union(&self, other: &Rect) -> Rect {
+        let left = self.x.min(other.x);
+        let top = self.y.min(other.y);
+        let right = (self.x as i64 + self.width as i64).max(other.x as i64 + other.width as i64);
+        let bottom = (self.y as i64 + self.height as i64).max(other.y as i64 + other.height as i64);
+
+        Rect {
+            x: left,
+            y: top,
+            width: (right - left as i64) as u32,
+            height: (bottom - top as i64) as u32,
+        }
+    }
+
+    /// Returns the rectangle with aspect ratio `ratio` (width, height) nearest to this one.
+    ///
+    /// The result keeps this rectangle's center and area, then is shrunk to fit
+    /// within `bounds` if needed and shifted inside them. Fails when the ratio
+    /// has a zero term or the constrained side would be under one pixel.
+    pub fn fit_aspect_ratio(&self, ratio: (u32, u32), bounds: &Rect) -> Result<Rect, String> {
+        let (ratio_w, ratio_h) = ratio;
+        if ratio_w == 0 || ratio_h == 0 {
+            return Err(format!("Invalid aspect ratio {}:{}", ratio_w, ratio_h));
+        }
+        let aspect = ratio_w as f64 / ratio_h as f64;
+
+        // Same area as the selection, reshaped to the requested aspect ratio
+        let area = self.width as f64 * self.height as f64;
+        let mut width = (area * aspect).sqrt();
+        let mut height = width / aspect;
+
+        // Shrink proportionally if the rectangle doesn't fit within the bounds
+        let fit = (bounds.width as f64 / width)
+            .min(bounds.height as f64 / height)
+            .min(1.0);
+        width = (width * fit).round();
+        height = (height * fit).round();
+        if width < 1.0 || height < 1.0 {
+            return Err("Selection is too small for the requested aspect ratio".to_string());
+        }
+
+        let (center_x, center_y) = self.center();
+        let max_x = bounds.x as i64 + bounds.width as i64 - width as i64;
+        let max_y = bounds.y as i64 + bounds.height as i64 - height as i64;
+        let x = (center_x as i64 - (width / 2.0) as i64).clamp(bounds.x as i64, max_x);
+        let y = (center_y as i64 - (height / 2.0) as i64).clamp(bounds.y as i64, max_y);
+
+        Ok(Rect {
+            x: x as i32,
+            y: y as i32,
+            width: width as u32,
+            height: height as u32,
+        })
+    }
+
+    /// Returns the center point of the rectangle
+    pub fn center(&self) -> (i32, i32) {
+        (
+            self.x + (self.width / 2) as i32,
+            self.y + (self.height / 2) as i32,
+        )
+    }
+}
+
+impl From<&ScreenInfo> for Rect {
+    fn from(info: &ScreenInfo) -> Self {
+        Self {
+            x: info.x,
+            y: info.y,
+            width: info.width,
+            height: info.height,
+        }
+    }
+}
+
+/// Selects which screen a full-screen capture targets
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CaptureMode {
+    /// The screen the mouse cursor is currently on
+    #[default]
+    Cursor,
+    /// The OS primary screen
+    Primary,
+    /// The screen containing the focused window, falling back to the cursor's screen
+    FocusedWindow,
+}
+
+/// Captures a full screen picked by `mode`.
+/// Returns the image and the index of the captured screen.
+pub fn capture_full_screen(
+    app: &AppHandle,
+    mode: CaptureMode,
+) -> Result<(RgbaImage, usize), String> {
     let screens = Screen::all().map_err(|e| e.to_string())?;
 
     if screens.is_empty() {
         return Err("No screens found".to_string());
     }
 
-    // Capture the primary screen (first screen)
-    let screen = &screens[0];
-    let image = screen.capture().map_err(|e| e.to_string())?;
+    // Fall back to the primary screen if the cursor position is unavailable
+    let primary = primary_screen_index(app, &screens);
+    let index = match mode {
+        CaptureMode::Cursor => cursor_screen_index(app, &screens).unwrap_or(primary),
+        CaptureMode::Primary => primary,
+        CaptureMode::FocusedWindow => focused_window_screen_index(&screens)
+            .or_else(|| cursor_screen_index(app, &screens))
+            .unwrap_or(primary),
+    };
 
-    // Convert to PNG bytes
-    let mut png_bytes: Vec<u8> = Vec::new();
-    image
-        .write_to(&mut Cursor::new(&mut png_bytes), ImageFormat::Png)
-        .map_err(|e| e.to_string())?;
+    let masks = app.state::<ScreenshotState>().capture_masks();
+    Ok((capture_screen(&screens[index], &masks)?, index))
+}
 
-    Ok(png_bytes)
+/// Finds the index of the OS primary screen. `Screen::all()` doesn't guarantee
+/// the primary display comes first, so it is matched against Tauri's primary monitor.
+fn primary_screen_index(app: &AppHandle, screens: &[Screen]) -> usize {
+    let infos: Vec<ScreenInfo> = screens.iter().map(ScreenInfo::from).collect();
+    let primary_pos = app
+        .primary_monitor()
+        .ok()
+        .flatten()
+        .map(|monitor| (monitor.position().x, monitor.position().y));
+    select_primary_screen(&infos, primary_pos)
 }
 
-/// Converts PNG bytes to base64 data URL
-fn encode_to_data_url(png_bytes: &[u8]) -> String {
-    let base64_image = STANDARD.encode(png_bytes);
-    format!("data:image/png;base64,{}", base64_image)
+/// Picks the primary screen from `screens`: the one positioned at
+/// `primary_pos`, else the one flagged as primary, else the first one.
+pub fn select_primary_screen(screens: &[ScreenInfo], primary_pos: Option<(i32, i32)>) -> usize {
+    primary_pos
+        .and_then(|(x, y)| {
+            screens
+                .iter()
+                .position(|screen| screen.x == x && screen.y == y)
+        })
+        .or_else(|| screens.iter().position(|screen| screen.is_primary))
+        .unwrap_or(0)
 }
 
-/// Stores the screenshot in the application state and emits an event
-fn store_and_emit_screenshot(
-    app: &AppHandle,
-    state: &tauri::State<ScreenshotState>,
-    data_url: &str,
-) {
-    *state.current_screenshot.lock().unwrap() = Some(data_url.to_string());
-    app.emit("screenshot-taken", data_url);
+/// Finds the index of the screen under the mouse cursor, if it can be determined
+fn cursor_screen_index(app: &AppHandle, screens: &[Screen]) -> Option<usize> {
+    let cursor = app.cursor_position().ok()?;
+    let screen = Screen::from_point(cursor.x as i32, cursor.y as i32).ok()?;
+    screens
+        .iter()
+        .position(|s| s.display_info.id == screen.display_info.id)
 }
 
-/// Tauri command: Captures a screenshot and returns it as a base64 data URL
-#[tauri::command]
-pub fn take_screenshot(
-    app: AppHandle,
-    state: tauri::State<ScreenshotState>,
-) -> Result<String, String> {
-    let png_bytes = capture_screenshot_as_data_url()?;
-    let data_url = encode_to_data_url(&png_bytes);
-    store_and_emit_screenshot(&app, &state, &data_url);
-    Ok(data_url)
+/// Finds the index of the screen containing the center of the focused window, if it can be determined
+fn focused_window_screen_index(screens: &[Screen]) -> Option<usize> {
+    let window = active_window_bounds()?;
+    let (x, y) = window.center();
+    screen_index_at(screens, x, y)
 }
 
-/// Tauri command: Retrieves the currently stored screenshot
-#[tauri::command]
-pub fn get_current_screenshot(state: tauri::State<ScreenshotState>) -> Option<String> {
-    state.current_screenshot.lock().unwrap().clone()
+/// Finds the index of the screen containing the point `(x, y)`
+fn screen_index_at(screens: &[Screen], x: i32, y: i32) -> Option<usize> {
+    screens.iter().position(|screen| {
+        let info = &screen.display_info;
+        x >= info.x
+            && x < info.x + info.width as i32
+            && y >= info.y
+            && y < info.y + info.height as i32
+    })
 }
 
-/// Creates and displays the preview window at the bottom-right corner of the primary monitor
-fn create_preview_window(app: &AppHandle) -> Result<(), String> {
-    // Get primary monitor size for positioning
-    let (x, y) = if let Some(monitor) = app.primary_monitor().ok().flatten() {
-        let size = monitor.size();
-        let position = monitor.position();
-        (
-            position.x as f64 + size.width as f64 - 320.0 - 20.0,
-            position.y as f64 + size.height as f64 - 200.0 - 60.0,
+/// Captures the given screen
+fn capture_screen(screen: &Screen, masks: &[CaptureMask]) -> Result<RgbaImage, String> {
+    let mut image = screen.capture().map_err(|e| e.to_string())?;
+    apply_masks(&mut image, screen.display_info.id, (0, 0), masks);
+    Ok(image)
+}
+
+/// Captures a region of the virtual desktop.
+///
+/// The region is clamped to the screens it overlaps. When it spans several
+/// screens, each screen's part is captured separately and composited using
+/// the screens' positions, rendered at the highest involved scale factor with
+/// lower-DPI parts upscaled. Returns the image, the index of the captured
+/// screen (`None` when several screens were combined) and the area actually
+/// captured after clamping.
+fn capture_region(
+    region: Rect,
+    masks: &[CaptureMask],
+) -> Result<(RgbaImage, Option<usize>, Rect), String> {
+    if region.width == 0 || region.height == 0 {
+        return Err("Region must have a non-zero width and height".to_string());
+    }
+
+    let screens = Screen::all().map_err(|e| e.to_string())?;
+    let hits: Vec<_> = screens
+        .iter()
+        .enumerate()
+        .filter_map(|(index, screen)| {
+            let bounds = Rect::from(&ScreenInfo::from(screen));
+            region
+                .intersection(&bounds)
+                .map(|clamped| (index, screen, bounds, clamped))
+        })
+        .collect();
+
+    match hits.as_slice() {
+        [] => Err("Region lies entirely off-screen".to_string()),
+        [(index, screen, bounds, clamped)] => Ok((
+            capture_area_on_screen(screen, *bounds, *clamped, masks)?,
+            Some(*index),
+            *clamped,
+        )),
+        _ => {
+            let parts = hits
+                .iter()
+                .map(|(_, screen, bounds, clamped)| {
+                    capture_area_on_screen(screen, *bounds, *clamped, masks)
+                        .map(|image| (*clamped, image))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let scale = hits
+                .iter()
+                .map(|(_, screen, _, _)| screen.display_info.scale_factor)
+                .fold(1.0, f32::max);
+
+            let area = parts
+                .iter()
+                .map(|(clamped, _)| *clamped)
+                .reduce(|area, clamped| area.union(&clamped))
+                .unwrap_or(region);
+
+            Ok((composite(&parts, scale), None, area))
+        }
+    }
+}
+
+/// Captures `area` from `screen`, where `area` lies within the screen's `bounds`
+fn capture_area_on_screen(
+    screen: &Screen,
+    bounds: Rect,
+    area: Rect,
+    masks: &[CaptureMask],
+) -> Result<RgbaImage, String> {
+    // capture_area expects coordinates relative to the screen's origin
+    let mut image = screen
+        .capture_area(
+            area.x - bounds.x,
+            area.y - bounds.y,
+            area.width,
+            area.height,
         )
-    } else {
-        (1580.0, 820.0) // Default position
-    };
+        .map_err(|e| e.to_string())?;
 
-    // Create preview window
-    let _preview_window = WebviewWindowBuilder::new(
-        app,
-        "preview",
-        WebviewUrl::App("index.html?mode=preview".into()),
-    )
-    .title("Screenshot Preview")
-    .inner_size(300.0, 180.0)
-    .position(x, y)
-    .decorations(false)
-    .always_on_top(true)
-    .resizable(false)
-    .skip_taskbar(true)
-    .build()
-    .map_err(|e| e.to_string())?;
+    let scale = screen.display_info.scale_factor as f64;
+    let origin = (
+        ((area.x - bounds.x) as f64 * scale).round() as i64,
+        ((area.y - bounds.y) as f64 * scale).round() as i64,
+    );
+    apply_masks(&mut image, screen.display_info.id, origin, masks);
+    Ok(image)
+}
 
-    Ok(())
+/// Paints the masks belonging to screen `screen_id` solid black onto an image
+/// captured from that screen. `origin` is the image's top-left corner within
+/// the screen, in physical pixels.
+fn apply_masks(image: &mut RgbaImage, screen_id: u32, origin: (i64, i64), masks: &[CaptureMask]) {
+    let (origin_x, origin_y) = origin;
+    let (width, height) = image.dimensions();
+
+    for mask in masks.iter().filter(|mask| mask.screen_id == screen_id) {
+        let left = (mask.rect.x as i64 - origin_x).clamp(0, width as i64) as u32;
+        let top = (mask.rect.y as i64 - origin_y).clamp(0, height as i64) as u32;
+        let right =
+            (mask.rect.x as i64 + mask.rect.width as i64 - origin_x).clamp(0, width as i64) as u32;
+        let bottom = (mask.rect.y as i64 + mask.rect.height as i64 - origin_y)
+            .clamp(0, height as i64) as u32;
+
+        for y in top..bottom {
+            for x in left..right {
+                image.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+            }
+        }
+    }
 }
 
-/// Shows the preview window, closing any existing preview window first
-pub fn show_preview_window(app: &AppHandle) -> Result<(), String> {
-    // Close existing preview window if any
-    if let Some(window) = app.get_webview_window("preview") {
-        let _ = window.close();
+/// Bounds of the foreground window in virtual-desktop coordinates, if the platform reports them
+fn active_window_bounds() -> Option<Rect> {
+    let window = active_win_pos_rs::get_active_window().ok()?;
+    let position = window.position;
+    if position.width < 1.0 || position.height < 1.0 {
+        return None;
     }
 
-    create_preview_window(app)
+    Some(Rect {
+        x: position.x.round() as i32,
+        y: position.y.round() as i32,
+        width: position.width.round() as u32,
+        height: position.height.round() as u32,
+    })
 }
 
-/// Tauri command: Hides/closes the preview window
-#[tauri::command]
-pub fn hide_preview_window(app: AppHandle) -> Result<(), String> {
-    if let Some(window) = app.get_webview_window("preview") {
-        window.close().map_err(|e| e.to_string())?;
+/// Title of the foreground window, `None` if it can't be determined
+fn active_window_title() -> Option<String> {
+    active_win_pos_rs::get_active_window()
+        .ok()
+        .map(|window| window.title)
+}
+
+/// Captures `window` clamped to the screen containing its center.
+/// Returns the image and the index of the captured screen.
+fn capture_window_rect(window: Rect, masks: &[CaptureMask]) -> Result<(RgbaImage, usize), String> {
+    let (center_x, center_y) = window.center();
+
+    let screens = Screen::all().map_err(|e| e.to_string())?;
+    let index = screen_index_at(&screens, center_x, center_y)
+        .ok_or_else(|| "Window lies entirely off-screen".to_string())?;
+    let screen = &screens[index];
+
+    let bounds = Rect::from(&ScreenInfo::from(screen));
+    let clamped = window
+        .intersection(&bounds)
+        .ok_or_else(|| "Window lies entirely off-screen".to_string())?;
+
+    Ok((
+        capture_area_on_screen(screen, bounds, clamped, masks)?,
+        index,
+    ))
+}
+
+/// Captures every screen and composites them into one image laid out by
+/// each screen's position on the virtual desktop.
+///
+/// The canvas origin is the top-left-most screen corner, so screens left of
+/// or above the primary (negative coordinates) are handled. Gaps between
+/// non-contiguous screens stay transparent. Screens with a lower scale factor
+/// are upscaled to the highest one so the layout stays consistent.
+fn capture_all_screens(masks: &[CaptureMask]) -> Result<RgbaImage, String> {
+    let screens = Screen::all().map_err(|e| e.to_string())?;
+
+    if screens.is_empty() {
+        return Err("No screens found".to_string());
     }
-    Ok(())
+
+    let parts = screens
+        .iter()
+        .map(|screen| {
+            let bounds = Rect::from(&ScreenInfo::from(screen));
+            capture_screen(screen, masks).map(|image| (bounds, image))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let scale = screens
+        .iter()
+        .map(|screen| screen.display_info.scale_factor)
+        .fold(1.0, f32::max);
+
+    Ok(composite(&parts, scale))
 }
 
-/// Tauri command: Shows the editor window
-#[tauri::command]
-pub fn show_editor_window(app: AppHandle) -> Result<(), String> {
-    if let Some(window) = app.get_webview_window("main") {
-        window.show().map_err(|e| e.to_string())?;
-        window.set_focus().map_err(|e| e.to_string())?;
+/// Composites images onto one transparent canvas covering their bounding box.
+///
+/// `parts` pairs each image with its bounds in virtual-desktop coordinates.
+/// Bounds are multiplied by `scale` to get pixel positions, and images whose
+/// size doesn't match their scaled bounds are resized to fit.
+fn composite(parts: &[(Rect, RgbaImage)], scale: f32) -> RgbaImage {
+    let to_px = |value: i64| (value as f64 * scale as f64).round() as i64;
+
+    let left = parts.iter().map(|(r, _)| r.x as i64).min().unwrap_or(0);
+    let top = parts.iter().map(|(r, _)| r.y as i64).min().unwrap_or(0);
+    let right = parts
+        .iter()
+        .map(|(r, _)| r.x as i64 + r.width as i64)
+        .max()
+        .unwrap_or(0);
+    let bottom = parts
+        .iter()
+        .map(|(r, _)| r.y as i64 + r.height as i64)
+        .max()
+        .unwrap_or(0);
+
+    let mut canvas = RgbaImage::new(to_px(right - left) as u32, to_px(bottom - top) as u32);
+    for (bounds, image) in parts {
+        let width = to_px(bounds.width as i64) as u32;
+        let height = to_px(bounds.height as i64) as u32;
+        let x = to_px(bounds.x as i64 - left);
+        let y = to_px(bounds.y as i64 - top);
+
+        if image.dimensions() == (width, height) {
+            imageops::overlay(&mut canvas, image, x, y);
+        } else {
+            let resized = imageops::resize(image, width, height, FilterType::Lanczos3);
+            imageops::overlay(&mut canvas, &resized, x, y);
+        }
     }
-    Ok(())
+
+    canvas
 }
 
-/// Triggers a screenshot capture in a background thread with a small delay
-pub fn trigger_screenshot(app: &AppHandle) {
-    let app_handle = app.clone();
-    std::thread::spawn(move || {
-        // Small delay to allow key release
-        std::thread::sleep(std::time::Duration::from_millis(100));
+/// How an image is encoded, beyond its output format
+#[derive(Clone, Debug, Default)]
+struct EncodeOptions {
+    png_compression: PngCompression,
+    /// Embedded as PNG tEXt chunks when set
+    metadata: Option<CaptureMetadata>,
+    /// Background transparency is flattened onto for formats without alpha
+    alpha_background: AlphaBackground,
+    /// Write PNGs Adam7-interlaced, so they render progressively
+    interlaced: bool,
+    /// Skip ancillary chunks and use a fixed compression strategy, so identical
+    /// pixels always encode to identical PNG bytes
+    deterministic: bool,
+    /// Color space chunk written to PNGs
+    color_profile: ColorProfile,
+    /// OS id of the captured display, whose ICC profile `ColorProfile::Auto` embeds
+    display_id: Option<u32>,
+}
 
-        let state = app_handle.state::<ScreenshotState>();
-        if let Ok(_data_url) = take_screenshot(app_handle.clone(), state) {
-            let _ = show_preview_window(&app_handle);
+impl EncodeOptions {
+    /// Fast compression and no metadata, for images only shown inside the app
+    fn fast() -> Self {
+        Self {
+            png_compression: PngCompression::Fast,
+            ..Default::default()
         }
-    });
+    }
+}
+
+/// Encodes an image in the given format. Returns the bytes and their MIME type.
+fn encode_image(
+    image: &RgbaImage,
+    format: OutputFormat,
+    options: &EncodeOptions,
+) -> Result<(Vec<u8>, &'static str), String> {
+    let mut bytes: Vec<u8> = Vec::new();
+    match format {
+        OutputFormat::Png => bytes = encode_png(image, options)?,
+        OutputFormat::Jpeg { quality } => {
+            JpegEncoder::new_with_quality(&mut bytes, quality.clamp(1, 100))
+                .encode_image(&flatten_alpha(image, options.alpha_background))
+                .map_err(|e| e.to_string())?;
+        }
+        OutputFormat::Webp { quality } => {
+            bytes = encode_webp(image, false, quality.clamp(1, 100))?;
+        }
+        OutputFormat::WebpLossless => {
+            bytes = encode_webp(image, true, 100)?;
+        }
+        OutputFormat::Bmp => {
+            BmpEncoder::new(&mut bytes)
+                .encode(
+                    flatten_alpha(image, options.alpha_background).as_raw(),
+                    image.width(),
+                    image.height(),
+                    ColorType::Rgb8,
+                )
+                .map_err(|e| e.to_string())?;
+        }
+        OutputFormat::Tiff => {
+            TiffEncoder::new(Cursor::new(&mut bytes))
+                .write_image(
+                    image.as_raw(),
+                    image.width(),
+                    image.height(),
+                    ColorType::Rgba8,
+                )
+                .map_err(|e| e.to_string())?;
+        }
+        #[cfg(feature = "avif")]
+        OutputFormat::Avif { quality, speed } => {
+            bytes = encode_avif(image, quality.clamp(1, 100), speed.clamp(1, 10))?;
+        }
+    }
+
+    Ok((bytes, format.mime()))
+}
+
+/// Encodes an image as PNG with the png crate, which unlike image's encoder can write text chunks
+fn encode_png(image: &RgbaImage, options: &EncodeOptions) -> Result<Vec<u8>, String> {
+    let png_compression = if options.deterministic {
+        PngCompression::Default
+    } else {
+        options.png_compression
+    };
+    let (compression, filter, adaptive, level) = match png_compression {
+        PngCompression::Fast => (
+            png::Compression::Fast,
+            png::FilterType::Sub,
+            png::AdaptiveFilterType::NonAdaptive,
+            flate2::Compression::fast(),
+        ),
+        PngCompression::Default if options.deterministic => (
+            png::Compression::Default,
+            png::FilterType::Sub,
+            png::AdaptiveFilterType::NonAdaptive,
+            flate2::Compression::default(),
+        ),
+        PngCompression::Default => (
+            png::Compression::Default,
+            png::FilterType::Sub,
+            png::AdaptiveFilterType::Adaptive,
+            flate2::Compression::default(),
+        ),
+        PngCompression::Best => (
+            png::Compression::Best,
+            png::FilterType::Sub,
+            png::AdaptiveFilterType::Adaptive,
+            flate2::Compression::best(),
+        ),
+    };
+
+    let mut info = png::Info::with_size(image.width(), image.height());
+    info.color_type = png::ColorType::Rgba;
+    info.bit_depth = png::BitDepth::Eight;
+    info.interlaced = options.interlaced;
+    if !options.deterministic {
+        match options.color_profile {
+            ColorProfile::Auto => {
+                match options
+                    .display_id
+                    .and_then(color_profile::display_icc_profile)
+                {
+                    Some(profile) => info.icc_profile = Some(profile.into()),
+                    None => info.srgb = Some(png::SrgbRenderingIntent::Perceptual),
+                }
+            }
+            ColorProfile::Srgb => info.srgb = Some(png::SrgbRenderingIntent::Perceptual),
+            ColorProfile::None => {}
+        }
+    }
+
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut encoder = png::Encoder::with_info(&mut bytes, info).map_err(|e| e.to_string())?;
+    encoder.set_compression(compression);
+    encoder.set_filter(filter);
+    encoder.set_adaptive_filter(adaptive);
+    if let Some(metadata) = options.metadata.as_ref().filter(|_| !options.deterministic) {
+        for (keyword, text) in metadata.text_chunks() {
+            encoder
+                .add_text_chunk(keyword.to_string(), text)
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    if options.interlaced {
+        let data = interlace::adam7_image_data(image, level)?;
+        writer
+            .write_chunk(png::chunk::IDAT, &data)
+            .map_err(|e| e.to_string())?;
+    } else {
+        writer
+            .write_image_data(image.as_raw())
+            .map_err(|e| e.to_string())?;
+    }
+    writer.finish().map_err(|e| e.to_string())?;
+
+    Ok(bytes)
+}
+
+/// Encodes an image as AVIF with ravif
+#[cfg(feature = "avif")]
+fn encode_avif(image: &RgbaImage, quality: u8, speed: u8) -> Result<Vec<u8>, String> {
+    let pixels: Vec<ravif::RGBA8> = image
+        .pixels()
+        .map(|&Rgba([r, g, b, a])| ravif::RGBA8::new(r, g, b, a))
+        .collect();
+    let encoded = ravif::Encoder::new()
+        .with_quality(quality as f32)
+        .with_speed(speed)
+        .encode_rgba(ravif::Img::new(
+            pixels.as_slice(),
+            image.width() as usize,
+            image.height() as usize,
+        ))
+        .map_err(|e| e.to_string())?;
+
+    Ok(encoded.avif_file)
+}
+
+/// Composites an image over an opaque background, dropping its alpha channel
+pub(crate) fn flatten_alpha(image: &RgbaImage, background: AlphaBackground) -> RgbImage {
+    let background = match background {
+        AlphaBackground::Black => 0,
+        AlphaBackground::White => 255,
+    };
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let Rgba([r, g, b, a]) = *image.get_pixel(x, y);
+        let blend = |channel: u8| {
+            ((channel as u32 * a as u32 + background * (255 - a as u32) + 127) / 255) as u8
+        };
+        Rgb([blend(r), blend(g), blend(b)])
+    })
+}
+
+/// Encodes an image as WebP with libwebp
+fn encode_webp(image: &RgbaImage, lossless: bool, quality: u8) -> Result<Vec<u8>, String> {
+    let encoder = webp::Encoder::from_rgba(image.as_raw(), image.width(), image.height());
+    let memory = encoder
+        .encode_simple(lossless, quality as f32)
+        .map_err(|e| format!("WebP encoding failed: {:?}", e))?;
+
+    Ok(memory.to_vec())
+}
+
+/// Encode options from the user's settings, without metadata
+fn settings_encode_options(app: &AppHandle) -> EncodeOptions {
+    let settings = app.state::<SettingsState>().get();
+    EncodeOptions {
+        png_compression: settings.png_compression,
+        alpha_background: settings.alpha_background,
+        interlaced: settings.interlaced_png,
+        deterministic: settings.deterministic_png,
+        color_profile: settings.color_profile,
+        ..Default::default()
+    }
+}
+
+/// Re-encodes stored screenshot bytes from one format to another with the
+/// user's encode settings. Bytes already in `to` are returned unchanged.
+pub(crate) fn convert_encoded<'a>(
+    app: &AppHandle,
+    bytes: &'a [u8],
+    from: OutputFormat,
+    to: OutputFormat,
+) -> Result<Cow<'a, [u8]>, String> {
+    if from == to {
+        return Ok(Cow::Borrowed(bytes));
+    }
+
+    let image = decode_image(bytes)?;
+    encode_for_saving(app, &image, to).map(Cow::Owned)
+}
+
+/// Decodes a stored screenshot's bytes. Every feature working on pixels goes
+/// through here rather than decoding on its own.
+pub(crate) fn decode_image(bytes: &[u8]) -> Result<RgbaImage, String> {
+    Ok(screenshots::image::load_from_memory(bytes)
+        .map_err(|e| e.to_string())?
+        .to_rgba8())
+}
+
+/// Encodes an image in `format` with the user's encode settings, without metadata
+pub(crate) fn encode_for_saving(
+    app: &AppHandle,
+    image: &RgbaImage,
+    format: OutputFormat,
+) -> Result<Vec<u8>, String> {
+    let (bytes, _) = encode_image(image, format, &settings_encode_options(app))?;
+    Ok(bytes)
+}
+
+/// Converts encoded image bytes to base64 data URL
+pub(crate) fn encode_to_data_url(bytes: &[u8], mime: &str) -> String {
+    let base64_image = STANDARD.encode(bytes);
+    format!("data:{};base64,{}", mime, base64_image)
+}
+
+/// Encodes an image in the given format as a base64 data URL
+fn encode_data_url(
+    image: &RgbaImage,
+    format: OutputFormat,
+    options: &EncodeOptions,
+) -> Result<String, String> {
+    let (bytes, mime) = encode_image(image, format, options)?;
+    Ok(encode_to_data_url(&bytes, mime))
+}
+
+/// Encodes the full-resolution image in the capture's output format, with the
+/// user's encode settings and, when enabled, the capture metadata
+fn encode_main(
+    app: &AppHandle,
+    image: &RgbaImage,
+    format: OutputFormat,
+    screen_index: Option<usize>,
+    captured_at: u64,
+    capture_mode: &str,
+    source_file: Option<&str>,
+) -> Result<(Vec<u8>, &'static str), String> {
+    let settings = app.state::<SettingsState>().get();
+    let options = EncodeOptions {
+        png_compression: settings.png_compression,
+        metadata: settings.embed_metadata.then(|| {
+            CaptureMetadata::new(
+                app,
+                captured_at,
+                screen_index.and_then(screen_id_at),
+                capture_mode,
+                source_file,
+            )
+        }),
+        alpha_background: settings.alpha_background,
+        interlaced: settings.interlaced_png,
+        deterministic: settings.deterministic_png,
+        color_profile: settings.color_profile,
+        display_id: screen_index.and_then(screen_id_at),
+    };
+
+    encode_image(image, format, &options)
+}
+
+/// Encodes a lossy WebP thumbnail whose longest edge is at most `max_edge`,
+/// independent of the main output format. Returns it as a data URL.
+pub(crate) fn encode_thumbnail(image: &RgbaImage, max_edge: u32) -> Result<String, String> {
+    let thumbnail = make_thumbnail(image, max_edge);
+    let bytes = encode_webp(&thumbnail, false, THUMBNAIL_QUALITY)?;
+    Ok(encode_to_data_url(&bytes, "image/webp"))
+}
+
+/// Downscales an image so its longest edge is at most `max_edge`, using a Lanczos3 filter
+fn make_thumbnail(image: &RgbaImage, max_edge: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let longest = width.max(height);
+    if longest <= max_edge {
+        return image.clone();
+    }
+
+    let ratio = max_edge as f64 / longest as f64;
+    let thumb_width = ((width as f64 * ratio).round() as u32).max(1);
+    let thumb_height = ((height as f64 * ratio).round() as u32).max(1);
+    imageops::resize(image, thumb_width, thumb_height, FilterType::Lanczos3)
+}
+
+/// Milliseconds since the Unix epoch
+pub(crate) fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Local time of a Unix timestamp in milliseconds, or now if it's out of range
+pub(crate) fn local_time(millis: u64) -> DateTime<Local> {
+    Local
+        .timestamp_millis_opt(millis as i64)
+        .single()
+        .unwrap_or_else(Local::now)
+}
+
+/// URL of the stored screenshot `id` under the `screenshot` URI scheme.
+/// Windows and Android only allow custom schemes through an `http://<scheme>.localhost` origin.
+fn screenshot_url(id: u64) -> String {
+    if cfg!(any(windows, target_os = "android")) {
+        format!("http://{}.localhost/{}", SCREENSHOT_PROTOCOL, id)
+    } else {
+        format!("{}://localhost/{}", SCREENSHOT_PROTOCOL, id)
+    }
+}
+
+/// Stores the encoded screenshot in the application state, generates its
+/// thumbnail and emits an event carrying only the thumbnail and the image URL.
+/// Returns the stored screenshot's payload without a data URL.
+#[allow(clippy::too_many_arguments)]
+fn store_and_emit_screenshot(
+    app: &AppHandle,
+    state: &tauri::State<ScreenshotState>,
+    image: &RgbaImage,
+    bytes: Vec<u8>,
+    format: OutputFormat,
+    screen_index: Option<usize>,
+    captured_at: u64,
+    capture_mode: &str,
+    source: CaptureSource,
+    trigger: TriggerSource,
+) -> Result<ScreenshotPayload, String> {
+    let settings = app.state::<SettingsState>().get();
+    let thumbnail_data_url = encode_thumbnail(image, settings.thumbnail_max_edge)?;
+    let mut stored = StoredScreenshot {
+        bytes: Arc::new(bytes),
+        format,
+        width: image.width(),
+        height: image.height(),
+        screen_id: screen_index.and_then(screen_id_at),
+        captured_at,
+        thumbnail_data_url,
+        screen_index,
+        capture_mode: capture_mode.to_string(),
+        saved_path: None,
+        pinned: false,
+        promoted_at: None,
+        label: None,
+        source: Some(source.clone()),
+        trigger: Some(trigger),
+        memory_only: state.paused.load(Ordering::SeqCst),
+    };
+    let paused = stored.memory_only;
+    if settings.auto_save.enabled && !paused {
+        let context = stored.filename_context(state.next_filename_counter());
+        stored.saved_path = save::auto_save(app, state, &settings, &stored.bytes, format, &context);
+    }
+
+    let id = state.next_id.fetch_add(1, Ordering::SeqCst);
+    activity::log_event(
+        app,
+        Some(id),
+        Activity::Captured {
+            capture_mode: stored.capture_mode.clone(),
+            width: stored.width,
+            height: stored.height,
+            bytes: stored.bytes.len() as u64,
+            trigger,
+        },
+    );
+    if let Some(path) = &stored.saved_path {
+        activity::log_event(
+            app,
+            Some(id),
+            Activity::Saved {
+                path: path.clone(),
+                bytes: stored.bytes.len() as u64,
+                auto: true,
+            },
+        );
+    }
+    let payload = stored.payload(id);
+    let event = ScreenshotTaken {
+        id,
+        url: payload.url.clone(),
+        thumbnail_data_url: stored.thumbnail_data_url.clone(),
+        screen_index,
+        screen_id: stored.screen_id,
+        width: stored.width,
+        height: stored.height,
+        captured_at: stored.captured_at,
+        saved_path: stored.saved_path.clone(),
+        source,
+        trigger,
+    };
+
+    // While paused, captures only go into memory
+    if !paused {
+        history::report(app, Some(id), history::save(app, id, &stored));
+        if stored.saved_path.is_none() {
+            recovery::write(app, id, stored.bytes.clone(), format);
+        }
+    }
+    state.screenshots.locked().insert(id, stored);
+    trim_history(app, state, &settings);
+    history::report(app, None, history::write_index(app, state));
+    *state.current_id.locked() = Some(id);
+
+    if !paused {
+        copy_after_capture(app, state, id, image, settings.after_capture_copy);
+    }
+
+    state.unviewed.fetch_add(1, Ordering::SeqCst);
+    tray::refresh_tray_menu(app);
+    tray::refresh_tray_icon(app);
+    app.emit("screenshot-taken", event);
+    Ok(payload)
+}
+
+/// Drops the full images of the oldest screenshots over the `history_size`
+/// setting, in memory and persisted, keeping their thumbnails in the history
+/// up to `history_thumbnails_kept`. Pinned screenshots are never dropped.
+///
+/// Over `history_memory_budget_mb`, the oldest full images also leave memory,
+/// except the newest screenshot's. Persisted ones stay in the history and are
+/// read back from disk when opened. Emits `memory-pressure` with the id of the
+/// current screenshot if its full image has to go.
+fn trim_history(app: &AppHandle, state: &ScreenshotState, settings: &Settings) {
+    let current_id = *state.current_id.locked();
+    let trimmed = trim_entries(
+        &mut state.screenshots.locked(),
+        &mut state.thumbnail_history.locked(),
+        settings,
+        current_id,
+    );
+
+    for (id, format) in trimmed.images {
+        history::remove_image(app, id, format);
+        recovery::remove(app, id);
+    }
+    for id in trimmed.unpersisted {
+        recovery::remove(app, id);
+    }
+    for (id, format) in trimmed.removed {
+        history::remove(app, id, format);
+    }
+    if let Some(id) = trimmed.evicted_current {
+        app.emit("memory-pressure", id);
+    }
+    tray::update_tray_tooltip(app, state);
+}
+
+/// What `trim_entries` dropped, cleaned up on disk by `trim_history` once the
+/// history locks are released
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Trimmed {
+    /// Screenshots over `history_size`, whose image and recovery files go
+    images: Vec<(u64, OutputFormat)>,
+    /// Screenshots out of memory over the budget and not persisted, whose recovery file goes
+    unpersisted: Vec<u64>,
+    /// Screenshots over `history_thumbnails_kept`, whose files all go
+    removed: Vec<(u64, OutputFormat)>,
+    /// The current screenshot, when its full image left memory over the budget
+    evicted_current: Option<u64>,
+}
+
+/// Applies the `trim_history` limits to the history maps, without touching the disk
+fn trim_entries(
+    screenshots: &mut BTreeMap<u64, StoredScreenshot>,
+    thumbnail_history: &mut BTreeMap<u64, HistoryEntry>,
+    settings: &Settings,
+    current_id: Option<u64>,
+) -> Trimmed {
+    let mut trimmed = Trimmed::default();
+    let mut full: Vec<(u64, u64)> = screenshots
+        .iter()
+        .filter(|(_, stored)| !stored.pinned)
+        .map(|(id, stored)| (stored.promoted_at.unwrap_or(stored.captured_at), *id))
+        .chain(
+            thumbnail_history
+                .values()
+                .filter(|entry| entry.full_image && !entry.pinned)
+                .map(HistoryEntry::eviction_key),
+        )
+        .collect();
+    full.sort_by_key(|key| Reverse(*key));
+    for (_, id) in full.into_iter().skip(settings.history_size) {
+        let entry = match screenshots.remove(&id) {
+            Some(stored) => stored.history_entry(id),
+            None => match thumbnail_history.remove(&id) {
+                Some(entry) => entry,
+                None => continue,
+            },
+        };
+        trimmed.images.push((id, entry.format));
+        thumbnail_history.insert(
+            id,
+            HistoryEntry {
+                full_image: false,
+                ..entry
+            },
+        );
+    }
+
+    if let Some(budget_mb) = settings.history_memory_budget_mb {
+        let budget = budget_mb.saturating_mul(1024 * 1024);
+        let mut used = MemoryUsage::of(screenshots, thumbnail_history).total_bytes;
+        let newest = screenshots.keys().max().copied();
+        let mut oldest: Vec<(u64, u64)> = screenshots
+            .iter()
+            .filter(|(id, stored)| !stored.pinned && Some(**id) != newest)
+            .map(|(id, stored)| (stored.promoted_at.unwrap_or(stored.captured_at), *id))
+            .collect();
+        oldest.sort();
+        for (_, id) in oldest {
+            if used <= budget {
+                break;
+            }
+            let Some(stored) = screenshots.remove(&id) else {
+                continue;
+            };
+            used -= stored.bytes.len() as u64;
+            let persisted = settings.persist_history && !stored.memory_only;
+            if !persisted {
+                trimmed.unpersisted.push(id);
+            }
+            if current_id == Some(id) {
+                trimmed.evicted_current = Some(id);
+            }
+            thumbnail_history.insert(
+                id,
+                HistoryEntry {
+                    full_image: persisted,
+                    ..stored.history_entry(id)
+                },
+            );
+        }
+    }
+
+    let mut thumbnail_ids: Vec<u64> = thumbnail_history
+        .values()
+        .filter(|entry| !entry.full_image && !entry.pinned)
+        .map(|entry| entry.id)
+        .collect();
+    thumbnail_ids.sort_by_key(|id| Reverse(*id));
+    for id in thumbnail_ids
+        .into_iter()
+        .skip(settings.history_thumbnails_kept)
+    {
+        if let Some(entry) = thumbnail_history.remove(&id) {
+            trimmed.removed.push((id, entry.format));
+        }
+    }
+    trimmed
+}
+
+/// Copies a new capture as the `after_capture_copy` setting asks. A failure
+/// doesn't fail the capture, it's emitted as `screenshot-copy-error`, or as
+/// `screenshot-save-error` when the file for `FilePath` couldn't be written.
+fn copy_after_capture(
+    app: &AppHandle,
+    state: &ScreenshotState,
+    id: u64,
+    image: &RgbaImage,
+    copy: AfterCaptureCopy,
+) {
+    let saved_path = || save::saved_path(app, state, id);
+    match dispatch_after_capture_copy(app, copy, image, saved_path) {
+        None => {}
+        Some(Ok(copied)) => activity::log_event(app, Some(id), copied),
+        Some(Err(AfterCaptureCopyError::Save(error))) => {
+            app.emit("screenshot-save-error", error);
+        }
+        Some(Err(AfterCaptureCopyError::Copy(error))) => {
+            app.emit("screenshot-copy-error", error);
+        }
+    }
+}
+
+/// Why `dispatch_after_capture_copy` put nothing on the clipboard
+#[derive(Debug)]
+enum AfterCaptureCopyError {
+    /// The file for `FilePath` couldn't be written
+    Save(SaveError),
+    Copy(String),
+}
+
+/// Puts what `copy` asks for on `clipboard`, returning the activity to log, or
+/// `None` for `Off`. `saved_path` is only called for `FilePath`.
+fn dispatch_after_capture_copy(
+    clipboard: &impl CopyTarget,
+    copy: AfterCaptureCopy,
+    image: &RgbaImage,
+    saved_path: impl FnOnce() -> Result<String, SaveError>,
+) -> Option<Result<Activity, AfterCaptureCopyError>> {
+    let copied = match copy {
+        AfterCaptureCopy::Off => return None,
+        AfterCaptureCopy::Image => clipboard.copy_image(image).map(|()| Activity::CopiedImage),
+        AfterCaptureCopy::FilePath => {
+            let path = match saved_path() {
+                Ok(path) => path,
+                Err(error) => return Some(Err(AfterCaptureCopyError::Save(error))),
+            };
+            clipboard
+                .copy_path(&path)
+                .map(|()| Activity::CopiedPath { path })
+        }
+    };
+    Some(copied.map_err(AfterCaptureCopyError::Copy))
+}
+
+/// Serves a stored screenshot over the `screenshot` URI scheme, e.g. `screenshot://localhost/3`.
+/// Allows any origin so the editor can export canvases the image was drawn on.
+pub fn serve_screenshot(app: &AppHandle, request: &Request<Vec<u8>>) -> http::Response<Vec<u8>> {
+    let id = request
+        .uri()
+        .path()
+        .trim_start_matches('/')
+        .parse::<u64>()
+        .ok();
+    // The bytes are copied into the response after the history lock is released
+    let state = app.state::<ScreenshotState>();
+    let found = id.and_then(|id| {
+        let screenshots = state.screenshots.locked();
+        let stored = screenshots.get(&id)?;
+        Some((stored.bytes.clone(), stored.format))
+    });
+
+    let response = match found {
+        Some((bytes, format)) => http::Response::builder()
+            .header(header::CONTENT_TYPE, format.mime())
+            .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .body(bytes.to_vec()),
+        None => http::Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Vec::new()),
+    };
+    response.unwrap_or_default()
+}
+
+/// OS display id of the screen at `index` in `Screen::all()`
+fn screen_id_at(index: usize) -> Option<u32> {
+    Screen::all()
+        .ok()?
+        .get(index)
+        .map(|screen| screen.display_info.id)
+}
+
+/// Encodes a captured image, stores it and emits it. Returns the image as a
+/// data URL along with the stored screenshot's payload.
+///
+/// `captured_at` is when the screen was captured, in milliseconds since the
+/// Unix epoch, rather than when it's published after post-processing.
+/// `capture_mode` names what was captured in the embedded metadata, see
+/// `Settings::embed_metadata`, which also records the file of an imported
+/// `source`. `trigger` is what started the capture.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn publish_capture(
+    app: &AppHandle,
+    state: &tauri::State<ScreenshotState>,
+    image: &RgbaImage,
+    screen_index: Option<usize>,
+    captured_at: u64,
+    format: OutputFormat,
+    capture_mode: &str,
+    source: CaptureSource,
+    trigger: TriggerSource,
+) -> Result<(String, ScreenshotPayload), String> {
+    let source_file = match &source {
+        CaptureSource::Imported { path } => Some(path.as_str()),
+        _ => None,
+    };
+    app.emit("screenshot-encoding", format);
+    let (bytes, mime) = encode_main(
+        app,
+        image,
+        format,
+        screen_index,
+        captured_at,
+        capture_mode,
+        source_file,
+    )?;
+    let data_url = encode_to_data_url(&bytes, mime);
+    let payload = store_and_emit_screenshot(
+        app,
+        state,
+        image,
+        bytes,
+        format,
+        screen_index,
+        captured_at,
+        capture_mode,
+        source,
+        trigger,
+    )?;
+    Ok((data_url, payload))
+}
+
+/// How a screenshot was produced, listed in the history and carried by
+/// `screenshot-taken`. Serialized with a `type` tag, e.g.
+/// `{"type":"region","rect":{...},"screenId":2}`; the shape is part of the
+/// frontend contract.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum CaptureSource {
+    /// A whole screen, `screen_id` being `None` when all screens were combined
+    #[serde(rename_all = "camelCase")]
+    FullScreen {
+        screen_id: Option<u32>,
+    },
+    /// A region of the virtual desktop, in the units `take_screenshot_region` takes
+    #[serde(rename_all = "camelCase")]
+    Region {
+        rect: Rect,
+        screen_id: Option<u32>,
+    },
+    Window {
+        title: String,
+    },
+    /// An image file loaded with `load_screenshot_from_file`
+    Imported {
+        path: String,
+    },
+    /// An image pasted from the clipboard
+    Clipboard,
+}
+
+/// What started a capture, listed in the history and carried by
+/// `screenshot-taken` and the activity log. Serialized as a stable camelCase
+/// string the frontend can switch on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TriggerSource {
+    /// A global shortcut
+    Hotkey,
+    /// The tray icon or its menu
+    Tray,
+    /// The preview's "Retake" button
+    Retake,
+    /// A command called by the frontend
+    #[default]
+    Ipc,
+}
+
+impl CaptureSource {
+    /// The whole screen at `screen_index` in `Screen::all()`, or all screens
+    fn full_screen(screen_index: Option<usize>) -> Self {
+        Self::FullScreen {
+            screen_id: screen_index.and_then(screen_id_at),
+        }
+    }
+}
+
+/// Describes what a capture should grab. The most recent successful request
+/// is kept in `ScreenshotState` so it can be replayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum CaptureRequest {
+    /// A full screen picked by `mode`
+    FullScreen { mode: CaptureMode },
+    /// The screen at `index` in `Screen::all()`
+    Screen { index: usize },
+    /// A rectangle on the virtual desktop
+    Region { rect: Rect },
+    /// All screens composited into one image
+    AllScreens,
+    /// The foreground window
+    ActiveWindow,
+    /// The window with the given platform id, see `list_windows`
+    Window { id: u32 },
+}
+
+impl CaptureRequest {
+    /// Name of the kind of capture, as serialized in the `kind` tag
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::FullScreen { .. } => "fullScreen",
+            Self::Screen { .. } => "screen",
+            Self::Region { .. } => "region",
+            Self::AllScreens => "allScreens",
+            Self::ActiveWindow => "activeWindow",
+            Self::Window { .. } => "window",
+        }
+    }
+}
+
+impl Default for CaptureRequest {
+    fn default() -> Self {
+        Self::FullScreen {
+            mode: CaptureMode::default(),
+        }
+    }
+}
+
+/// Resolution of the captured image on HiDPI displays
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CaptureScale {
+    /// Native pixels as captured from the display
+    #[default]
+    Physical,
+    /// Downscaled by the display's scale factor to its logical size
+    Logical,
+}
+
+/// Options applied to a capture regardless of what it grabs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CaptureOptions {
+    /// Hide the app's visible windows during the capture
+    pub hide_own_windows: bool,
+    pub scale: CaptureScale,
+    /// Time to wait before capturing, see `Settings::pre_capture_delay_ms`
+    pub pre_capture_delay_ms: u64,
+    pub format: OutputFormat,
+    /// Color reduction applied before encoding, so the thumbnail matches the output
+    pub post_process: Option<PostProcess>,
+    /// Set by the caller rather than taken from the frontend
+    #[serde(skip)]
+    pub trigger: TriggerSource,
+}
+
+/// Image format of a published capture
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum OutputFormat {
+    /// Lossless PNG
+    #[default]
+    Png,
+    /// Lossy JPEG, with `quality` clamped to 1–100. Transparency is flattened.
+    Jpeg { quality: u8 },
+    /// Lossy WebP, with `quality` clamped to 1–100
+    Webp { quality: u8 },
+    /// Lossless WebP, usually much smaller than PNG for UI screenshots
+    WebpLossless,
+    /// Uncompressed BMP. Transparency is flattened, since readers disagree on BMP alpha.
+    Bmp,
+    /// Uncompressed TIFF
+    Tiff,
+    /// AVIF, with `quality` clamped to 1–100 and `speed` to 1–10, 10 being fastest.
+    /// Encoding a 4K capture takes seconds even at high speeds.
+    #[cfg(feature = "avif")]
+    Avif { quality: u8, speed: u8 },
+}
+
+impl OutputFormat {
+    /// Name and extensions of every file format, e.g. for save dialog filters.
+    /// The first extension is the one `extension` gives.
+    pub const FILE_TYPES: &'static [(&'static str, &'static [&'static str])] = &[
+        ("PNG", &["png"]),
+        ("JPEG", &["jpg", "jpeg"]),
+        ("WebP", &["webp"]),
+        ("BMP", &["bmp"]),
+        ("TIFF", &["tiff", "tif"]),
+        #[cfg(feature = "avif")]
+        ("AVIF", &["avif"]),
+    ];
+
+    /// MIME type of images in this format
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg { .. } => "image/jpeg",
+            Self::Webp { .. } | Self::WebpLossless => "image/webp",
+            Self::Bmp => "image/bmp",
+            Self::Tiff => "image/tiff",
+            #[cfg(feature = "avif")]
+            Self::Avif { .. } => "image/avif",
+        }
+    }
+
+    /// File extension of images in this format, without the dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg { .. } => "jpg",
+            Self::Webp { .. } | Self::WebpLossless => "webp",
+            Self::Bmp => "bmp",
+            Self::Tiff => "tiff",
+            #[cfg(feature = "avif")]
+            Self::Avif { .. } => "avif",
+        }
+    }
+
+    /// Format for a file extension, case-insensitive. Lossy formats get a quality of 90.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg { quality: 90 }),
+            "webp" => Some(Self::WebpLossless),
+            "bmp" => Some(Self::Bmp),
+            "tif" | "tiff" => Some(Self::Tiff),
+            #[cfg(feature = "avif")]
+            "avif" => Some(Self::Avif {
+                quality: 90,
+                speed: 8,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Opaque color transparent pixels are composited over for formats without an
+/// alpha channel (JPEG, BMP)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AlphaBackground {
+    Black,
+    #[default]
+    White,
+}
+
+/// What is put on the clipboard once a capture is stored and auto-saved
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AfterCaptureCopy {
+    #[default]
+    Off,
+    Image,
+    /// Path of the auto-saved file, or of a temporary export when auto-save is off
+    FilePath,
+}
+
+/// Color space information embedded in PNG output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ColorProfile {
+    /// The captured display's ICC profile where the OS exposes it (macOS), sRGB otherwise
+    #[default]
+    Auto,
+    /// Always tag sRGB
+    Srgb,
+    /// No color space chunk
+    None,
+}
+
+/// Trade-off between PNG encoding speed and file size
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PngCompression {
+    /// Fastest encode, noticeably larger files; best for 4K captures
+    Fast,
+    #[default]
+    Default,
+    /// Smallest files, slowest encode
+    Best,
+}
+
+/// An image produced by a capture, before it is encoded
+struct Captured {
+    image: RgbaImage,
+    /// Index of the captured screen, `None` when several screens were combined
+    screen_index: Option<usize>,
+    /// Non-fatal issue encountered while capturing
+    warning: Option<String>,
+    /// Virtual-desktop area actually captured, when the capture was of a region
+    area: Option<Rect>,
+    /// Capture time in milliseconds since the Unix epoch
+    captured_at: u64,
+    source: CaptureSource,
+}
+
+impl Captured {
+    /// A capture of the whole screen at `screen_index`, or of all screens
+    fn new(image: RgbaImage, screen_index: Option<usize>) -> Self {
+        Self {
+            image,
+            screen_index,
+            warning: None,
+            area: None,
+            captured_at: unix_millis(),
+            source: CaptureSource::full_screen(screen_index),
+        }
+    }
+}
+
+/// Result of a published capture
+struct CaptureOutcome {
+    data_url: String,
+    payload: ScreenshotPayload,
+    /// Non-fatal issue encountered while capturing
+    warning: Option<String>,
+    /// Virtual-desktop area actually captured, when the capture was of a region
+    area: Option<Rect>,
+}
+
+/// Errors from the capture pipeline, emitted with `screenshot-error` when a
+/// shortcut or tray capture fails. Serialized with a `kind` tag like
+/// `WindowError`; every kind carries a `message` to show the user.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "camelCase")]
+pub enum ScreenshotError {
+    /// No screen is connected
+    NoScreens(String),
+    /// The OS denied screen access, i.e. Screen Recording permission is missing on macOS
+    PermissionDenied(String),
+    CaptureFailed(String),
+    /// The image was captured but couldn't be encoded or stored
+    EncodeFailed(String),
+}
+
+impl From<ScreenshotError> for String {
+    fn from(error: ScreenshotError) -> Self {
+        match error {
+            ScreenshotError::NoScreens(message)
+            | ScreenshotError::PermissionDenied(message)
+            | ScreenshotError::CaptureFailed(message)
+            | ScreenshotError::EncodeFailed(message) => message,
+        }
+    }
+}
+
+/// Whether the app may capture the screen. On macOS this needs the Screen
+/// Recording permission; when it's missing, the system is asked to prompt for
+/// it, which it does once.
+#[cfg(target_os = "macos")]
+fn screen_capture_allowed() -> bool {
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGPreflightScreenCaptureAccess() -> bool;
+        fn CGRequestScreenCaptureAccess() -> bool;
+    }
+    // Both only read the process's permission state
+    unsafe { CGPreflightScreenCaptureAccess() || CGRequestScreenCaptureAccess() }
+}
+
+/// Whether the app may capture the screen, which other platforms always allow
+#[cfg(not(target_os = "macos"))]
+fn screen_capture_allowed() -> bool {
+    true
+}
+
+/// Classifies an error returned by `execute_capture`
+fn capture_error(message: String) -> ScreenshotError {
+    if Screen::all().is_ok_and(|screens| screens.is_empty()) {
+        ScreenshotError::NoScreens(message)
+    } else {
+        ScreenshotError::CaptureFailed(message)
+    }
+}
+
+/// Performs the capture described by `request`
+fn execute_capture(app: &AppHandle, request: CaptureRequest) -> Result<Captured, String> {
+    let masks = app.state::<ScreenshotState>().capture_masks();
+    match request {
+        CaptureRequest::FullScreen { mode } => {
+            let (image, index) = capture_full_screen(app, mode)?;
+            Ok(Captured::new(image, Some(index)))
+        }
+        CaptureRequest::Screen { index } => Ok(Captured::new(
+            capture_screen_at(index, &masks)?,
+            Some(index),
+        )),
+        CaptureRequest::Region { rect } => {
+            let (image, index, area) = capture_region(rect, &masks)?;
+            Ok(Captured {
+                area: Some(area),
+                source: CaptureSource::Region {
+                    rect: area,
+                    screen_id: index.and_then(screen_id_at),
+                },
+                ..Captured::new(image, index)
+            })
+        }
+        CaptureRequest::AllScreens => Ok(Captured::new(capture_all_screens(&masks)?, None)),
+        CaptureRequest::ActiveWindow => capture_active_window(app),
+        CaptureRequest::Window { id } => {
+            let window = find_window(id).map_err(|e| match e {
+                WindowError::Failed(message) => message,
+                WindowError::NotFound => format!("No window with id {}", id),
+                WindowError::Unsupported => {
+                    "Window capture is not supported on this platform".to_string()
+                }
+            })?;
+            if window.is_minimized {
+                return Err(format!("Window \"{}\" is minimized", window.title));
+            }
+            let (image, index) = capture_window_rect(window.bounds, &masks)?;
+            Ok(Captured {
+                source: CaptureSource::Window {
+                    title: window.title,
+                },
+                ..Captured::new(image, Some(index))
+            })
+        }
+    }
+}
+
+/// Captures the screen at `index` in `Screen::all()`
+fn capture_screen_at(index: usize, masks: &[CaptureMask]) -> Result<RgbaImage, String> {
+    let screens = Screen::all().map_err(|e| e.to_string())?;
+    let screen = screens.get(index).ok_or_else(|| {
+        format!(
+            "screen index {} out of range ({} screens)",
+            index,
+            screens.len()
+        )
+    })?;
+
+    capture_screen(screen, masks)
+}
+
+/// Captures the foreground window.
+///
+/// If one of the app's own windows has focus, it is hidden first so the window
+/// behind it is captured. Falls back to a full-screen capture with a warning
+/// when the platform can't report the window bounds.
+fn capture_active_window(app: &AppHandle) -> Result<Captured, String> {
+    let own_focused = OWN_WINDOW_LABELS.iter().any(|label| {
+        app.get_webview_window(label)
+            .map(|window| window.is_focused().unwrap_or(false))
+            .unwrap_or(false)
+    });
+    let hidden = if own_focused {
+        hide_app_windows(app)
+    } else {
+        Vec::new()
+    };
+
+    let masks = app.state::<ScreenshotState>().capture_masks();
+    let result = match active_window_bounds() {
+        Some(bounds) => capture_window_rect(bounds, &masks).map(|(image, index)| Captured {
+            source: CaptureSource::Window {
+                title: active_window_title().unwrap_or_default(),
+            },
+            ..Captured::new(image, Some(index))
+        }),
+        None => capture_full_screen(app, CaptureMode::default()).map(|(image, index)| Captured {
+            warning: Some("Active window bounds unavailable; captured the full screen".to_string()),
+            ..Captured::new(image, Some(index))
+        }),
+    };
+    restore_windows(&hidden);
+
+    result
+}
+
+/// Scale factor of the captured screen, or the highest one when several screens were combined
+fn capture_scale_factor(screen_index: Option<usize>) -> f32 {
+    let screens = Screen::all().unwrap_or_default();
+    match screen_index.and_then(|index| screens.get(index)) {
+        Some(screen) => screen.display_info.scale_factor,
+        None => screens
+            .iter()
+            .map(|screen| screen.display_info.scale_factor)
+            .fold(1.0, f32::max),
+    }
+}
+
+/// Downscales a physical-resolution capture to logical size with a Lanczos3 filter.
+/// Fractional scale factors such as 1.25 round each dimension to the nearest pixel.
+fn to_logical_size(image: RgbaImage, scale_factor: f32) -> RgbaImage {
+    if scale_factor <= 1.0 {
+        return image;
+    }
+
+    let width = ((image.width() as f64 / scale_factor as f64).round() as u32).max(1);
+    let height = ((image.height() as f64 / scale_factor as f64).round() as u32).max(1);
+    imageops::resize(&image, width, height, FilterType::Lanczos3)
+}
+
+/// Runs `request`, records it as the last capture and publishes the result.
+/// Returns the screenshot along with details of what was captured.
+///
+/// With `options.hide_own_windows`, the app's visible windows are hidden during
+/// the capture and shown again afterwards, even if the capture fails. Blocks
+/// until any other capture in progress is done.
+fn run_capture(
+    app: &AppHandle,
+    state: &tauri::State<ScreenshotState>,
+    request: CaptureRequest,
+    options: CaptureOptions,
+) -> Result<CaptureOutcome, ScreenshotError> {
+    if !screen_capture_allowed() {
+        return Err(ScreenshotError::PermissionDenied(
+            "Screen recording permission is missing. Grant it in System Settings > \
+             Privacy & Security > Screen Recording, then restart the app."
+                .to_string(),
+        ));
+    }
+    if options.pre_capture_delay_ms > 0 {
+        std::thread::sleep(Duration::from_millis(options.pre_capture_delay_ms));
+    }
+
+    let _capturing = state.capture_lock.locked();
+    let hidden = if options.hide_own_windows {
+        hide_app_windows(app)
+    } else {
+        Vec::new()
+    };
+    let result = execute_capture(app, request);
+    restore_windows(&hidden);
+
+    let captured = result.map_err(capture_error)?;
+    *state.last_capture.locked() = Some((request, options));
+
+    let image = match options.scale {
+        CaptureScale::Physical => captured.image,
+        CaptureScale::Logical => {
+            to_logical_size(captured.image, capture_scale_factor(captured.screen_index))
+        }
+    };
+    let image = match options.post_process {
+        Some(post_process) => postprocess::apply(image, post_process),
+        None => image,
+    };
+    let (data_url, payload) = publish_capture(
+        app,
+        state,
+        &image,
+        captured.screen_index,
+        captured.captured_at,
+        options.format,
+        request.kind(),
+        captured.source,
+        options.trigger,
+    )
+    .map_err(ScreenshotError::EncodeFailed)?;
+    Ok(CaptureOutcome {
+        data_url,
+        payload,
+        warning: captured.warning,
+        area: captured.area,
+    })
+}
+
+/// Capture options with only the output scale, format and post-processing set
+fn output_options(
+    scale: Option<CaptureScale>,
+    format: Option<OutputFormat>,
+    post_process: Option<PostProcess>,
+) -> CaptureOptions {
+    CaptureOptions {
+        scale: scale.unwrap_or_default(),
+        format: format.unwrap_or_default(),
+        post_process,
+        ..Default::default()
+    }
+}
+
+/// Tauri command: Captures a screenshot and returns it as a base64 data URL with its dimensions.
+/// Captures the screen under the cursor unless another `mode` is given.
+/// With `hide_own_windows`, the app's visible windows are hidden during the
+/// capture and shown again afterwards, even if the capture fails.
+#[tauri::command(async)]
+pub fn take_screenshot(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    mode: Option<CaptureMode>,
+    hide_own_windows: Option<bool>,
+    scale: Option<CaptureScale>,
+    format: Option<OutputFormat>,
+    post_process: Option<PostProcess>,
+) -> Result<ScreenshotPayload, String> {
+    let request = CaptureRequest::FullScreen {
+        mode: mode.unwrap_or_default(),
+    };
+    let options = CaptureOptions {
+        hide_own_windows: hide_own_windows.unwrap_or(false),
+        scale: scale.unwrap_or_default(),
+        format: format.unwrap_or_default(),
+        post_process,
+        ..Default::default()
+    };
+    let outcome = run_capture(&app, &state, request, options)?;
+    Ok(ScreenshotPayload {
+        data_url: Some(outcome.data_url),
+        ..outcome.payload
+    })
+}
+
+/// Tauri command: Captures the screen containing the focused window and returns it as a base64 data URL.
+/// Falls back to the screen under the cursor, then the primary screen, if focus can't be determined.
+#[tauri::command(async)]
+pub fn take_screenshot_of_focused_monitor(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    scale: Option<CaptureScale>,
+    format: Option<OutputFormat>,
+    post_process: Option<PostProcess>,
+) -> Result<String, String> {
+    let request = CaptureRequest::FullScreen {
+        mode: CaptureMode::FocusedWindow,
+    };
+    run_capture(
+        &app,
+        &state,
+        request,
+        output_options(scale, format, post_process),
+    )
+    .map(|outcome| outcome.data_url)
+    .map_err(String::from)
+}
+
+/// Tauri command: Captures the screen at `index` in `Screen::all()` and returns it as a base64 data URL
+#[tauri::command(async)]
+pub fn take_screenshot_of_screen(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    index: usize,
+    scale: Option<CaptureScale>,
+    format: Option<OutputFormat>,
+    post_process: Option<PostProcess>,
+) -> Result<String, String> {
+    run_capture(
+        &app,
+        &state,
+        CaptureRequest::Screen { index },
+        output_options(scale, format, post_process),
+    )
+    .map(|outcome| outcome.data_url)
+    .map_err(String::from)
+}
+
+/// Result of a region capture
+#[derive(Clone, Serialize)]
+pub struct RegionCapture {
+    pub data_url: String,
+    /// Area actually captured after aspect-ratio fitting and clamping to the screens
+    pub rect: Rect,
+}
+
+/// Tauri command: Captures a rectangular region of the virtual desktop.
+///
+/// With `aspect_ratio` (width, height), the region is first reshaped to the
+/// nearest rectangle of that aspect ratio centered on the selection, kept
+/// within the screen containing the selection's center.
+#[tauri::command(async)]
+#[allow(clippy::too_many_arguments)]
+pub fn take_screenshot_region(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    aspect_ratio: Option<(u32, u32)>,
+    scale: Option<CaptureScale>,
+    format: Option<OutputFormat>,
+    post_process: Option<PostProcess>,
+) -> Result<RegionCapture, String> {
+    let mut rect = Rect {
+        x,
+        y,
+        width,
+        height,
+    };
+    if let Some(ratio) = aspect_ratio {
+        if width == 0 || height == 0 {
+            return Err("Region must have a non-zero width and height".to_string());
+        }
+        let screens = Screen::all().map_err(|e| e.to_string())?;
+        let (center_x, center_y) = rect.center();
+        let screen = screen_index_at(&screens, center_x, center_y)
+            .map(|index| &screens[index])
+            .ok_or_else(|| "Region lies entirely off-screen".to_string())?;
+        rect = rect.fit_aspect_ratio(ratio, &Rect::from(&ScreenInfo::from(screen)))?;
+    }
+
+    let outcome = run_capture(
+        &app,
+        &state,
+        CaptureRequest::Region { rect },
+        output_options(scale, format, post_process),
+    )?;
+    Ok(RegionCapture {
+        data_url: outcome.data_url,
+        rect: outcome.area.unwrap_or(rect),
+    })
+}
+
+/// Tauri command: Captures all screens combined into one image and returns it as a base64 data URL
+#[tauri::command(async)]
+pub fn take_screenshot_all_screens(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    scale: Option<CaptureScale>,
+    format: Option<OutputFormat>,
+    post_process: Option<PostProcess>,
+) -> Result<String, String> {
+    run_capture(
+        &app,
+        &state,
+        CaptureRequest::AllScreens,
+        output_options(scale, format, post_process),
+    )
+    .map(|outcome| outcome.data_url)
+    .map_err(String::from)
+}
+
+/// Result of an active window capture
+#[derive(Clone, Serialize)]
+pub struct ActiveWindowCapture {
+    pub data_url: String,
+    /// Set when the window bounds were unavailable and the full screen was captured instead
+    pub warning: Option<String>,
+}
+
+/// Tauri command: Captures the foreground window and returns it as a base64 data URL
+#[tauri::command(async)]
+pub fn take_screenshot_active_window(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    scale: Option<CaptureScale>,
+    format: Option<OutputFormat>,
+    post_process: Option<PostProcess>,
+) -> Result<ActiveWindowCapture, String> {
+    let outcome = run_capture(
+        &app,
+        &state,
+        CaptureRequest::ActiveWindow,
+        output_options(scale, format, post_process),
+    )?;
+    Ok(ActiveWindowCapture {
+        data_url: outcome.data_url,
+        warning: outcome.warning,
+    })
+}
+
+/// Tauri command: Lists visible top-level windows that can be captured with `capture_window`
+#[tauri::command]
+pub fn list_windows() -> Result<Vec<WindowInfo>, WindowError> {
+    window_list::list_windows()
+}
+
+/// Tauri command: Captures the window with the given id and returns it as a base64 data URL
+#[tauri::command(async)]
+pub fn capture_window(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    id: u32,
+    scale: Option<CaptureScale>,
+    format: Option<OutputFormat>,
+    post_process: Option<PostProcess>,
+) -> Result<String, WindowError> {
+    // Resolve the window first so a missing window or unsupported platform keeps its error kind
+    find_window(id)?;
+    run_capture(
+        &app,
+        &state,
+        CaptureRequest::Window { id },
+        output_options(scale, format, post_process),
+    )
+    .map(|outcome| outcome.data_url)
+    .map_err(|e| WindowError::Failed(e.into()))
+}
+
+/// Tauri command: Retakes the most recent capture for the preview's "Retake" button.
+///
+/// Closes the preview so it isn't captured, then captures in the background
+/// with the same request and options as the most recent capture and reopens
+/// the preview. Behaves like `take_screenshot` if nothing was captured yet.
+#[tauri::command]
+pub fn retake_screenshot(app: AppHandle, state: tauri::State<ScreenshotState>) {
+    let (request, options) = state.last_capture();
+    if let Some(window) = app.get_webview_window("preview") {
+        let _ = window.close();
+    }
+
+    // Give the compositor time to remove the closed preview
+    let options = CaptureOptions {
+        pre_capture_delay_ms: HIDE_SETTLE_DELAY.as_millis() as u64,
+        trigger: TriggerSource::Retake,
+        ..options
+    };
+    spawn_capture(&app, request, options);
+}
+
+/// Tauri command: Repeats the most recent capture with the same parameters.
+/// Falls back to a default full-screen capture if nothing was captured yet.
+#[tauri::command(async)]
+pub fn repeat_last_capture(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+) -> Result<String, String> {
+    let (request, options) = state.last_capture();
+    let options = CaptureOptions {
+        pre_capture_delay_ms: 0,
+        trigger: TriggerSource::Ipc,
+        ..options
+    };
+    run_capture(&app, &state, request, options)
+        .map(|outcome| outcome.data_url)
+        .map_err(String::from)
+}
+
+/// Tauri command: Retrieves the currently stored screenshot. The image is
+/// served from `url`; `include_data_url` also embeds it as a data URL for
+/// callers that haven't moved to the `screenshot` protocol yet.
+#[tauri::command]
+pub fn get_current_screenshot(
+    window: WebviewWindow,
+    state: tauri::State<ScreenshotState>,
+    include_data_url: Option<bool>,
+) -> Option<ScreenshotPayload> {
+    if window.label() == "main" {
+        mark_viewed(window.app_handle());
+    }
+    state.current_payload(include_data_url.unwrap_or(false))
+}
+
+/// Packs an image into the raw frame returned by `get_current_screenshot_raw`
+fn encode_raw_frame(image: &RgbaImage) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(8 + image.as_raw().len());
+    frame.extend_from_slice(&image.width().to_le_bytes());
+    frame.extend_from_slice(&image.height().to_le_bytes());
+    frame.extend_from_slice(image.as_raw());
+    frame
+}
+
+/// Tauri command: Retrieves the currently stored screenshot as raw pixels,
+/// transferred as binary without any base64 encoding.
+///
+/// The response is a frame of:
+/// - bytes 0..4: width in pixels, little-endian `u32`
+/// - bytes 4..8: height in pixels, little-endian `u32`
+/// - bytes 8..: `width * height` RGBA pixels, 4 bytes each, rows from top to bottom
+#[tauri::command]
+pub fn get_current_screenshot_raw(
+    state: tauri::State<ScreenshotState>,
+) -> Result<Response, String> {
+    let bytes = state.with_current(|stored| stored.bytes.clone())?;
+
+    // Stored images are kept encoded to save memory, so decode on request
+    let image = decode_image(&bytes)?;
+    Ok(Response::new(encode_raw_frame(&image)))
+}
+
+/// Estimated size of a stored screenshot encoded in one format
+#[derive(Clone, Serialize)]
+pub struct SizeEstimate {
+    pub format: OutputFormat,
+    pub bytes: u64,
+    /// `false` when the size was extrapolated from a downscaled copy
+    pub exact: bool,
+}
+
+/// Formats estimated when `estimate_encoded_sizes` is given none
+fn default_estimate_formats() -> Vec<OutputFormat> {
+    vec![
+        OutputFormat::Png,
+        OutputFormat::Jpeg { quality: 85 },
+        OutputFormat::Webp { quality: 85 },
+        OutputFormat::WebpLossless,
+    ]
+}
+
+/// Tauri command: Estimates the encoded size of a stored screenshot in each of
+/// `formats`, so the user can pick one before saving or uploading. Screenshots
+/// larger than `ESTIMATE_MAX_EDGE` are encoded downscaled and the sizes scaled up
+/// by the pixel ratio. The stored screenshot is left untouched.
+#[tauri::command(async)]
+pub fn estimate_encoded_sizes(
+    app_handle: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    id: u64,
+    formats: Option<Vec<OutputFormat>>,
+) -> Result<Vec<SizeEstimate>, String> {
+    let bytes = state
+        .screenshots
+        .locked()
+        .get(&id)
+        .map(|stored| stored.bytes.clone())
+        .ok_or_else(|| format!("Screenshot {} not found", id))?;
+    let image = decode_image(&bytes)?;
+
+    let sample = make_thumbnail(&image, ESTIMATE_MAX_EDGE);
+    let exact = sample.dimensions() == image.dimensions();
+    let pixel_ratio = (image.width() as f64 * image.height() as f64)
+        / (sample.width() as f64 * sample.height() as f64);
+    let options = settings_encode_options(&app_handle);
+
+    formats
+        .unwrap_or_else(default_estimate_formats)
+        .into_iter()
+        .map(|format| {
+            let (encoded, _) = encode_image(&sample, format, &options)?;
+            Ok(SizeEstimate {
+                format,
+                bytes: (encoded.len() as f64 * pixel_ratio).round() as u64,
+                exact,
+            })
+        })
+        .collect()
+}
+
+/// Tauri command: Retrieves the thumbnail of the currently stored screenshot
+#[tauri::command]
+pub fn get_current_thumbnail(state: tauri::State<ScreenshotState>) -> Option<String> {
+    let id = (*state.current_id.locked())?;
+    state
+        .screenshots
+        .locked()
+        .get(&id)
+        .map(|stored| stored.thumbnail_data_url.clone())
+}
+
+/// Tauri command: Retrieves the thumbnail of a stored screenshot by the id from its `screenshot-taken` event
+#[tauri::command]
+pub fn get_thumbnail(state: tauri::State<ScreenshotState>, id: u64) -> Option<String> {
+    let thumbnail = state
+        .screenshots
+        .locked()
+        .get(&id)
+        .map(|stored| stored.thumbnail_data_url.clone());
+    thumbnail.or_else(|| {
+        state
+            .thumbnail_history
+            .locked()
+            .get(&id)
+            .map(|entry| entry.thumbnail_data_url.clone())
+    })
+}
+
+/// Tauri command: Lists the screenshots in the history, newest first,
+/// including those only kept as thumbnails. With `pinned_first`, pinned
+/// screenshots come before all others.
+#[tauri::command]
+pub fn get_screenshot_history(
+    state: tauri::State<ScreenshotState>,
+    pinned_first: Option<bool>,
+) -> Vec<HistoryEntry> {
+    let mut history: Vec<HistoryEntry> = state
+        .screenshots
+        .locked()
+        .iter()
+        .map(|(id, stored)| stored.history_entry(*id))
+        .chain(state.thumbnail_history.locked().values().cloned())
+        .collect();
+    if pinned_first.unwrap_or(false) {
+        history.sort_by_key(|entry| (!entry.pinned, Reverse(entry.id)));
+    } else {
+        history.sort_by_key(|entry| Reverse(entry.id));
+    }
+    history
+}
+
+/// Memory held by the screenshot history, returned by `get_memory_usage`
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryUsage {
+    /// Encoded full images kept in memory
+    pub full_image_bytes: u64,
+    /// Thumbnail data URLs, including those of screenshots only kept as thumbnails
+    pub thumbnail_bytes: u64,
+    pub total_bytes: u64,
+    /// Screenshots whose full image is in memory
+    pub full_images: usize,
+    /// Screenshots listed in the history
+    pub history_count: usize,
+    /// The `history_memory_budget_mb` setting in bytes
+    pub budget_bytes: Option<u64>,
+}
+
+impl MemoryUsage {
+    fn of(
+        screenshots: &BTreeMap<u64, StoredScreenshot>,
+        thumbnail_history: &BTreeMap<u64, HistoryEntry>,
+    ) -> Self {
+        let full_image_bytes = screenshots
+            .values()
+            .map(|stored| stored.bytes.len() as u64)
+            .sum();
+        let thumbnail_bytes = screenshots
+            .values()
+            .map(|stored| stored.thumbnail_data_url.len() as u64)
+            .chain(
+                thumbnail_history
+                    .values()
+                    .map(|entry| entry.thumbnail_data_url.len() as u64),
+            )
+            .sum();
+        Self {
+            full_image_bytes,
+            thumbnail_bytes,
+            total_bytes: full_image_bytes + thumbnail_bytes,
+            full_images: screenshots.len(),
+            history_count: screenshots.len() + thumbnail_history.len(),
+            budget_bytes: None,
+        }
+    }
+}
+
+/// Tauri command: Reports how much memory the screenshot history takes
+#[tauri::command]
+pub fn get_memory_usage(
+    state: tauri::State<ScreenshotState>,
+    settings: tauri::State<SettingsState>,
+) -> MemoryUsage {
+    let usage = MemoryUsage::of(
+        &state.screenshots.locked(),
+        &state.thumbnail_history.locked(),
+    );
+    MemoryUsage {
+        budget_bytes: settings
+            .get()
+            .history_memory_budget_mb
+            .map(|budget_mb| budget_mb.saturating_mul(1024 * 1024)),
+        ..usage
+    }
+}
+
+/// Tauri command: Makes a screenshot from the history the current one, which
+/// the copy and save commands act on, and emits `current-screenshot-changed`
+/// with its payload. With `promote`, it also moves to the back of the eviction
+/// order as if it had just been captured.
+#[tauri::command]
+pub fn set_current_screenshot(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    id: u64,
+    promote: Option<bool>,
+) -> Result<ScreenshotPayload, String> {
+    history::rehydrate(&app, &state, id);
+    let payload = {
+        let mut screenshots = state.screenshots.locked();
+        let stored = screenshots
+            .get_mut(&id)
+            .ok_or_else(|| format!("Screenshot {} not found or only kept as a thumbnail", id))?;
+        if promote.unwrap_or(false) {
+            stored.promoted_at = Some(unix_millis());
+        }
+        stored.payload(id)
+    };
+    *state.current_id.locked() = Some(id);
+
+    app.emit("current-screenshot-changed", payload.clone());
+    if promote.unwrap_or(false) {
+        history::write_index(&app, &state)?;
+    }
+    Ok(payload)
+}
+
+/// Tauri command: Pins or unpins a screenshot in the history. Pinned
+/// screenshots are kept in full regardless of the `history_size` setting, up
+/// to `max_pinned_screenshots`; unpinned ones are evicted as usual.
+#[tauri::command]
+pub fn set_screenshot_pinned(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    id: u64,
+    pinned: bool,
+) -> Result<(), String> {
+    let settings = app.state::<SettingsState>().get();
+    {
+        let mut screenshots = state.screenshots.locked();
+        let mut thumbnail_history = state.thumbnail_history.locked();
+        let pinned_count = screenshots.values().filter(|stored| stored.pinned).count()
+            + thumbnail_history
+                .values()
+                .filter(|entry| entry.pinned)
+                .count();
+
+        let flag = match screenshots.get_mut(&id) {
+            Some(stored) => &mut stored.pinned,
+            None => match thumbnail_history.get_mut(&id) {
+                Some(entry) => &mut entry.pinned,
+                None => return Err(format!("Screenshot {} not found", id)),
+            },
+        };
+        if pinned && !*flag && pinned_count >= settings.max_pinned_screenshots {
+            return Err(format!(
+                "At most {} screenshots can be pinned, unpin one first",
+                settings.max_pinned_screenshots
+            ));
+        }
+        *flag = pinned;
+    }
+
+    trim_history(&app, &state, &settings);
+    history::write_index(&app, &state)
+}
+
+/// Longest screenshot label, in characters
+const MAX_LABEL_CHARS: usize = 200;
+
+/// Tauri command: Names a screenshot in the history, for finding it later
+/// and for the `{label}` file name token. The label is trimmed; an empty or
+/// missing one clears it.
+#[tauri::command]
+pub fn set_screenshot_label(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    id: u64,
+    label: Option<String>,
+) -> Result<(), String> {
+    let label = label
+        .map(|label| label.trim().to_string())
+        .filter(|label| !label.is_empty());
+    if label
+        .as_ref()
+        .is_some_and(|label| label.chars().count() > MAX_LABEL_CHARS)
+    {
+        return Err(format!(
+            "Labels can be at most {} characters",
+            MAX_LABEL_CHARS
+        ));
+    }
+
+    {
+        let mut screenshots = state.screenshots.locked();
+        let mut thumbnail_history = state.thumbnail_history.locked();
+        let slot = match screenshots.get_mut(&id) {
+            Some(stored) => &mut stored.label,
+            None => match thumbnail_history.get_mut(&id) {
+                Some(entry) => &mut entry.label,
+                None => return Err(format!("Screenshot {} not found", id)),
+            },
+        };
+        *slot = label;
+    }
+
+    tray::refresh_tray_menu(&app);
+    history::write_index(&app, &state)
+}
+
+/// Tauri command: Retrieves a screenshot from the history, loading its full
+/// image back from disk when it was persisted in an earlier session. Unlike
+/// `get_full_screenshot`, tells apart ids that never existed from screenshots
+/// whose full image was dropped from the history.
+#[tauri::command]
+pub fn get_screenshot_by_id(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    id: u64,
+    include_data_url: Option<bool>,
+) -> Result<ScreenshotPayload, String> {
+    history::rehydrate(&app, &state, id);
+    if let Some(payload) = state.payload(id, include_data_url.unwrap_or(false)) {
+        return Ok(payload);
+    }
+    if state.thumbnail_history.locked().contains_key(&id) {
+        return Err(format!(
+            "Screenshot {} is only kept as a thumbnail, its full image was dropped",
+            id
+        ));
+    }
+    Err(format!("Screenshot {} not found", id))
+}
+
+/// Tauri command: Removes a screenshot from the history, including its
+/// persisted files, and returns whether it was there. Deleting the current
+/// screenshot makes the newest remaining one current, see `fall_back_to_newest`.
+/// Fails when the history index couldn't be rewritten; the screenshot is
+/// removed from memory regardless.
+#[tauri::command]
+pub fn delete_screenshot(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    id: u64,
+) -> Result<bool, String> {
+    let mut screenshots = state.screenshots.locked();
+    let format = screenshots
+        .remove(&id)
+        .map(|stored| stored.format)
+        .or_else(|| {
+            state
+                .thumbnail_history
+                .locked()
+                .remove(&id)
+                .map(|entry| entry.format)
+        });
+
+    drop(screenshots);
+    let Some(format) = format else {
+        return Ok(false);
+    };
+
+    let was_current = {
+        let mut current_id = state.current_id.locked();
+        let was_current = *current_id == Some(id);
+        if was_current {
+            *current_id = None;
+        }
+        was_current
+    };
+    history::remove(&app, id, format);
+    recovery::remove(&app, id);
+    if was_current {
+        fall_back_to_newest(&app, &state);
+    }
+    tray::refresh_tray_menu(&app);
+    tray::update_tray_tooltip(&app, &state);
+    activity::log_event(&app, Some(id), Activity::Deleted);
+    history::write_index(&app, &state)?;
+    Ok(true)
+}
+
+/// Makes the most recent screenshot in the history that still has its full
+/// image the current one, after the current one was deleted, and emits
+/// `current-screenshot-changed` with its payload. When none is left, the
+/// payload is `null` and the preview window is closed.
+fn fall_back_to_newest(app: &AppHandle, state: &ScreenshotState) {
+    let payload = newest_with_image(app, state).and_then(|id| {
+        state
+            .screenshots
+            .locked()
+            .get(&id)
+            .map(|stored| stored.payload(id))
+    });
+
+    *state.current_id.locked() = payload.as_ref().map(|payload| payload.id);
+    if payload.is_none() {
+        let _ = hide_preview_window(app.clone());
+    }
+    app.emit("current-screenshot-changed", payload);
+}
+
+/// Id of the most recent screenshot in the history that still has its full
+/// image, loaded back into memory when it was persisted
+pub(crate) fn newest_with_image(app: &AppHandle, state: &ScreenshotState) -> Option<u64> {
+    let candidates = with_image_newest_first(
+        &state.screenshots.locked(),
+        &state.thumbnail_history.locked(),
+    );
+
+    // Persisted images that can't be read back are skipped
+    candidates.into_iter().find(|id| {
+        history::rehydrate(app, state, *id);
+        state.screenshots.locked().contains_key(id)
+    })
+}
+
+/// Ids of the screenshots in the history that still have their full image,
+/// in memory or persisted, newest first
+fn with_image_newest_first(
+    screenshots: &BTreeMap<u64, StoredScreenshot>,
+    thumbnail_history: &BTreeMap<u64, HistoryEntry>,
+) -> Vec<u64> {
+    let mut ids: Vec<u64> = screenshots
+        .keys()
+        .copied()
+        .chain(
+            thumbnail_history
+                .values()
+                .filter(|entry| entry.full_image)
+                .map(|entry| entry.id),
+        )
+        .collect();
+    ids.sort_by_key(|id| Reverse(*id));
+    ids
+}
+
+/// Tauri command: Retrieves a full-resolution screenshot by the id from its `screenshot-taken` event.
+/// `include_data_url` embeds the image as a data URL, see `get_current_screenshot`.
+#[tauri::command]
+pub fn get_full_screenshot(
+    state: tauri::State<ScreenshotState>,
+    id: u64,
+    include_data_url: Option<bool>,
+) -> Option<ScreenshotPayload> {
+    state.payload(id, include_data_url.unwrap_or(false))
+}
+
+/// Tauri command: Replaces the capture masks and saves them to the settings file.
+/// Masks are in physical pixels relative to the screen identified by `screen_id`.
+#[tauri::command]
+pub fn set_capture_masks(
+    state: tauri::State<ScreenshotState>,
+    settings: tauri::State<SettingsState>,
+    masks: Vec<CaptureMask>,
+) -> Result<(), String> {
+    if masks
+        .iter()
+        .any(|mask| mask.rect.width == 0 || mask.rect.height == 0)
+    {
+        return Err("Masks must have a non-zero width and height".to_string());
+    }
+
+    settings.set(Settings {
+        capture_masks: masks.clone(),
+        ..settings.get()
+    })?;
+    *state.capture_masks.locked() = masks;
+    Ok(())
+}
+
+/// Tauri command: Removes all capture masks
+#[tauri::command]
+pub fn clear_capture_masks(
+    state: tauri::State<ScreenshotState>,
+    settings: tauri::State<SettingsState>,
+) -> Result<(), String> {
+    set_capture_masks(state, settings, Vec::new())
+}
+
+/// Tauri command: Lists the connected screens in `Screen::all()` order.
+/// Returns an empty list on headless systems instead of an error.
+#[tauri::command]
+pub fn list_screens() -> Vec<ScreenInfo> {
+    Screen::all()
+        .map(|screens| screens.iter().map(ScreenInfo::from).collect())
+        .unwrap_or_default()
+}
+
+/// Creates and displays the preview window at the bottom-right corner of the primary monitor
+fn create_preview_window(app: &AppHandle) -> Result<(), String> {
+    // Get primary monitor size for positioning
+    let (x, y) = if let Some(monitor) = app.primary_monitor().ok().flatten() {
+        let size = monitor.size();
+        let position = monitor.position();
+        (
+            position.x as f64 + size.width as f64 - 320.0 - 20.0,
+            position.y as f64 + size.height as f64 - 200.0 - 60.0,
+        )
+    } else {
+        (1580.0, 820.0) // Default position
+    };
+
+    // Create preview window
+    let _preview_window = WebviewWindowBuilder::new(
+        app,
+        "preview",
+        WebviewUrl::App("index.html?mode=preview".into()),
+    )
+    .title("Screenshot Preview")
+    .inner_size(300.0, 180.0)
+    .position(x, y)
+    .decorations(false)
+    .always_on_top(true)
+    .resizable(false)
+    .skip_taskbar(true)
+    .build()
+    .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Shows the preview window, closing any existing preview window first
+pub fn show_preview_window(app: &AppHandle) -> Result<(), String> {
+    // Close existing preview window if any
+    if let Some(window) = app.get_webview_window("preview") {
+        let _ = window.close();
+    }
+
+    create_preview_window(app)
+}
+
+/// Tauri command: Hides/closes the preview window
+#[tauri::command]
+pub fn hide_preview_window(app: AppHandle) -> Result<(), String> {
+    if let Some(window) = app.get_webview_window("preview") {
+        window.close().map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Tauri command: Imports an image file as the current screenshot, as if it
+/// had just been captured, and opens it in the editor. Images over the
+/// `max_import_megapixels` setting are rejected before decoding.
+#[tauri::command(async)]
+pub fn load_screenshot_from_file(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    path: String,
+    format: Option<OutputFormat>,
+) -> Result<ScreenshotPayload, String> {
+    let open = || {
+        ImageReader::open(&path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|e| format!("Could not read {}: {}", path, e))
+    };
+
+    let reader = open()?;
+    if reader.format().is_none() {
+        return Err(format!("{} is not a supported image format", path));
+    }
+    let (width, height) = reader
+        .into_dimensions()
+        .map_err(|e| format!("{} is not a valid image: {}", path, e))?;
+    let max_megapixels = app.state::<SettingsState>().get().max_import_megapixels;
+    if width as u64 * height as u64 > max_megapixels as u64 * 1_000_000 {
+        return Err(format!(
+            "{} is {}x{} pixels, larger than the {} megapixel import limit",
+            path, width, height, max_megapixels
+        ));
+    }
+    let image = open()?
+        .decode()
+        .map_err(|e| format!("{} is corrupt or unsupported: {}", path, e))?
+        .to_rgba8();
+
+    let (data_url, payload) = publish_capture(
+        &app,
+        &state,
+        &image,
+        None,
+        unix_millis(),
+        format.unwrap_or_default(),
+        "imported",
+        CaptureSource::Imported { path: path.clone() },
+        TriggerSource::Ipc,
+    )?;
+    show_editor_window(app)?;
+    Ok(ScreenshotPayload {
+        data_url: Some(data_url),
+        ..payload
+    })
+}
+
+/// Tauri command: Shows the editor window
+#[tauri::command]
+pub fn show_editor_window(app: AppHandle) -> Result<(), String> {
+    if let Some(window) = app.get_webview_window("main") {
+        window.show().map_err(|e| e.to_string())?;
+        window.set_focus().map_err(|e| e.to_string())?;
+        mark_viewed(&app);
+    }
+    Ok(())
+}
+
+/// Tauri command: Retrieves whether automatic behaviors are paused, see `set_paused`
+#[tauri::command]
+pub fn get_paused(state: tauri::State<ScreenshotState>) -> bool {
+    state.paused.load(Ordering::SeqCst)
+}
+
+/// Tauri command: Pauses or resumes automatic behaviors. While paused,
+/// captures still work but are only kept in memory, without auto-save, the
+/// after-capture copy, the preview or being persisted; they stay memory-only
+/// after resuming, see `StoredScreenshot::memory_only`. The tray icon is faded
+/// and `paused-changed` is emitted with the new value. Not persisted across restarts.
+#[tauri::command]
+pub fn set_paused(app: AppHandle, state: tauri::State<ScreenshotState>, paused: bool) {
+    if state.paused.swap(paused, Ordering::SeqCst) != paused {
+        app.emit("paused-changed", paused);
+    }
+    tray::set_paused(&app, paused);
+}
+
+/// Clears the count of captures not seen in the editor, and the tray badge
+pub(crate) fn mark_viewed(app: &AppHandle) {
+    if app
+        .state::<ScreenshotState>()
+        .unviewed
+        .swap(0, Ordering::SeqCst)
+        > 0
+    {
+        tray::refresh_tray_icon(app);
+    }
+}
+
+/// Tauri command: Captures a screenshot after a countdown of `seconds`.
+///
+/// Emits `screenshot-countdown` with the remaining seconds once per second,
+/// then captures and shows the preview like the global shortcut does, or emits
+/// `screenshot-error` if the capture fails. Starting a new delayed capture
+/// replaces any pending one.
+#[tauri::command]
+pub fn take_screenshot_delayed(app: AppHandle, state: tauri::State<ScreenshotState>, seconds: u32) {
+    let generation = state.countdown_generation.fetch_add(1, Ordering::SeqCst) + 1;
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app_handle.state::<ScreenshotState>();
+        let is_current = || state.countdown_generation.load(Ordering::SeqCst) == generation;
+
+        for remaining in (1..=seconds).rev() {
+            if !is_current() {
+                return;
+            }
+            app_handle.emit("screenshot-countdown", remaining);
+            std::thread::sleep(Duration::from_secs(1));
+        }
+
+        if !is_current() {
+            return;
+        }
+        tray::set_tray_state(&app_handle, TrayState::Capturing);
+        match run_capture(
+            &app_handle,
+            &state,
+            CaptureRequest::default(),
+            CaptureOptions::default(),
+        ) {
+            Ok(_) => {
+                tray::finish_tray_state(&app_handle);
+                show_capture_preview(&app_handle);
+            }
+            Err(error) => {
+                tray::set_tray_state(&app_handle, TrayState::Error);
+                app_handle.emit("screenshot-error", error);
+            }
+        }
+    });
+}
+
+/// Tauri command: Cancels a pending delayed capture, if any
+#[tauri::command]
+pub fn cancel_delayed_screenshot(state: tauri::State<ScreenshotState>) {
+    state.countdown_generation.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Shortest interval allowed between burst frames
+const MIN_BURST_INTERVAL_MS: u64 = 50;
+
+/// Payload emitted with the `screenshot-burst-progress` event after each frame
+#[derive(Clone, Serialize)]
+pub struct BurstProgress {
+    /// Index of the frame just captured, usable with `get_burst_frame`
+    pub index: usize,
+    pub count: u32,
+}
+
+/// Payload emitted with the `screenshot-burst-done` event
+#[derive(Clone, Serialize)]
+pub struct BurstDone {
+    /// Ids of all frames captured, usable with `get_burst_frame`
+    pub ids: Vec<usize>,
+    /// Whether the burst was cancelled before capturing every frame
+    pub cancelled: bool,
+    /// Frames that couldn't be captured or encoded, and were skipped
+    pub failed: u32,
+}
+
+/// Tauri command: Captures `count` full-screen frames spaced `interval_ms` apart.
+///
+/// Runs on a background thread, emitting `screenshot-burst-progress` after each
+/// frame and `screenshot-burst-done` at the end. A frame that fails is skipped
+/// and counted in `BurstDone::failed`. Frames replace those of the previous
+/// burst, and starting a new burst cancels one still in flight.
+#[tauri::command]
+pub fn take_screenshot_burst(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    count: u32,
+    interval_ms: u64,
+) -> Result<(), String> {
+    if count == 0 {
+        return Err("Burst count must be at least 1".to_string());
+    }
+    if interval_ms < MIN_BURST_INTERVAL_MS {
+        return Err(format!(
+            "Burst interval must be at least {}ms",
+            MIN_BURST_INTERVAL_MS
+        ));
+    }
+
+    let generation = state.burst_generation.fetch_add(1, Ordering::SeqCst) + 1;
+    state.burst_frames.locked().clear();
+
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app_handle.state::<ScreenshotState>();
+        let is_current = || state.burst_generation.load(Ordering::SeqCst) == generation;
+
+        let mut ids = Vec::new();
+        let mut failed = 0;
+        for frame in 0..count {
+            if frame > 0 {
+                std::thread::sleep(Duration::from_millis(interval_ms));
+            }
+            if !is_current() {
+                break;
+            }
+
+            let captured = {
+                let _capturing = state.capture_lock.locked();
+                execute_capture(&app_handle, CaptureRequest::default())
+            };
+            let data_url = captured.and_then(|captured| {
+                let options = settings_encode_options(&app_handle);
+                encode_data_url(&captured.image, OutputFormat::Png, &options)
+            });
+            let data_url = match data_url {
+                Ok(data_url) => data_url,
+                Err(message) => {
+                    eprintln!("Skipping burst frame {}: {}", frame + 1, message);
+                    failed += 1;
+                    continue;
+                }
+            };
+
+            // Checked again under the lock, as a new burst clears the frames
+            // once it's started
+            let mut frames = state.burst_frames.locked();
+            if !is_current() {
+                break;
+            }
+            let index = frames.len();
+            frames.push(data_url);
+            drop(frames);
+
+            ids.push(index);
+            app_handle.emit("screenshot-burst-progress", BurstProgress { index, count });
+        }
+
+        app_handle.emit(
+            "screenshot-burst-done",
+            BurstDone {
+                cancelled: !is_current(),
+                ids,
+                failed,
+            },
+        );
+    });
+
+    Ok(())
+}
+
+/// Tauri command: Cancels an in-flight burst; frames captured so far are kept
+#[tauri::command]
+pub fn cancel_screenshot_burst(state: tauri::State<ScreenshotState>) {
+    state.burst_generation.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Tauri command: Retrieves a frame of the most recent burst by id
+#[tauri::command]
+pub fn get_burst_frame(state: tauri::State<ScreenshotState>, id: usize) -> Option<String> {
+    state.burst_frames.locked().get(id).cloned()
+}
+
+/// Tauri command: Freezes the screen under the cursor and opens the region selection overlay on it.
+///
+/// The frame stays in memory so the selection is cropped from exactly what the
+/// user saw, even if the screen content changes while they drag. `scale`,
+/// `format` and `post_process` apply to the selection once it's confirmed.
+#[tauri::command(async)]
+pub fn start_frozen_capture(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    scale: Option<CaptureScale>,
+    format: Option<OutputFormat>,
+    post_process: Option<PostProcess>,
+) -> Result<(), String> {
+    freeze_screen(&app, &state, output_options(scale, format, post_process))
+}
+
+/// Freezes the screen under the cursor for a region capture with `options`
+/// and opens the selection overlay, see `start_frozen_capture`
+fn freeze_screen(
+    app: &AppHandle,
+    state: &ScreenshotState,
+    options: CaptureOptions,
+) -> Result<(), String> {
+    if options.pre_capture_delay_ms > 0 {
+        std::thread::sleep(Duration::from_millis(options.pre_capture_delay_ms));
+    }
+    let (image, screen_index) = {
+        let _capturing = state.capture_lock.locked();
+        let hidden = if options.hide_own_windows {
+            hide_app_windows(app)
+        } else {
+            Vec::new()
+        };
+        let result = capture_full_screen(app, CaptureMode::Cursor);
+        restore_windows(&hidden);
+        result?
+    };
+    let captured_at = unix_millis();
+    let screens = Screen::all().map_err(|e| e.to_string())?;
+    let info = screens
+        .get(screen_index)
+        .map(ScreenInfo::from)
+        .ok_or_else(|| "Frozen screen is no longer connected".to_string())?;
+
+    *state.frozen_frame.locked() = Some(FrozenFrame {
+        image: Arc::new(image),
+        screen_index,
+        bounds: Rect::from(&info),
+        scale_factor: info.scale_factor,
+        captured_at,
+        options,
+    });
+
+    show_overlay_window(app, Rect::from(&info))
+}
+
+/// Tauri command: Returns the frozen frame as a base64 data URL for the overlay to display
+#[tauri::command]
+pub fn get_frozen_frame(state: tauri::State<ScreenshotState>) -> Result<String, String> {
+    let image = state
+        .frozen_frame
+        .locked()
+        .as_ref()
+        .map(|frame| frame.image.clone())
+        .ok_or_else(|| "No frozen frame".to_string())?;
+    // Only shown while selecting, so favor speed
+    encode_data_url(&image, OutputFormat::Png, &EncodeOptions::fast())
+}
+
+/// Tauri command: Crops the frozen frame to the selection and publishes it as the current screenshot.
+///
+/// The selection is in logical overlay coordinates and is mapped to physical
+/// pixels with the screen's scale factor. The frozen frame is released afterwards.
+#[tauri::command(async)]
+pub fn confirm_frozen_selection(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+) -> Result<String, String> {
+    let frame = state
+        .frozen_frame
+        .locked()
+        .take()
+        .ok_or_else(|| "No frozen frame".to_string())?;
+    close_overlay_window(&app);
+
+    let scale = frame.scale_factor as f64;
+    let to_px = |value: f64| (value * scale).round().max(0.0) as u32;
+    let (image_width, image_height) = frame.image.dimensions();
+    let left = to_px(x).min(image_width);
+    let top = to_px(y).min(image_height);
+    let right = to_px(x + width).min(image_width);
+    let bottom = to_px(y + height).min(image_height);
+    if right <= left || bottom <= top {
+        return Err("Selection must have a non-zero width and height".to_string());
+    }
+
+    let cropped =
+        imageops::crop_imm(frame.image.as_ref(), left, top, right - left, bottom - top).to_image();
+    drop(frame.image);
+    let options = frame.options;
+    let image = match options.scale {
+        CaptureScale::Physical => cropped,
+        CaptureScale::Logical => to_logical_size(cropped, frame.scale_factor),
+    };
+    let image = match options.post_process {
+        Some(post_process) => postprocess::apply(image, post_process),
+        None => image,
+    };
+
+    // Record the selection in logical desktop coordinates so it can be repeated live
+    let rect = Rect {
+        x: frame.bounds.x + x.round() as i32,
+        y: frame.bounds.y + y.round() as i32,
+        width: width.round() as u32,
+        height: height.round() as u32,
+    };
+    *state.last_capture.locked() = Some((CaptureRequest::Region { rect }, options));
+
+    publish_capture(
+        &app,
+        &state,
+        &image,
+        Some(frame.screen_index),
+        frame.captured_at,
+        options.format,
+        "region",
+        CaptureSource::Region {
+            rect,
+            screen_id: screen_id_at(frame.screen_index),
+        },
+        options.trigger,
+    )
+    .map(|(data_url, _)| data_url)
+}
+
+/// Tauri command: Cancels region selection and releases the frozen frame
+#[tauri::command]
+pub fn cancel_frozen_capture(app: AppHandle, state: tauri::State<ScreenshotState>) {
+    state.frozen_frame.locked().take();
+    close_overlay_window(&app);
+}
+
+/// Opens the borderless selection overlay covering `bounds`
+fn show_overlay_window(app: &AppHandle, bounds: Rect) -> Result<(), String> {
+    close_overlay_window(app);
+
+    WebviewWindowBuilder::new(
+        app,
+        "overlay",
+        WebviewUrl::App("index.html?mode=overlay".into()),
+    )
+    .title("Select Region")
+    .position(bounds.x as f64, bounds.y as f64)
+    .inner_size(bounds.width as f64, bounds.height as f64)
+    .decorations(false)
+    .always_on_top(true)
+    .resizable(false)
+    .skip_taskbar(true)
+    .focused(true)
+    .build()
+    .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Closes the selection overlay if it is open
+fn close_overlay_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("overlay") {
+        let _ = window.close();
+    }
+}
+
+/// Result of a finished scrolling capture
+#[derive(Clone, Serialize)]
+pub struct ScrollingCapture {
+    pub data_url: String,
+    /// Number of frames that made it into the stitched image
+    pub frames_used: usize,
+    /// False when stitching stopped early because consecutive frames shared no overlap
+    pub complete: bool,
+}
+
+/// Tauri command (experimental): Starts a scrolling capture of `region` and captures its first frame.
+/// Replaces any scrolling capture already in progress.
+#[tauri::command(async)]
+pub fn start_scrolling_capture(
+    state: tauri::State<ScreenshotState>,
+    region: Rect,
+) -> Result<(), String> {
+    let (frame, screen_index, _) = {
+        let _capturing = state.capture_lock.locked();
+        capture_region(region, &state.capture_masks())?
+    };
+    let started_at = unix_millis();
+    *state.scroll_session.locked() = Some(ScrollSession {
+        region,
+        frames: vec![frame],
+        screen_index,
+        started_at,
+    });
+    Ok(())
+}
+
+/// Tauri command (experimental): Captures the scrolling region again. Returns the number of frames so far.
+#[tauri::command(async)]
+pub fn append_scroll_frame(state: tauri::State<ScreenshotState>) -> Result<usize, String> {
+    let region = state
+        .scroll_session
+        .locked()
+        .as_ref()
+        .map(|session| session.region)
+        .ok_or_else(|| "No scrolling capture in progress".to_string())?;
+
+    let (frame, _, _) = {
+        let _capturing = state.capture_lock.locked();
+        capture_region(region, &state.capture_masks())?
+    };
+    let mut session = state.scroll_session.locked();
+    let session = session
+        .as_mut()
+        .ok_or_else(|| "No scrolling capture in progress".to_string())?;
+    session.frames.push(frame);
+    Ok(session.frames.len())
+}
+
+/// Tauri command (experimental): Stitches the collected frames into one tall image and publishes it.
+///
+/// If two consecutive frames share no detectable overlap, the frames stitched
+/// up to that point are published and `complete` is false.
+#[tauri::command(async)]
+pub fn finish_scrolling_capture(
+    app: AppHandle,
+    state: tauri::State<ScreenshotState>,
+) -> Result<ScrollingCapture, String> {
+    let session = state
+        .scroll_session
+        .locked()
+        .take()
+        .ok_or_else(|| "No scrolling capture in progress".to_string())?;
+
+    let stitched = stitch::stitch_vertical(&session.frames)?;
+    drop(session.frames);
+
+    let (data_url, _) = publish_capture(
+        &app,
+        &state,
+        &stitched.image,
+        session.screen_index,
+        session.started_at,
+        OutputFormat::default(),
+        "scrolling",
+        CaptureSource::Region {
+            rect: session.region,
+            screen_id: session.screen_index.and_then(screen_id_at),
+        },
+        TriggerSource::Ipc,
+    )?;
+    Ok(ScrollingCapture {
+        data_url,
+        frames_used: stitched.frames_used,
+        complete: stitched.complete,
+    })
+}
+
+/// Labels of the app's own webview windows
+const OWN_WINDOW_LABELS: [&str; 2] = ["main", "preview"];
+
+/// Time given to the compositor to remove hidden windows from the screen
+const HIDE_SETTLE_DELAY: Duration = Duration::from_millis(150);
+
+/// Whether a triggered capture arriving while another is still running is
+/// dropped, emitting `screenshot-skipped`, rather than queued behind it
+const COALESCE_TRIGGERS: bool = true;
+
+/// Admits one triggered capture at a time
+#[derive(Default)]
+pub struct TriggerGate(AtomicBool);
+
+impl TriggerGate {
+    /// Enters the gate, returning false if a capture is already inside
+    pub fn try_enter(&self) -> bool {
+        !self.0.swap(true, Ordering::SeqCst)
+    }
+
+    /// Leaves the gate so the next trigger is admitted
+    pub fn leave(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    /// Whether a capture is inside the gate
+    pub fn is_busy(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Hides the app's visible windows so they don't end up in a capture.
+/// Returns the windows that were hidden so they can be restored afterwards.
+fn hide_app_windows(app: &AppHandle) -> Vec<WebviewWindow> {
+    let hidden: Vec<WebviewWindow> = OWN_WINDOW_LABELS
+        .iter()
+        .filter_map(|label| app.get_webview_window(label))
+        .filter(|window| window.is_visible().unwrap_or(false))
+        .filter(|window| window.hide().is_ok())
+        .collect();
+
+    if !hidden.is_empty() {
+        std::thread::sleep(HIDE_SETTLE_DELAY);
+    }
+    hidden
+}
+
+/// Shows windows previously hidden by `hide_app_windows`
+fn restore_windows(windows: &[WebviewWindow]) {
+    for window in windows {
+        let _ = window.show();
+    }
+}
+
+/// Triggers a screenshot capture in a background thread after the configured
+/// pre-capture delay. Used by the global shortcut and tray entry points.
+pub fn trigger_screenshot(app: &AppHandle, hide_own_windows: bool, trigger: TriggerSource) {
+    let options = CaptureOptions {
+        hide_own_windows,
+        trigger,
+        ..Default::default()
+    };
+    spawn_capture(
+        app,
+        CaptureRequest::default(),
+        with_configured_delay(app, options),
+    );
+}
+
+/// Triggers a capture like `trigger_screenshot` without showing the preview;
+/// `on_captured` gets the new screenshot's id instead
+pub fn trigger_screenshot_then<F>(app: &AppHandle, trigger: TriggerSource, on_captured: F)
+where
+    F: FnOnce(&AppHandle, u64) + Send + 'static,
+{
+    let options = CaptureOptions {
+        hide_own_windows: true,
+        trigger,
+        ..Default::default()
+    };
+    spawn_capture_then(
+        app,
+        CaptureRequest::default(),
+        with_configured_delay(app, options),
+        on_captured,
+    );
+}
+
+/// Overrides of the shortcut's behavior for `trigger_capture`
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TriggerOptions {
+    /// Delay before capturing, `None` for the `pre_capture_delay_ms` setting
+    pub delay_ms: Option<u64>,
+    /// Show the preview window once captured
+    pub show_preview: bool,
+}
+
+impl Default for TriggerOptions {
+    fn default() -> Self {
+        Self {
+            delay_ms: None,
+            show_preview: true,
+        }
+    }
+}
+
+/// Tauri command: Captures the screen exactly like the capture shortcut does,
+/// waiting the configured delay, hiding our own windows and showing the
+/// preview, so a capture button behaves like the hotkey. `options` can change
+/// the delay or skip the preview. Returns once the capture is started; its
+/// outcome is emitted as for the shortcut.
+#[tauri::command]
+pub fn trigger_capture(app: AppHandle, options: Option<TriggerOptions>) -> Result<(), String> {
+    let options = options.unwrap_or_default();
+    if options
+        .delay_ms
+        .is_some_and(|delay_ms| delay_ms > MAX_PRE_CAPTURE_DELAY_MS)
+    {
+        return Err(format!(
+            "Delay must be at most {}ms",
+            MAX_PRE_CAPTURE_DELAY_MS
+        ));
+    }
+
+    let mut capture_options = with_configured_delay(
+        &app,
+        CaptureOptions {
+            hide_own_windows: true,
+            ..Default::default()
+        },
+    );
+    if let Some(delay_ms) = options.delay_ms {
+        capture_options.pre_capture_delay_ms = delay_ms;
+    }
+    if options.show_preview {
+        spawn_capture(&app, CaptureRequest::default(), capture_options);
+    } else {
+        spawn_capture_then(&app, CaptureRequest::default(), capture_options, |_, _| {});
+    }
+    Ok(())
+}
+
+/// Triggers a capture of the screen at `index` in `Screen::all()`, keeping our
+/// own windows out of it. An index past the connected screens is emitted as
+/// `screenshot-error`.
+pub fn trigger_screen_capture(app: &AppHandle, index: usize, trigger: TriggerSource) {
+    let options = CaptureOptions {
+        hide_own_windows: true,
+        trigger,
+        ..Default::default()
+    };
+    spawn_capture(
+        app,
+        CaptureRequest::Screen { index },
+        with_configured_delay(app, options),
+    );
+}
+
+/// Triggers a capture that replays the most recent capture request
+pub fn trigger_repeat_capture(app: &AppHandle, trigger: TriggerSource) {
+    let (request, options) = app.state::<ScreenshotState>().last_capture();
+    let options = CaptureOptions {
+        hide_own_windows: true,
+        trigger,
+        ..options
+    };
+    spawn_capture(app, request, with_configured_delay(app, options));
+}
+
+/// Triggers a capture of the foreground window, keeping our own windows out of it
+pub fn trigger_active_window_capture(app: &AppHandle, trigger: TriggerSource) {
+    let options = CaptureOptions {
+        hide_own_windows: true,
+        trigger,
+        ..Default::default()
+    };
+    spawn_capture(
+        app,
+        CaptureRequest::ActiveWindow,
+        with_configured_delay(app, options),
+    );
+}
+
+/// Freezes the screen under the cursor and opens the region selection overlay
+/// on it, or emits `screenshot-error` if it can't be captured
+pub fn trigger_region_capture(app: &AppHandle, trigger: TriggerSource) {
+    let options = CaptureOptions {
+        hide_own_windows: true,
+        trigger,
+        ..Default::default()
+    };
+    let options = with_configured_delay(app, options);
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app_handle.state::<ScreenshotState>();
+        if let Err(message) = freeze_screen(&app_handle, &state, options) {
+            tray::set_tray_state(&app_handle, TrayState::Error);
+            app_handle.emit("screenshot-error", ScreenshotError::CaptureFailed(message));
+        }
+    });
+}
+
+/// Sets the pre-capture delay from settings. The delay allows key release and
+/// on-screen hotkey overlays to disappear.
+fn with_configured_delay(app: &AppHandle, options: CaptureOptions) -> CaptureOptions {
+    CaptureOptions {
+        pre_capture_delay_ms: app.state::<SettingsState>().get().pre_capture_delay_ms,
+        ..options
+    }
+}
+
+/// Runs `request` on the blocking thread pool and shows the preview unless
+/// paused, or emits `screenshot-error` if it fails. With `COALESCE_TRIGGERS`,
+/// does nothing but emit `screenshot-skipped` with the request's kind while an
+/// earlier triggered capture is still running.
+fn spawn_capture(app: &AppHandle, request: CaptureRequest, options: CaptureOptions) {
+    spawn_capture_then(app, request, options, |app, _id| show_capture_preview(app));
+}
+
+/// Shows the preview after a triggered capture, unless paused
+fn show_capture_preview(app: &AppHandle) {
+    if !app.state::<ScreenshotState>().paused.load(Ordering::SeqCst) {
+        let _ = show_preview_window(app);
+    }
+}
+
+/// Like `spawn_capture`, calling `on_captured` with the new screenshot's id
+/// instead of showing the preview
+fn spawn_capture_then<F>(
+    app: &AppHandle,
+    request: CaptureRequest,
+    options: CaptureOptions,
+    on_captured: F,
+) where
+    F: FnOnce(&AppHandle, u64) + Send + 'static,
+{
+    let state = app.state::<ScreenshotState>();
+    if COALESCE_TRIGGERS && !state.trigger_in_progress.try_enter() {
+        app.emit("screenshot-skipped", request.kind());
+        return;
+    }
+
+    tray::set_tray_state(app, TrayState::Capturing);
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app_handle.state::<ScreenshotState>();
+        match run_capture(&app_handle, &state, request, options) {
+            Ok(capture) => {
+                state.trigger_in_progress.leave();
+                tray::finish_tray_state(&app_handle);
+                on_captured(&app_handle, capture.payload.id);
+            }
+            Err(error) => {
+                state.trigger_in_progress.leave();
+                tray::set_tray_state(&app_handle, TrayState::Error);
+                app_handle.emit("screenshot-error", error);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(id: u32, x: i32, y: i32, is_primary: bool) -> ScreenInfo {
+        ScreenInfo {
+            id,
+            x,
+            y,
+            width: 1920,
+            height: 1080,
+            scale_factor: 1.0,
+            is_primary,
+        }
+    }
+
+    #[test]
+    fn primary_screen_at_origin() {
+        let screens = [screen(1, 1920, 0, false), screen(2, 0, 0, false)];
+        assert_eq!(select_primary_screen(&screens, Some((0, 0))), 1);
+    }
+
+    #[test]
+    fn primary_screen_at_secondary_position() {
+        let screens = [screen(1, 0, 0, true), screen(2, -1920, 0, false)];
+        assert_eq!(select_primary_screen(&screens, Some((-1920, 0))), 1);
+    }
+
+    #[test]
+    fn primary_screen_without_position_uses_flag() {
+        let screens = [screen(1, 0, 0, false), screen(2, 1920, 0, true)];
+        assert_eq!(select_primary_screen(&screens, None), 1);
+    }
+
+    #[test]
+    fn primary_screen_with_unmatched_position_falls_back() {
+        let flagged = [screen(1, 0, 0, false), screen(2, 1920, 0, true)];
+        assert_eq!(select_primary_screen(&flagged, Some((5000, 5000))), 1);
+        let unflagged = [screen(1, 0, 0, false), screen(2, 1920, 0, false)];
+        assert_eq!(select_primary_screen(&unflagged, Some((5000, 5000))), 0);
+        assert_eq!(select_primary_screen(&[], Some((0, 0))), 0);
+    }
+
+    #[test]
+    fn trigger_gate_admits_one_of_many_concurrent_triggers() {
+        let gate = Arc::new(TriggerGate::default());
+        let barrier = Arc::new(std::sync::Barrier::new(10));
+        let handles: Vec<_> = (0..10)
+            .map(|_| {
+                let gate = Arc::clone(&gate);
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    gate.try_enter()
+                })
+            })
+            .collect();
+        let admitted = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|&admitted| admitted)
+            .count();
+        assert_eq!(admitted, 1);
+        assert!(gate.is_busy());
+        assert!(!gate.try_enter());
+
+        gate.leave();
+        assert!(!gate.is_busy());
+        assert!(gate.try_enter());
+    }
+
+    #[test]
+    fn locked_recovers_poisoned_mutex() {
+        let mutex = Arc::new(Mutex::new(vec![1, 2]));
+        let poisoner = Arc::clone(&mutex);
+        let result = std::thread::spawn(move || {
+            let mut guard = poisoner.lock().unwrap();
+            guard.push(3);
+            panic!("poison the lock");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(mutex.is_poisoned());
+
+        let mut guard = mutex.locked();
+        assert_eq!(*guard, [1, 2, 3]);
+        guard.push(4);
+        drop(guard);
+        assert_eq!(*mutex.locked(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn current_screenshot_survives_poisoned_state() {
+        let state = ScreenshotState::new();
+        state.screenshots.locked().insert(1, stored(10, 8));
+        *state.current_id.locked() = Some(1);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _screenshots = state.screenshots.lock().unwrap();
+            let _current_id = state.current_id.lock().unwrap();
+            panic!("poison the screenshot state");
+        }));
+        assert!(result.is_err());
+        assert!(state.screenshots.is_poisoned());
+        assert!(state.current_id.is_poisoned());
+
+        let payload = state.current_payload(true).unwrap();
+        assert_eq!(payload.id, 1);
+        assert_eq!(payload.captured_at, 10);
+        assert!(payload.data_url.is_some());
+    }
+
+    /// Clipboard recording what was copied, failing every write when `fail` is set
+    #[derive(Default)]
+    struct MockClipboard {
+        fail: bool,
+        images: std::cell::RefCell<Vec<(u32, u32)>>,
+        paths: std::cell::RefCell<Vec<String>>,
+    }
+
+    impl CopyTarget for MockClipboard {
+        fn copy_image(&self, image: &RgbaImage) -> Result<(), String> {
+            if self.fail {
+                return Err("clipboard unavailable".to_string());
+            }
+            self.images.borrow_mut().push(image.dimensions());
+            Ok(())
+        }
+
+        fn copy_path(&self, path: &str) -> Result<(), String> {
+            if self.fail {
+                return Err("clipboard unavailable".to_string());
+            }
+            self.paths.borrow_mut().push(path.to_string());
+            Ok(())
+        }
+    }
+
+    fn saved() -> Result<String, SaveError> {
+        Ok("/shots/a.png".to_string())
+    }
+
+    #[test]
+    fn after_capture_copy_off_copies_nothing() {
+        let clipboard = MockClipboard::default();
+        let image = RgbaImage::new(4, 3);
+        let result = dispatch_after_capture_copy(&clipboard, AfterCaptureCopy::Off, &image, || {
+            panic!("the file isn't needed")
+        });
+        assert!(result.is_none());
+        assert!(clipboard.images.borrow().is_empty());
+        assert!(clipboard.paths.borrow().is_empty());
+    }
+
+    #[test]
+    fn after_capture_copy_image() {
+        let clipboard = MockClipboard::default();
+        let image = RgbaImage::new(4, 3);
+        let result =
+            dispatch_after_capture_copy(&clipboard, AfterCaptureCopy::Image, &image, || {
+                panic!("the file isn't needed")
+            });
+        assert!(matches!(result, Some(Ok(Activity::CopiedImage))));
+        assert_eq!(*clipboard.images.borrow(), [(4, 3)]);
+        assert!(clipboard.paths.borrow().is_empty());
+    }
+
+    #[test]
+    fn after_capture_copy_file_path() {
+        let clipboard = MockClipboard::default();
+        let image = RgbaImage::new(4, 3);
+        let result =
+            dispatch_after_capture_copy(&clipboard, AfterCaptureCopy::FilePath, &image, saved);
+        assert!(matches!(
+            result,
+            Some(Ok(Activity::CopiedPath { path })) if path == "/shots/a.png"
+        ));
+        assert_eq!(*clipboard.paths.borrow(), ["/shots/a.png"]);
+        assert!(clipboard.images.borrow().is_empty());
+    }
+
+    #[test]
+    fn after_capture_copy_file_path_not_saved() {
+        let clipboard = MockClipboard::default();
+        let image = RgbaImage::new(4, 3);
+        let result =
+            dispatch_after_capture_copy(&clipboard, AfterCaptureCopy::FilePath, &image, || {
+                Err(SaveError::DiskFull("disk full".to_string()))
+            });
+        assert!(matches!(
+            result,
+            Some(Err(AfterCaptureCopyError::Save(SaveError::DiskFull(_))))
+        ));
+        assert!(clipboard.paths.borrow().is_empty());
+    }
+
+    #[test]
+    fn after_capture_copy_clipboard_failure() {
+        let clipboard = MockClipboard {
+            fail: true,
+            ..Default::default()
+        };
+        let image = RgbaImage::new(4, 3);
+        for copy in [AfterCaptureCopy::Image, AfterCaptureCopy::FilePath] {
+            let result = dispatch_after_capture_copy(&clipboard, copy, &image, saved);
+            assert!(matches!(result, Some(Err(AfterCaptureCopyError::Copy(_)))));
+        }
+    }
+
+    fn rect(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn rect_intersection() {
+        let screen = rect(-1920, 0, 1920, 1080);
+        assert_eq!(
+            rect(-100, 500, 300, 1000).intersection(&screen),
+            Some(rect(-100, 500, 100, 580))
+        );
+        assert_eq!(rect(0, 0, 10, 10).intersection(&screen), None);
+        assert_eq!(
+            rect(-1920, 0, 1920, 1080).intersection(&screen),
+            Some(screen)
+        );
+    }
+
+    #[test]
+    fn composite_places_parts_by_position() {
+        let left = RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]));
+        let right = RgbaImage::from_pixel(2, 2, Rgba([0, 0, 255, 255]));
+        let image = composite(&[(rect(0, 0, 2, 2), left), (rect(2, 1, 2, 2), right)], 1.0);
+        assert_eq!(image.dimensions(), (4, 3));
+        assert_eq!(*image.get_pixel(1, 1), Rgba([255, 0, 0, 255]));
+        assert_eq!(*image.get_pixel(2, 1), Rgba([0, 0, 255, 255]));
+        assert_eq!(*image.get_pixel(3, 2), Rgba([0, 0, 255, 255]));
+        // Neither screen covers these corners
+        assert_eq!(*image.get_pixel(3, 0), Rgba([0, 0, 0, 0]));
+        assert_eq!(*image.get_pixel(0, 2), Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn composite_upscales_lower_dpi_parts() {
+        let hidpi = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+        let lodpi = RgbaImage::from_pixel(2, 2, Rgba([0, 0, 255, 255]));
+        let image = composite(&[(rect(0, 0, 2, 2), hidpi), (rect(2, 0, 2, 2), lodpi)], 2.0);
+        assert_eq!(image.dimensions(), (8, 4));
+        assert_eq!(*image.get_pixel(3, 3), Rgba([255, 0, 0, 255]));
+        assert_eq!(*image.get_pixel(4, 0), Rgba([0, 0, 255, 255]));
+        assert_eq!(*image.get_pixel(7, 3), Rgba([0, 0, 255, 255]));
+    }
+
+    /// A small image with varied colors and partial transparency
+    fn sample_image() -> RgbaImage {
+        RgbaImage::from_fn(13, 7, |x, y| {
+            Rgba([
+                (x * 19) as u8,
+                (y * 37) as u8,
+                (x * y * 5) as u8,
+                255 - (x * 9) as u8,
+            ])
+        })
+    }
+
+    fn encode(image: &RgbaImage, format: OutputFormat, options: &EncodeOptions) -> Vec<u8> {
+        let (bytes, mime) = encode_image(image, format, options).unwrap();
+        assert_eq!(mime, format.mime());
+        bytes
+    }
+
+    #[test]
+    fn lossless_webp_round_trips() {
+        let image = sample_image();
+        let bytes = encode(
+            &image,
+            OutputFormat::WebpLossless,
+            &EncodeOptions::default(),
+        );
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(&bytes[8..12], b"WEBP");
+        assert_eq!(decode_image(&bytes).unwrap(), image);
+    }
+
+    #[test]
+    fn lossy_webp_clamps_quality() {
+        let image = sample_image();
+        for quality in [0, 50, 255] {
+            let bytes = encode(
+                &image,
+                OutputFormat::Webp { quality },
+                &EncodeOptions::default(),
+            );
+            assert_eq!(&bytes[8..12], b"WEBP");
+            assert_eq!(
+                decode_image(&bytes).unwrap().dimensions(),
+                image.dimensions()
+            );
+        }
+    }
+
+    fn png_options(png_compression: PngCompression) -> EncodeOptions {
+        EncodeOptions {
+            png_compression,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn every_png_compression_round_trips() {
+        let image = sample_image();
+        for compression in [
+            PngCompression::Fast,
+            PngCompression::Default,
+            PngCompression::Best,
+        ] {
+            let bytes = encode(&image, OutputFormat::Png, &png_options(compression));
+            assert_eq!(decode_image(&bytes).unwrap(), image);
+        }
+    }
+
+    /// Reports the encode time of a 4K noise image at each PNG compression
+    /// level. Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn png_compression_encode_times() {
+        // xorshift, so the noise is the same on every run
+        let mut seed = 0x2545_f491_u32;
+        let image = RgbaImage::from_fn(3840, 2160, |_, _| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            Rgba(seed.to_le_bytes())
+        });
+        for compression in [
+            PngCompression::Fast,
+            PngCompression::Default,
+            PngCompression::Best,
+        ] {
+            let started = std::time::Instant::now();
+            let bytes = encode(&image, OutputFormat::Png, &png_options(compression));
+            println!(
+                "{:?}: {} ms, {} KB",
+                compression,
+                started.elapsed().as_millis(),
+                bytes.len() / 1024
+            );
+        }
+    }
+
+    #[test]
+    fn best_png_compression_is_no_larger_than_fast() {
+        let image = RgbaImage::from_fn(128, 128, |x, y| {
+            Rgba([(x / 8) as u8, (y / 4) as u8, 0, 255])
+        });
+        let fast = encode(
+            &image,
+            OutputFormat::Png,
+            &png_options(PngCompression::Fast),
+        );
+        let best = encode(
+            &image,
+            OutputFormat::Png,
+            &png_options(PngCompression::Best),
+        );
+        assert!(best.len() <= fast.len());
+    }
+
+    #[test]
+    fn raw_frame_layout() {
+        let image = sample_image();
+        let frame = encode_raw_frame(&image);
+        assert_eq!(frame.len(), 8 + 13 * 7 * 4);
+        assert_eq!(frame[..4], 13u32.to_le_bytes());
+        assert_eq!(frame[4..8], 7u32.to_le_bytes());
+        assert_eq!(&frame[8..], image.as_raw().as_slice());
+        // Rows run top to bottom: the second row starts after 13 pixels
+        let offset = 8 + 13 * 4;
+        assert_eq!(frame[offset..offset + 4], image.get_pixel(0, 1).0);
+    }
+
+    #[test]
+    fn tiff_round_trips() {
+        let image = sample_image();
+        let bytes = encode(&image, OutputFormat::Tiff, &EncodeOptions::default());
+        assert_eq!(decode_image(&bytes).unwrap(), image);
+    }
+
+    #[test]
+    fn bmp_flattens_alpha() {
+        let image = sample_image();
+        let bytes = encode(&image, OutputFormat::Bmp, &EncodeOptions::default());
+        assert_eq!(&bytes[..2], b"BM");
+        let decoded = decode_image(&bytes).unwrap();
+        let flattened = flatten_alpha(&image, AlphaBackground::White);
+        for (x, y, pixel) in decoded.enumerate_pixels() {
+            let Rgb([r, g, b]) = *flattened.get_pixel(x, y);
+            assert_eq!(*pixel, Rgba([r, g, b, 255]));
+        }
+    }
+
+    #[test]
+    fn output_format_extensions() {
+        for format in [
+            OutputFormat::Png,
+            OutputFormat::Jpeg { quality: 90 },
+            OutputFormat::WebpLossless,
+            OutputFormat::Bmp,
+            OutputFormat::Tiff,
+        ] {
+            assert_eq!(
+                OutputFormat::from_extension(format.extension()),
+                Some(format)
+            );
+        }
+        assert_eq!(
+            OutputFormat::from_extension("JPEG"),
+            Some(OutputFormat::Jpeg { quality: 90 })
+        );
+        assert_eq!(
+            OutputFormat::from_extension("tif"),
+            Some(OutputFormat::Tiff)
+        );
+        assert_eq!(OutputFormat::from_extension("gif"), None);
+        assert_eq!(OutputFormat::Bmp.mime(), "image/bmp");
+        assert_eq!(OutputFormat::Tiff.mime(), "image/tiff");
+    }
+
+    #[test]
+    fn file_types_cover_every_extension() {
+        for (name, extensions) in OutputFormat::FILE_TYPES {
+            let format = OutputFormat::from_extension(extensions[0]).unwrap();
+            assert_eq!(format.extension(), extensions[0], "{}", name);
+            for extension in *extensions {
+                assert_eq!(
+                    OutputFormat::from_extension(extension).map(|f| f.mime()),
+                    Some(format.mime())
+                );
+            }
+        }
+        assert_eq!(
+            OutputFormat::FILE_TYPES.len(),
+            if cfg!(feature = "avif") { 6 } else { 5 }
+        );
+    }
+
+    #[test]
+    fn flatten_alpha_blends_onto_background() {
+        let image =
+            RgbaImage::from_vec(3, 1, vec![10, 20, 30, 0, 200, 200, 200, 128, 1, 2, 3, 255])
+                .unwrap();
+        let white = flatten_alpha(&image, AlphaBackground::White);
+        assert_eq!(white.as_raw(), &[255, 255, 255, 227, 227, 227, 1, 2, 3]);
+        let black = flatten_alpha(&image, AlphaBackground::Black);
+        assert_eq!(black.as_raw(), &[0, 0, 0, 100, 100, 100, 1, 2, 3]);
+    }
+
+    #[test]
+    fn jpeg_uses_alpha_background() {
+        let transparent = RgbaImage::from_pixel(16, 16, Rgba([255, 0, 0, 0]));
+        let options = EncodeOptions {
+            alpha_background: AlphaBackground::Black,
+            ..Default::default()
+        };
+        let bytes = encode(&transparent, OutputFormat::Jpeg { quality: 90 }, &options);
+        let decoded = decode_image(&bytes).unwrap();
+        assert!(decoded
+            .pixels()
+            .all(|&Rgba([r, g, b, a])| r.max(g).max(b) <= 2 && a == 255));
+    }
+
+    #[test]
+    fn interlaced_png_round_trips() {
+        let image = sample_image();
+        let options = EncodeOptions {
+            interlaced: true,
+            ..Default::default()
+        };
+        let bytes = encode(&image, OutputFormat::Png, &options);
+        // Interlace method byte of IHDR, after the signature, chunk header and 12 bytes
+        assert_eq!(bytes[28], 1);
+        assert_eq!(decode_image(&bytes).unwrap(), image);
+    }
+
+    fn has_chunk(png: &[u8], name: &[u8; 4]) -> bool {
+        png.windows(4).any(|window| window == name)
+    }
+
+    #[test]
+    fn deterministic_png_ignores_metadata_and_compression() {
+        let image = sample_image();
+        let metadata = CaptureMetadata {
+            captured_at: Some("2024-01-01T00:00:00+00:00".to_string()),
+            software: Some("test".to_string()),
+            ..Default::default()
+        };
+        let first = EncodeOptions {
+            png_compression: PngCompression::Best,
+            metadata: Some(metadata),
+            color_profile: ColorProfile::Srgb,
+            deterministic: true,
+            ..Default::default()
+        };
+        let second = EncodeOptions {
+            png_compression: PngCompression::Fast,
+            deterministic: true,
+            ..Default::default()
+        };
+        let bytes = encode(&image, OutputFormat::Png, &first);
+        assert_eq!(bytes, encode(&image, OutputFormat::Png, &second));
+        assert!(!has_chunk(&bytes, b"tEXt"));
+        assert!(!has_chunk(&bytes, b"sRGB"));
+        assert_eq!(decode_image(&bytes).unwrap(), image);
+    }
+
+    #[test]
+    fn png_metadata_written_unless_deterministic() {
+        let options = EncodeOptions {
+            metadata: Some(CaptureMetadata {
+                capture_mode: Some("region".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let bytes = encode(&sample_image(), OutputFormat::Png, &options);
+        assert!(has_chunk(&bytes, b"tEXt"));
+    }
+
+    #[test]
+    fn png_color_profile_chunks() {
+        let image = sample_image();
+        let with = |color_profile: ColorProfile| {
+            let options = EncodeOptions {
+                color_profile,
+                display_id: Some(1),
+                ..Default::default()
+            };
+            encode(&image, OutputFormat::Png, &options)
+        };
+        let srgb = with(ColorProfile::Srgb);
+        assert!(has_chunk(&srgb, b"sRGB"));
+        assert!(!has_chunk(&srgb, b"iCCP"));
+        let none = with(ColorProfile::None);
+        assert!(!has_chunk(&none, b"sRGB"));
+        assert!(!has_chunk(&none, b"iCCP"));
+        // Either the display's profile or the sRGB fallback
+        let auto = with(ColorProfile::Auto);
+        assert!(has_chunk(&auto, b"sRGB") != has_chunk(&auto, b"iCCP"));
+        assert_eq!(decode_image(&auto).unwrap(), image);
+    }
+
+    fn stored(captured_at: u64, size: usize) -> StoredScreenshot {
+        StoredScreenshot {
+            bytes: Arc::new(vec![0; size]),
+            format: OutputFormat::Png,
+            width: 1,
+            height: 1,
+            screen_id: None,
+            captured_at,
+            thumbnail_data_url: String::new(),
+            screen_index: None,
+            capture_mode: "fullScreen".to_string(),
+            saved_path: None,
+            pinned: false,
+            promoted_at: None,
+            label: None,
+            source: None,
+            trigger: None,
+            memory_only: false,
+        }
+    }
+
+    /// Screenshots 1 to 4, captured in that order
+    fn four_screenshots(size: usize) -> BTreeMap<u64, StoredScreenshot> {
+        (1..=4).map(|id| (id, stored(id * 10, size))).collect()
+    }
+
+    fn history_settings(history_size: usize, budget_mb: Option<u64>) -> Settings {
+        Settings {
+            history_size,
+            history_thumbnails_kept: 10,
+            history_memory_budget_mb: budget_mb,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn trim_keeps_newest_full_images() {
+        let mut screenshots = four_screenshots(1);
+        let mut thumbnails = BTreeMap::new();
+        let trimmed = trim_entries(
+            &mut screenshots,
+            &mut thumbnails,
+            &history_settings(2, None),
+            None,
+        );
+        assert_eq!(
+            trimmed.images,
+            [(2, OutputFormat::Png), (1, OutputFormat::Png)]
+        );
+        assert!(trimmed.removed.is_empty());
+        assert_eq!(screenshots.keys().copied().collect::<Vec<_>>(), [3, 4]);
+        assert_eq!(thumbnails.keys().copied().collect::<Vec<_>>(), [1, 2]);
+        assert!(thumbnails.values().all(|entry| !entry.full_image));
+    }
+
+    #[test]
+    fn trim_spares_promoted_and_pinned() {
+        let mut screenshots = four_screenshots(1);
+        screenshots.get_mut(&1).unwrap().promoted_at = Some(50);
+        screenshots.get_mut(&2).unwrap().pinned = true;
+        let mut thumbnails = BTreeMap::new();
+        let trimmed = trim_entries(
+            &mut screenshots,
+            &mut thumbnails,
+            &history_settings(2, None),
+            None,
+        );
+        assert_eq!(trimmed.images, [(3, OutputFormat::Png)]);
+        assert_eq!(screenshots.keys().copied().collect::<Vec<_>>(), [1, 2, 4]);
+    }
+
+    #[test]
+    fn trim_drops_thumbnails_over_limit() {
+        let mut screenshots = four_screenshots(1);
+        let mut thumbnails = BTreeMap::new();
+        let settings = Settings {
+            history_thumbnails_kept: 1,
+            ..history_settings(1, None)
+        };
+        let trimmed = trim_entries(&mut screenshots, &mut thumbnails, &settings, None);
+        assert_eq!(
+            trimmed.removed,
+            [(2, OutputFormat::Png), (1, OutputFormat::Png)]
+        );
+        assert_eq!(thumbnails.keys().copied().collect::<Vec<_>>(), [3]);
+    }
+
+    #[test]
+    fn trim_evicts_oldest_over_memory_budget() {
+        let mut screenshots = four_screenshots(400 * 1024);
+        let mut thumbnails = BTreeMap::new();
+        let trimmed = trim_entries(
+            &mut screenshots,
+            &mut thumbnails,
+            &history_settings(20, Some(1)),
+            Some(2),
+        );
+        // 1.6 MB over a 1 MB budget: the two oldest go, the newest never does
+        assert_eq!(screenshots.keys().copied().collect::<Vec<_>>(), [3, 4]);
+        assert_eq!(thumbnails.keys().copied().collect::<Vec<_>>(), [1, 2]);
+        assert!(thumbnails.values().all(|entry| entry.full_image));
+        assert_eq!(trimmed.evicted_current, Some(2));
+        assert!(trimmed.images.is_empty());
+        assert!(trimmed.unpersisted.is_empty());
+    }
+
+    #[test]
+    fn trim_over_memory_budget_without_persisting() {
+        let mut screenshots = four_screenshots(400 * 1024);
+        let mut thumbnails = BTreeMap::new();
+        let settings = Settings {
+            persist_history: false,
+            ..history_settings(20, Some(1))
+        };
+        let trimmed = trim_entries(&mut screenshots, &mut thumbnails, &settings, Some(4));
+        assert_eq!(trimmed.unpersisted, [1, 2]);
+        assert_eq!(trimmed.evicted_current, None);
+        assert!(thumbnails.values().all(|entry| !entry.full_image));
+    }
+
+    #[test]
+    fn trim_over_memory_budget_drops_memory_only_images() {
+        let mut screenshots = four_screenshots(400 * 1024);
+        screenshots.get_mut(&1).unwrap().memory_only = true;
+        let mut thumbnails = BTreeMap::new();
+        let trimmed = trim_entries(
+            &mut screenshots,
+            &mut thumbnails,
+            &history_settings(20, Some(1)),
+            None,
+        );
+        // Captured while paused, so there's no file to read it back from
+        assert_eq!(trimmed.unpersisted, [1]);
+        assert!(!thumbnails[&1].full_image);
+        assert!(thumbnails[&1].memory_only);
+        assert!(thumbnails[&2].full_image);
+    }
+
+    #[test]
+    fn memory_budget_always_keeps_newest() {
+        let mut screenshots = four_screenshots(2 * 1024 * 1024);
+        let mut thumbnails = BTreeMap::new();
+        trim_entries(
+            &mut screenshots,
+            &mut thumbnails,
+            &history_settings(20, Some(1)),
+            None,
+        );
+        assert_eq!(screenshots.keys().copied().collect::<Vec<_>>(), [4]);
+    }
+
+    #[test]
+    fn memory_usage_counts_images_and_thumbnails() {
+        let screenshots = four_screenshots(100);
+        let thumbnails = BTreeMap::from([(
+            9,
+            HistoryEntry {
+                thumbnail_data_url: "data:x".to_string(),
+                ..stored(90, 0).history_entry(9)
+            },
+        )]);
+        let usage = MemoryUsage::of(&screenshots, &thumbnails);
+        assert_eq!(usage.full_image_bytes, 400);
+        assert_eq!(usage.thumbnail_bytes, 6);
+        assert_eq!(usage.total_bytes, 406);
+        assert_eq!(usage.full_images, 4);
+        assert_eq!(usage.history_count, 5);
+    }
+
+    #[test]
+    fn fallback_candidates_are_newest_first_with_image() {
+        let screenshots: BTreeMap<u64, StoredScreenshot> =
+            [(2, stored(20, 1)), (5, stored(50, 1))].into();
+        let entry = |id: u64, full_image: bool| HistoryEntry {
+            full_image,
+            ..stored(id * 10, 1).history_entry(id)
+        };
+        let thumbnails = BTreeMap::from([
+            (1, entry(1, true)),
+            (3, entry(3, false)),
+            (4, entry(4, true)),
+        ]);
+        assert_eq!(
+            with_image_newest_first(&screenshots, &thumbnails),
+            [5, 4, 2, 1]
+        );
+        assert!(with_image_newest_first(&BTreeMap::new(), &BTreeMap::new()).is_empty());
+    }
+
+    #[test]
+    fn capture_source_wire_shape() {
+        let cases = [
+            (
+                CaptureSource::FullScreen { screen_id: Some(2) },
+                serde_json::json!({ "type": "fullScreen", "screenId": 2 }),
+            ),
+            (
+                CaptureSource::FullScreen { screen_id: None },
+                serde_json::json!({ "type": "fullScreen", "screenId": null }),
+            ),
+            (
+                CaptureSource::Region {
+                    rect: Rect {
+                        x: -10,
+                        y: 20,
+                        width: 300,
+                        height: 200,
+                    },
+                    screen_id: Some(1),
+                },
+                serde_json::json!({
+                    "type": "region",
+                    "rect": { "x": -10, "y": 20, "width": 300, "height": 200 },
+                    "screenId": 1,
+                }),
+            ),
+            (
+                CaptureSource::Window {
+                    title: "Editor".to_string(),
+                },
+                serde_json::json!({ "type": "window", "title": "Editor" }),
+            ),
+            (
+                CaptureSource::Imported {
+                    path: "/shots/a.png".to_string(),
+                },
+                serde_json::json!({ "type": "imported", "path": "/shots/a.png" }),
+            ),
+            (
+                CaptureSource::Clipboard,
+                serde_json::json!({ "type": "clipboard" }),
+            ),
+        ];
+        for (source, json) in cases {
+            assert_eq!(serde_json::to_value(&source).unwrap(), json);
+            assert_eq!(
+                serde_json::from_value::<CaptureSource>(json).unwrap(),
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn trigger_source_wire_shape() {
+        for (trigger, name) in [
+            (TriggerSource::Hotkey, "hotkey"),
+            (TriggerSource::Tray, "tray"),
+            (TriggerSource::Retake, "retake"),
+            (TriggerSource::Ipc, "ipc"),
+        ] {
+            assert_eq!(serde_json::to_value(trigger).unwrap(), name);
+        }
+    }
 }
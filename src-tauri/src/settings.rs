@@ -0,0 +1,279 @@
+use crate::filename::{FilenameTemplate, DEFAULT_FILENAME_TEMPLATE};
+use crate::screenshot::{
+    AfterCaptureCopy, AlphaBackground, CaptureMask, ColorProfile, LockExt, PngCompression,
+};
+use crate::shortcuts::{self, ShortcutAction, ShortcutMap};
+use crate::storage::Retention;
+use crate::tray::{self, TrayClickAction};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+/// Name of the settings file inside the app config directory
+const SETTINGS_FILE: &str = "settings.json";
+
+/// Longest allowed delay before a shortcut or tray capture
+pub const MAX_PRE_CAPTURE_DELAY_MS: u64 = 5000;
+
+/// Longest allowed window for the second press of a double press
+pub const MAX_DOUBLE_PRESS_WINDOW_MS: u64 = 1000;
+
+/// Most full-resolution screenshots the history may keep in memory
+pub const MAX_HISTORY_SIZE: usize = 100;
+
+/// Smallest allowed longest edge of the preview thumbnail
+pub const MIN_THUMBNAIL_EDGE: u32 = 120;
+
+/// Automatic saving of every capture to a folder
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AutoSave {
+    pub enabled: bool,
+    /// Folder captures are saved to, `None` for `default_screenshot_dir`
+    pub directory: Option<PathBuf>,
+}
+
+/// User settings persisted as JSON in the app config directory
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    /// Delay before a shortcut or tray capture, giving key-press overlays time to disappear
+    pub pre_capture_delay_ms: u64,
+    /// Areas blacked out in every capture
+    pub capture_masks: Vec<CaptureMask>,
+    /// PNG compression level; `fast` cuts encode time on large displays
+    pub png_compression: PngCompression,
+    /// Embed the capture time, screen, app version and capture mode in PNG files
+    pub embed_metadata: bool,
+    /// Color transparency is flattened onto when saving as JPEG or BMP
+    pub alpha_background: AlphaBackground,
+    /// Write Adam7-interlaced PNGs, which render progressively but are larger
+    pub interlaced_png: bool,
+    /// Write byte-for-byte reproducible PNGs without metadata, for diffing captures
+    pub deterministic_png: bool,
+    /// Color profile tagged in PNG files, so wide-gamut captures display correctly elsewhere
+    pub color_profile: ColorProfile,
+    /// Longest edge of the thumbnail sent with `screenshot-taken`, in pixels
+    pub thumbnail_max_edge: u32,
+    /// Save every capture to a folder as soon as it's taken
+    pub auto_save: AutoSave,
+    /// Name of auto-saved files and default name in save dialogs, see `FilenameTemplate`
+    pub filename_template: String,
+    /// Folder the save dialog opens in, the one last saved to
+    pub last_save_dir: Option<PathBuf>,
+    /// Limits after which the oldest auto-saved files are deleted
+    pub retention: Retention,
+    /// Largest image `load_screenshot_from_file` imports, in megapixels
+    pub max_import_megapixels: u32,
+    /// What is put on the clipboard after every capture
+    pub after_capture_copy: AfterCaptureCopy,
+    /// Clear images the app copied from the clipboard after this many seconds,
+    /// unless something else was copied since
+    pub clipboard_clear_after_secs: Option<u64>,
+    /// Number of screenshots kept in memory at full resolution
+    pub history_size: usize,
+    /// Number of older screenshots still listed in the history by their
+    /// thumbnail after their full image was dropped
+    pub history_thumbnails_kept: usize,
+    /// Keep the history in the app data folder, so it survives restarts
+    pub persist_history: bool,
+    /// Number of screenshots that may be pinned, which `history_size` doesn't evict
+    pub max_pinned_screenshots: usize,
+    /// Memory the history's images may take, in megabytes. Over it, the oldest
+    /// full images are dropped from memory, see `trim_history`.
+    pub history_memory_budget_mb: Option<u64>,
+    /// Also append the activity log to a file in the app data folder, see `activity`
+    pub activity_log_file: bool,
+    /// Accelerator of each global shortcut, changed with `set_shortcut`
+    pub shortcuts: ShortcutMap,
+    /// Keep the shortcuts suspended after a restart, see `suspend_shortcuts`
+    pub shortcuts_suspended: bool,
+    /// Holding the region shortcut opens the selection overlay, and releasing
+    /// it captures the selection made meanwhile
+    pub push_to_capture: bool,
+    /// Capture a screenshot when the copy-last shortcut finds none to copy,
+    /// rather than reporting that there's nothing to copy
+    pub capture_before_copy: bool,
+    /// Action run instead of an action when its shortcut is pressed twice in
+    /// quick succession, e.g. `region` to `fullScreen`. Delays single presses
+    /// of those shortcuts by `double_press_window_ms`.
+    pub double_press: BTreeMap<ShortcutAction, ShortcutAction>,
+    /// Time the second press of a double press may follow the first
+    pub double_press_window_ms: u64,
+    /// What a left click on the tray icon does
+    pub tray_left_click_action: TrayClickAction,
+    /// What a middle click on the tray icon does
+    pub tray_middle_click_action: TrayClickAction,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            pre_capture_delay_ms: 100,
+            capture_masks: Vec::new(),
+            png_compression: PngCompression::default(),
+            embed_metadata: true,
+            alpha_background: AlphaBackground::default(),
+            interlaced_png: false,
+            deterministic_png: false,
+            color_profile: ColorProfile::default(),
+            thumbnail_max_edge: 600,
+            auto_save: AutoSave::default(),
+            filename_template: DEFAULT_FILENAME_TEMPLATE.to_string(),
+            last_save_dir: None,
+            retention: Retention::default(),
+            max_import_megapixels: 50,
+            after_capture_copy: AfterCaptureCopy::default(),
+            clipboard_clear_after_secs: None,
+            history_size: 20,
+            history_thumbnails_kept: 0,
+            persist_history: true,
+            max_pinned_screenshots: 20,
+            history_memory_budget_mb: Some(512),
+            activity_log_file: false,
+            shortcuts: shortcuts::default_shortcuts(),
+            shortcuts_suspended: false,
+            push_to_capture: false,
+            capture_before_copy: true,
+            double_press: BTreeMap::new(),
+            double_press_window_ms: 350,
+            tray_left_click_action: TrayClickAction::default(),
+            tray_middle_click_action: TrayClickAction::CopyCurrent,
+        }
+    }
+}
+
+impl Settings {
+    /// Checks that every value is within its allowed range
+    pub fn validate(&self) -> Result<(), String> {
+        if self.pre_capture_delay_ms > MAX_PRE_CAPTURE_DELAY_MS {
+            return Err(format!(
+                "Pre-capture delay must be at most {}ms",
+                MAX_PRE_CAPTURE_DELAY_MS
+            ));
+        }
+        if self.thumbnail_max_edge < MIN_THUMBNAIL_EDGE {
+            return Err(format!(
+                "Thumbnail size must be at least {}px",
+                MIN_THUMBNAIL_EDGE
+            ));
+        }
+        FilenameTemplate::parse(&self.filename_template)?;
+        self.retention.validate()?;
+        if self.max_import_megapixels == 0 {
+            return Err("Import limit must be at least 1 megapixel".to_string());
+        }
+        if !(1..=MAX_HISTORY_SIZE).contains(&self.history_size) {
+            return Err(format!(
+                "History size must be 1 to {} screenshots",
+                MAX_HISTORY_SIZE
+            ));
+        }
+        if self.max_pinned_screenshots > MAX_HISTORY_SIZE {
+            return Err(format!(
+                "At most {} screenshots can be pinned",
+                MAX_HISTORY_SIZE
+            ));
+        }
+        if self.history_memory_budget_mb == Some(0) {
+            return Err("History memory budget must be at least 1 MB".to_string());
+        }
+        if self.clipboard_clear_after_secs == Some(0) {
+            return Err("Clipboard clear timeout must be at least 1 second".to_string());
+        }
+        shortcuts::validate(&self.shortcuts)?;
+        if !(1..=MAX_DOUBLE_PRESS_WINDOW_MS).contains(&self.double_press_window_ms) {
+            return Err(format!(
+                "Double-press window must be 1 to {}ms",
+                MAX_DOUBLE_PRESS_WINDOW_MS
+            ));
+        }
+        if self
+            .double_press
+            .iter()
+            .any(|(action, alternate)| action == alternate)
+        {
+            return Err("A double press must run a different action".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Manages the settings loaded at startup and where they are saved
+pub struct SettingsState {
+    pub settings: Mutex<Settings>,
+    path: Option<PathBuf>,
+}
+
+impl SettingsState {
+    /// Loads settings from the app config directory, falling back to defaults
+    /// when the file is missing, unreadable or invalid
+    pub fn load(app: &AppHandle) -> Self {
+        let path = app
+            .path()
+            .app_config_dir()
+            .ok()
+            .map(|dir| dir.join(SETTINGS_FILE));
+        let settings = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|json| serde_json::from_str::<Settings>(&json).ok())
+            .filter(|settings| settings.validate().is_ok())
+            .unwrap_or_default();
+
+        Self {
+            settings: Mutex::new(settings),
+            path,
+        }
+    }
+
+    /// Returns a copy of the current settings
+    pub fn get(&self) -> Settings {
+        self.settings.locked().clone()
+    }
+
+    /// Validates, saves and applies new settings
+    pub fn set(&self, settings: Settings) -> Result<(), String> {
+        settings.validate()?;
+
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+            }
+            let json = serde_json::to_string_pretty(&settings).map_err(|e| e.to_string())?;
+            fs::write(path, json).map_err(|e| e.to_string())?;
+        }
+
+        *self.settings.locked() = settings;
+        Ok(())
+    }
+}
+
+/// Tauri command: Retrieves the current settings
+#[tauri::command]
+pub fn get_settings(state: tauri::State<SettingsState>) -> Settings {
+    state.get()
+}
+
+/// Tauri command: Validates and saves new settings. The shortcuts and their
+/// suspension are kept, they're only changed by the shortcut commands, which
+/// rebind them. A new tray click action applies to the existing tray icon.
+#[tauri::command]
+pub fn update_settings(
+    app: AppHandle,
+    state: tauri::State<SettingsState>,
+    mut settings: Settings,
+) -> Result<(), String> {
+    let current = state.get();
+    settings.shortcuts = current.shortcuts;
+    settings.shortcuts_suspended = current.shortcuts_suspended;
+    let tray_left_click_action = settings.tray_left_click_action;
+    state.set(settings)?;
+    if tray_left_click_action != current.tray_left_click_action {
+        tray::apply_left_click_action(&app, tray_left_click_action);
+    }
+    Ok(())
+}
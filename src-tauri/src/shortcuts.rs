@@ -0,0 +1,968 @@
+use crate::clipboard::copy_last_screenshot;
+use crate::screenshot::{
+    cancel_frozen_capture, trigger_active_window_capture, trigger_region_capture,
+    trigger_repeat_capture, trigger_screen_capture, trigger_screenshot, LockExt, TriggerSource,
+};
+use crate::settings::SettingsState;
+use crate::tray;
+use screenshots::Screen;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+
+/// Number of numbered screen shortcuts, `captureScreen1` to `captureScreen9`
+const MAX_SCREEN_SHORTCUTS: usize = 9;
+
+/// Something a global shortcut can be bound to. Serialized as its camelCase
+/// name, `captureScreen<number>` for the numbered screen captures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum ShortcutAction {
+    /// Capture the screen under the cursor
+    FullScreen,
+    /// Freeze the screen and select a region on it
+    Region,
+    /// Capture the foreground window
+    ActiveWindow,
+    /// Replay the most recent capture
+    RepeatCapture,
+    /// Copy the most recent screenshot to the clipboard
+    CopyLast,
+    /// Capture the screen with this number, counting from 1 in `list_screens` order
+    CaptureScreen(usize),
+}
+
+impl From<ShortcutAction> for String {
+    fn from(action: ShortcutAction) -> Self {
+        match action {
+            ShortcutAction::FullScreen => "fullScreen".to_string(),
+            ShortcutAction::Region => "region".to_string(),
+            ShortcutAction::ActiveWindow => "activeWindow".to_string(),
+            ShortcutAction::RepeatCapture => "repeatCapture".to_string(),
+            ShortcutAction::CopyLast => "copyLast".to_string(),
+            ShortcutAction::CaptureScreen(number) => format!("captureScreen{}", number),
+        }
+    }
+}
+
+impl TryFrom<String> for ShortcutAction {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        match name.as_str() {
+            "fullScreen" => Ok(Self::FullScreen),
+            "region" => Ok(Self::Region),
+            "activeWindow" => Ok(Self::ActiveWindow),
+            "repeatCapture" => Ok(Self::RepeatCapture),
+            "copyLast" => Ok(Self::CopyLast),
+            _ => name
+                .strip_prefix("captureScreen")
+                .and_then(|number| number.parse().ok())
+                .filter(|number| (1..=MAX_SCREEN_SHORTCUTS).contains(number))
+                .map(Self::CaptureScreen)
+                .ok_or_else(|| format!("Unknown shortcut action \"{}\"", name)),
+        }
+    }
+}
+
+impl ShortcutAction {
+    /// Name of the action in error messages
+    fn label(self) -> String {
+        match self {
+            Self::FullScreen => "full-screen capture".to_string(),
+            Self::Region => "region capture".to_string(),
+            Self::ActiveWindow => "active window capture".to_string(),
+            Self::RepeatCapture => "repeat last capture".to_string(),
+            Self::CopyLast => "copy last screenshot".to_string(),
+            Self::CaptureScreen(number) => format!("screen {} capture", number),
+        }
+    }
+
+    /// Whether this is the numbered capture of a screen that isn't connected,
+    /// whose shortcut is left unregistered
+    fn screen_missing(self, screen_count: usize) -> bool {
+        matches!(self, Self::CaptureScreen(number) if number > screen_count)
+    }
+
+    /// Runs the action for a press of its shortcut, keeping our own windows
+    /// out of any capture
+    fn run(self, app: &AppHandle) {
+        let trigger = TriggerSource::Hotkey;
+        match self {
+            Self::FullScreen => trigger_screenshot(app, true, trigger),
+            Self::Region => trigger_region_capture(app, trigger),
+            Self::ActiveWindow => trigger_active_window_capture(app, trigger),
+            Self::RepeatCapture => trigger_repeat_capture(app, trigger),
+            Self::CopyLast => copy_last_screenshot(app, trigger),
+            Self::CaptureScreen(number) => trigger_screen_capture(app, number - 1, trigger),
+        }
+    }
+}
+
+/// Accelerator of each action with a shortcut; actions missing from the map are unbound
+pub type ShortcutMap = BTreeMap<ShortcutAction, String>;
+
+/// Default accelerator of each action on macOS, following its Cmd+Shift+number
+/// screenshot convention after the system's own 3 to 6
+#[cfg(target_os = "macos")]
+const DEFAULT_SHORTCUTS: [(ShortcutAction, &str); 5] = [
+    (ShortcutAction::FullScreen, "cmd+shift+7"),
+    (ShortcutAction::Region, "cmd+shift+8"),
+    (ShortcutAction::ActiveWindow, "cmd+shift+9"),
+    (ShortcutAction::RepeatCapture, "cmd+alt+shift+7"),
+    (ShortcutAction::CopyLast, "cmd+alt+shift+c"),
+];
+
+/// Default accelerator of each action on other platforms
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_SHORTCUTS: [(ShortcutAction, &str); 5] = [
+    (ShortcutAction::FullScreen, "ctrl+alt+s"),
+    (ShortcutAction::Region, "ctrl+alt+r"),
+    (ShortcutAction::ActiveWindow, "ctrl+alt+w"),
+    (ShortcutAction::RepeatCapture, "ctrl+alt+shift+s"),
+    (ShortcutAction::CopyLast, "ctrl+alt+c"),
+];
+
+/// Modifiers of the numbered screen shortcuts on macOS, e.g. Cmd+Alt+1
+#[cfg(target_os = "macos")]
+const SCREEN_SHORTCUT_MODIFIERS: &str = "cmd+alt";
+
+/// Modifiers of the numbered screen shortcuts on other platforms, e.g. Ctrl+Alt+1
+#[cfg(not(target_os = "macos"))]
+const SCREEN_SHORTCUT_MODIFIERS: &str = "ctrl+alt";
+
+/// Accelerators tried in order when the full-screen shortcut can't be
+/// registered on startup, so there's still a capture hotkey
+#[cfg(target_os = "macos")]
+const CAPTURE_FALLBACKS: &[&str] = &["cmd+ctrl+shift+7", "cmd+alt+shift+0"];
+
+/// Accelerators tried in order when the full-screen shortcut can't be
+/// registered on startup, so there's still a capture hotkey
+#[cfg(not(target_os = "macos"))]
+const CAPTURE_FALLBACKS: &[&str] = &["ctrl+alt+shift+p", "ctrl+alt+f12"];
+
+/// Shortcuts until the user picks others: `DEFAULT_SHORTCUTS`, and a
+/// numbered shortcut per screen
+pub fn default_shortcuts() -> ShortcutMap {
+    let screens = (1..=MAX_SCREEN_SHORTCUTS).map(|number| {
+        (
+            ShortcutAction::CaptureScreen(number),
+            format!("{}+{}", SCREEN_SHORTCUT_MODIFIERS, number),
+        )
+    });
+    DEFAULT_SHORTCUTS
+        .iter()
+        .map(|(action, accelerator)| (*action, accelerator.to_string()))
+        .chain(screens)
+        .collect()
+}
+
+/// Number of screens connected now
+fn connected_screens() -> usize {
+    Screen::all().map_or(0, |screens| screens.len())
+}
+
+/// How long a push-to-capture session waits for the shortcut's release before
+/// it's cancelled, as the release never arrives when focus was lost meanwhile
+const PUSH_TO_CAPTURE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Whether a shortcut chosen in the settings is registered with the OS
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShortcutStatus {
+    /// Accelerator chosen in the settings
+    pub accelerator: String,
+    /// Accelerator actually registered: `accelerator`, a fallback when it was
+    /// unavailable, or `None` when nothing could be registered
+    pub active: Option<String>,
+    /// Why `accelerator` couldn't be registered
+    pub error: Option<String>,
+}
+
+impl ShortcutStatus {
+    /// Status of a shortcut registered as chosen
+    fn registered(accelerator: String) -> Self {
+        Self {
+            accelerator: accelerator.clone(),
+            active: Some(accelerator),
+            error: None,
+        }
+    }
+
+    /// Status of a shortcut deliberately left unregistered
+    fn unregistered(accelerator: String) -> Self {
+        Self {
+            accelerator,
+            active: None,
+            error: None,
+        }
+    }
+}
+
+/// Payload of `shortcut-registration-failed`
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RegistrationFailure {
+    action: ShortcutAction,
+    #[serde(flatten)]
+    status: ShortcutStatus,
+}
+
+/// Push-to-capture state: the region shortcut held down, with the selection
+/// overlay open until it's released. See `Settings::push_to_capture`.
+#[derive(Default)]
+struct PushSession {
+    /// Generation of the session while the shortcut is held
+    held: Option<u64>,
+    /// Generation the next session gets
+    next_generation: u64,
+}
+
+impl PushSession {
+    /// Starts a session when the shortcut is pressed and returns its
+    /// generation; `None` while one is held, as key repeat presses again
+    fn press(&mut self) -> Option<u64> {
+        if self.held.is_some() {
+            return None;
+        }
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        self.held = Some(generation);
+        Some(generation)
+    }
+
+    /// Ends the held session when the shortcut is released; whether there
+    /// was one, whose selection is to be captured
+    fn release(&mut self) -> bool {
+        self.held.take().is_some()
+    }
+
+    /// Ends session `generation` when its timeout expires; whether it was
+    /// still held, and so is to be cancelled
+    fn expire(&mut self, generation: u64) -> bool {
+        if self.held != Some(generation) {
+            return false;
+        }
+        self.held = None;
+        true
+    }
+}
+
+/// Double-press detection: presses of shortcuts with a double-press action,
+/// whose own action waits to see whether a second press follows.
+/// See `Settings::double_press`.
+#[derive(Default)]
+struct DoublePressDetector {
+    /// Time and generation of each action's first press still waiting
+    pending: BTreeMap<ShortcutAction, (Instant, u64)>,
+    /// Generation the next first press gets
+    next_generation: u64,
+}
+
+/// What a press detected by `DoublePressDetector` does
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Press {
+    /// A first press, whose action runs unless a second press follows before
+    /// its generation expires
+    First(u64),
+    /// The second press of a double press, which runs the double-press action
+    Double,
+}
+
+impl DoublePressDetector {
+    /// Records a press of `action`'s shortcut at `now`; it's the second of a
+    /// double press when a first one is waiting and at most `window` old
+    fn press(&mut self, action: ShortcutAction, now: Instant, window: Duration) -> Press {
+        if let Some((at, _)) = self.pending.remove(&action) {
+            if now.saturating_duration_since(at) <= window {
+                return Press::Double;
+            }
+        }
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        self.pending.insert(action, (now, generation));
+        Press::First(generation)
+    }
+
+    /// Ends first press `generation` of `action` when its window is over;
+    /// whether it's still waiting, and so its action is to be run
+    fn expire(&mut self, action: ShortcutAction, generation: u64) -> bool {
+        if self.pending.get(&action).map(|(_, pending)| *pending) != Some(generation) {
+            return false;
+        }
+        self.pending.remove(&action);
+        true
+    }
+}
+
+/// Shortcuts registered with the OS, by action
+pub struct ShortcutRegistry {
+    status: Mutex<BTreeMap<ShortcutAction, ShortcutStatus>>,
+    /// Whether `suspend_shortcuts` released every shortcut; `status` still
+    /// lists them so `resume_shortcuts` can register them again
+    suspended: AtomicBool,
+    push_session: Mutex<PushSession>,
+    double_press: Mutex<DoublePressDetector>,
+}
+
+impl ShortcutRegistry {
+    /// Creates a registry with nothing registered
+    pub fn new() -> Self {
+        Self {
+            status: Mutex::new(BTreeMap::new()),
+            suspended: AtomicBool::new(false),
+            push_session: Mutex::new(PushSession::default()),
+            double_press: Mutex::new(DoublePressDetector::default()),
+        }
+    }
+}
+
+/// Errors from changing a global shortcut. Serialized with a `kind` tag like
+/// `ScreenshotError`; every kind carries a `message` to show the user. The
+/// previous shortcut stays registered whenever one is returned.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "camelCase")]
+pub enum ShortcutError {
+    /// The accelerator string couldn't be parsed
+    InvalidAccelerator(String),
+    /// Another action is already bound to the accelerator
+    Conflict(String),
+    /// The OS refused the shortcut, usually because another application owns it
+    Unavailable(String),
+    /// The shortcut was registered but the settings couldn't be saved
+    Failed(String),
+}
+
+impl From<ShortcutError> for String {
+    fn from(error: ShortcutError) -> Self {
+        match error {
+            ShortcutError::InvalidAccelerator(message)
+            | ShortcutError::Conflict(message)
+            | ShortcutError::Unavailable(message)
+            | ShortcutError::Failed(message) => message,
+        }
+    }
+}
+
+/// Parses an accelerator like `ctrl+alt+s` or `cmd+shift+7`. `cmd`, `command`,
+/// `super`, `meta` and `win` all name the Command key on macOS and the
+/// Windows/Super key elsewhere; `cmdorctrl` is Command on macOS, Ctrl elsewhere.
+pub fn parse(accelerator: &str) -> Result<Shortcut, ShortcutError> {
+    let normalized = accelerator
+        .split('+')
+        .map(|token| match token.trim().to_lowercase().as_str() {
+            "meta" | "win" | "windows" => "super".to_string(),
+            _ => token.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("+");
+    Shortcut::from_str(&normalized).map_err(|e| {
+        ShortcutError::InvalidAccelerator(format!("Invalid shortcut \"{}\": {}", accelerator, e))
+    })
+}
+
+/// Action other than `action` that `shortcuts` binds to `shortcut`
+fn conflicting_action(
+    shortcuts: &ShortcutMap,
+    action: ShortcutAction,
+    shortcut: Shortcut,
+) -> Option<ShortcutAction> {
+    shortcuts
+        .iter()
+        .filter(|(other, _)| **other != action)
+        .find(|(_, accelerator)| parse(accelerator).is_ok_and(|other| other.id() == shortcut.id()))
+        .map(|(other, _)| *other)
+}
+
+/// Checks that every accelerator parses and that no two actions share one
+pub fn validate(shortcuts: &ShortcutMap) -> Result<(), ShortcutError> {
+    for (action, accelerator) in shortcuts {
+        let shortcut = parse(accelerator)?;
+        if let Some(other) = conflicting_action(shortcuts, *action, shortcut) {
+            return Err(ShortcutError::Conflict(format!(
+                "{} is used for both {} and {}",
+                accelerator,
+                action.label(),
+                other.label()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Registers `shortcut` to call `handler` with every press and release of
+/// it. Every shortcut is registered through here, most through `on_press`.
+fn on_key<F>(app: &AppHandle, shortcut: Shortcut, handler: F) -> Result<(), ShortcutError>
+where
+    F: Fn(&AppHandle, ShortcutState) + Send + Sync + 'static,
+{
+    app.global_shortcut()
+        .on_shortcut(shortcut, move |app, _shortcut, event| {
+            handler(app, event.state)
+        })
+        .map_err(|e| {
+            ShortcutError::Unavailable(format!("Shortcut {} is unavailable: {}", shortcut, e))
+        })
+}
+
+/// Registers `shortcut` to call `handler` once per key press. The plugin
+/// also reports key releases, on Windows for every chord, which are ignored
+/// so one press never fires twice.
+fn on_press<F>(app: &AppHandle, shortcut: Shortcut, handler: F) -> Result<(), ShortcutError>
+where
+    F: Fn(&AppHandle) + Send + Sync + 'static,
+{
+    on_key(app, shortcut, move |app, state| {
+        if state == ShortcutState::Pressed {
+            handler(app);
+        }
+    })
+}
+
+/// Registers `shortcut` to run `action`. The region shortcut also listens for
+/// releases, for push-to-capture.
+fn register(
+    app: &AppHandle,
+    action: ShortcutAction,
+    shortcut: Shortcut,
+) -> Result<(), ShortcutError> {
+    if action == ShortcutAction::Region {
+        return on_key(app, shortcut, |app, state| match state {
+            ShortcutState::Pressed if app.state::<SettingsState>().get().push_to_capture => {
+                push_pressed(app)
+            }
+            ShortcutState::Pressed => press(app, ShortcutAction::Region),
+            ShortcutState::Released => push_released(app),
+        });
+    }
+    on_press(app, shortcut, move |app| press(app, action))
+}
+
+/// Runs `action` for a press of its shortcut. With a double-press action set
+/// up for it, `action` is delayed by the double-press window instead, and a
+/// second press within it runs the double-press action in its place.
+fn press(app: &AppHandle, action: ShortcutAction) {
+    let settings = app.state::<SettingsState>().get();
+    let Some(&alternate) = settings.double_press.get(&action) else {
+        action.run(app);
+        return;
+    };
+    let window = Duration::from_millis(settings.double_press_window_ms);
+    let registry = app.state::<ShortcutRegistry>();
+    let press = registry
+        .double_press
+        .locked()
+        .press(action, Instant::now(), window);
+    let Press::First(generation) = press else {
+        alternate.run(app);
+        return;
+    };
+
+    let app_handle = app.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(window);
+        let registry = app_handle.state::<ShortcutRegistry>();
+        if registry.double_press.locked().expire(action, generation) {
+            action.run(&app_handle);
+        }
+    });
+}
+
+/// Starts a push-to-capture session, opening the selection overlay at once.
+/// It's cancelled, emitting `push-to-capture-cancelled`, if the shortcut isn't
+/// released within `PUSH_TO_CAPTURE_TIMEOUT`.
+fn push_pressed(app: &AppHandle) {
+    let Some(generation) = app
+        .state::<ShortcutRegistry>()
+        .push_session
+        .locked()
+        .press()
+    else {
+        return;
+    };
+    trigger_region_capture(app, TriggerSource::Hotkey);
+
+    let app_handle = app.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(PUSH_TO_CAPTURE_TIMEOUT);
+        let registry = app_handle.state::<ShortcutRegistry>();
+        if registry.push_session.locked().expire(generation) {
+            cancel_frozen_capture(app_handle.clone(), app_handle.state());
+            app_handle.emit("push-to-capture-cancelled", ());
+        }
+    });
+}
+
+/// Ends a push-to-capture session, emitting `push-to-capture-released` to the
+/// overlay so it captures its current selection
+fn push_released(app: &AppHandle) {
+    if app
+        .state::<ShortcutRegistry>()
+        .push_session
+        .locked()
+        .release()
+    {
+        app.emit_to("overlay", "push-to-capture-released", ());
+    }
+}
+
+/// Registers every shortcut in the settings. Run once on startup. A shortcut
+/// another application already owns is skipped, so startup and the other
+/// shortcuts still work; the full-screen capture tries `CAPTURE_FALLBACKS`
+/// instead. Each failure is emitted as `shortcut-registration-failed` and
+/// kept for `get_shortcut_status`. Nothing is registered while suspension was
+/// persisted by `suspend_shortcuts`.
+pub fn register_all(app: &AppHandle) {
+    let settings = app.state::<SettingsState>().get();
+    let registry = app.state::<ShortcutRegistry>();
+    let screen_count = connected_screens();
+    if settings.shortcuts_suspended {
+        registry.suspended.store(true, Ordering::SeqCst);
+        *registry.status.locked() = settings
+            .shortcuts
+            .into_iter()
+            .map(|(action, accelerator)| {
+                let status = if action.screen_missing(screen_count) {
+                    ShortcutStatus::unregistered(accelerator)
+                } else {
+                    ShortcutStatus::registered(accelerator)
+                };
+                (action, status)
+            })
+            .collect();
+        return;
+    }
+
+    let shortcuts = settings.shortcuts;
+    let mut statuses = BTreeMap::new();
+    for (&action, accelerator) in &shortcuts {
+        if action.screen_missing(screen_count) {
+            statuses.insert(action, ShortcutStatus::unregistered(accelerator.clone()));
+            continue;
+        }
+        let registered = parse(accelerator).and_then(|shortcut| register(app, action, shortcut));
+        let status = match registered {
+            Ok(()) => ShortcutStatus::registered(accelerator.clone()),
+            Err(error) => {
+                let error = String::from(error);
+                eprintln!(
+                    "Could not register the {} shortcut: {}",
+                    action.label(),
+                    error
+                );
+                let fallbacks: &[&str] = if action == ShortcutAction::FullScreen {
+                    CAPTURE_FALLBACKS
+                } else {
+                    &[]
+                };
+                let active = fallbacks
+                    .iter()
+                    .find(|fallback| {
+                        parse(fallback).is_ok_and(|shortcut| {
+                            conflicting_action(&shortcuts, action, shortcut).is_none()
+                                && register(app, action, shortcut).is_ok()
+                        })
+                    })
+                    .map(|fallback| fallback.to_string());
+                let status = ShortcutStatus {
+                    accelerator: accelerator.clone(),
+                    active,
+                    error: Some(error),
+                };
+                app.emit(
+                    "shortcut-registration-failed",
+                    RegistrationFailure {
+                        action,
+                        status: status.clone(),
+                    },
+                );
+                status
+            }
+        };
+        statuses.insert(action, status);
+    }
+    *registry.status.locked() = statuses;
+    tray::update_tray_tooltip(app, &app.state());
+}
+
+/// Accelerator registered for `action`, `None` when it has none or the
+/// shortcuts are suspended
+pub fn active_accelerator(app: &AppHandle, action: ShortcutAction) -> Option<String> {
+    let registry = app.state::<ShortcutRegistry>();
+    if registry.suspended.load(Ordering::SeqCst) {
+        return None;
+    }
+    let status = registry.status.locked();
+    status.get(&action)?.active.clone()
+}
+
+/// Releases every global shortcut, when quitting
+pub fn unregister_all(app: &AppHandle) {
+    if let Err(error) = app.global_shortcut().unregister_all() {
+        eprintln!("Could not unregister the shortcuts: {}", error);
+    }
+}
+
+/// Whether the shortcuts are suspended
+pub fn suspended(app: &AppHandle) -> bool {
+    app.state::<ShortcutRegistry>()
+        .suspended
+        .load(Ordering::SeqCst)
+}
+
+/// Releases every shortcut until `resume`, remembering them in the registry,
+/// and emits `shortcuts-suspended` with `true`. With `persist`, they also stay
+/// suspended after a restart.
+pub fn suspend(app: &AppHandle, persist: bool) -> Result<(), String> {
+    if persist {
+        let settings = app.state::<SettingsState>();
+        let mut new_settings = settings.get();
+        new_settings.shortcuts_suspended = true;
+        settings.set(new_settings)?;
+    }
+
+    let registry = app.state::<ShortcutRegistry>();
+    if registry.suspended.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    for status in registry.status.locked().values() {
+        if let Some(shortcut) = status
+            .active
+            .as_deref()
+            .and_then(|active| parse(active).ok())
+        {
+            let _ = app.global_shortcut().unregister(shortcut);
+        }
+    }
+    tray::set_shortcuts_suspended(app, true);
+    app.emit("shortcuts-suspended", true);
+    Ok(())
+}
+
+/// Registers the shortcuts released by `suspend` again and emits
+/// `shortcuts-suspended` with `false`. Does nothing when they aren't
+/// suspended. One that's been taken by another application meanwhile is
+/// emitted as `shortcut-registration-failed`.
+pub fn resume(app: &AppHandle) -> Result<(), String> {
+    let settings = app.state::<SettingsState>();
+    let mut new_settings = settings.get();
+    if new_settings.shortcuts_suspended {
+        new_settings.shortcuts_suspended = false;
+        settings.set(new_settings)?;
+    }
+
+    let registry = app.state::<ShortcutRegistry>();
+    if !registry.suspended.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+    for (&action, status) in registry.status.locked().iter_mut() {
+        let Some(active) = status.active.clone() else {
+            continue;
+        };
+        if let Err(error) = parse(&active).and_then(|shortcut| register(app, action, shortcut)) {
+            let error = String::from(error);
+            eprintln!(
+                "Could not register the {} shortcut: {}",
+                action.label(),
+                error
+            );
+            status.active = None;
+            status.error = Some(error);
+            app.emit(
+                "shortcut-registration-failed",
+                RegistrationFailure {
+                    action,
+                    status: status.clone(),
+                },
+            );
+        }
+    }
+    tray::set_shortcuts_suspended(app, false);
+    app.emit("shortcuts-suspended", false);
+    Ok(())
+}
+
+/// Tauri command: Releases every shortcut, e.g. while playing a game that
+/// uses the same keys, until `resume_shortcuts`. Suspension only lasts until
+/// the app quits, unless `persist` is set.
+#[tauri::command]
+pub fn suspend_shortcuts(app_handle: AppHandle, persist: Option<bool>) -> Result<(), String> {
+    suspend(&app_handle, persist.unwrap_or(false))
+}
+
+/// Tauri command: Registers the shortcuts released by `suspend_shortcuts`
+/// again; does nothing when they aren't suspended
+#[tauri::command]
+pub fn resume_shortcuts(app_handle: AppHandle) -> Result<(), String> {
+    resume(&app_handle)
+}
+
+/// Tauri command: Retrieves whether the shortcuts are suspended
+#[tauri::command]
+pub fn get_shortcuts_suspended(app_handle: AppHandle) -> bool {
+    suspended(&app_handle)
+}
+
+/// Tauri command: Registers the numbered shortcuts of the screens connected
+/// now and releases those of screens no longer connected. Run after the
+/// displays changed; only the numbered shortcuts of screens connected at
+/// startup are registered otherwise.
+#[tauri::command]
+pub fn refresh_screen_shortcuts(
+    app_handle: AppHandle,
+    settings: tauri::State<SettingsState>,
+    registry: tauri::State<ShortcutRegistry>,
+) {
+    let screen_count = connected_screens();
+    let suspended = registry.suspended.load(Ordering::SeqCst);
+    let mut statuses = registry.status.locked();
+    for (action, accelerator) in settings.get().shortcuts {
+        if !matches!(action, ShortcutAction::CaptureScreen(_)) {
+            continue;
+        }
+        let wanted = !action.screen_missing(screen_count);
+        let registered = statuses
+            .get(&action)
+            .is_some_and(|status| status.active.is_some());
+        if wanted == registered {
+            continue;
+        }
+
+        let status = if !wanted {
+            if !suspended {
+                if let Ok(shortcut) = parse(&accelerator) {
+                    let _ = app_handle.global_shortcut().unregister(shortcut);
+                }
+            }
+            ShortcutStatus::unregistered(accelerator)
+        } else if suspended {
+            ShortcutStatus::registered(accelerator)
+        } else {
+            let registered =
+                parse(&accelerator).and_then(|shortcut| register(&app_handle, action, shortcut));
+            match registered {
+                Ok(()) => ShortcutStatus::registered(accelerator),
+                Err(error) => {
+                    let error = String::from(error);
+                    eprintln!(
+                        "Could not register the {} shortcut: {}",
+                        action.label(),
+                        error
+                    );
+                    ShortcutStatus {
+                        accelerator,
+                        active: None,
+                        error: Some(error),
+                    }
+                }
+            }
+        };
+        statuses.insert(action, status);
+    }
+}
+
+/// Tauri command: Retrieves whether each shortcut in the settings is
+/// registered, so the UI can offer to rebind those that aren't
+#[tauri::command]
+pub fn get_shortcut_status(
+    registry: tauri::State<ShortcutRegistry>,
+) -> BTreeMap<ShortcutAction, ShortcutStatus> {
+    registry.status.locked().clone()
+}
+
+/// Tauri command: Retrieves the accelerator of each action with a shortcut
+#[tauri::command]
+pub fn get_shortcuts(settings: tauri::State<SettingsState>) -> ShortcutMap {
+    settings.get().shortcuts
+}
+
+/// Tauri command: Binds `action` to `accelerator`, or unbinds it with `None`,
+/// and saves it in the settings. An accelerator another action uses is
+/// rejected. The new shortcut is registered before the old one is released,
+/// so on any error the old one keeps working. While shortcuts are suspended
+/// it's only registered on resume.
+#[tauri::command]
+pub fn set_shortcut(
+    app_handle: AppHandle,
+    settings: tauri::State<SettingsState>,
+    registry: tauri::State<ShortcutRegistry>,
+    action: ShortcutAction,
+    accelerator: Option<String>,
+) -> Result<(), ShortcutError> {
+    let shortcut = accelerator.as_deref().map(parse).transpose()?;
+    let mut new_settings = settings.get();
+    if let (Some(shortcut), Some(accelerator)) = (shortcut, &accelerator) {
+        if let Some(other) = conflicting_action(&new_settings.shortcuts, action, shortcut) {
+            return Err(ShortcutError::Conflict(format!(
+                "{} is already the shortcut for {}",
+                accelerator,
+                other.label()
+            )));
+        }
+    }
+
+    let old = registry
+        .status
+        .locked()
+        .get(&action)
+        .and_then(|status| status.active.as_deref())
+        .and_then(|active| parse(active).ok());
+    let rebound = !registry.suspended.load(Ordering::SeqCst)
+        && shortcut.map(|shortcut| shortcut.id()) != old.map(|old| old.id());
+    if rebound {
+        if let Some(shortcut) = shortcut {
+            register(&app_handle, action, shortcut)?;
+        }
+    }
+
+    match &accelerator {
+        Some(accelerator) => new_settings.shortcuts.insert(action, accelerator.clone()),
+        None => new_settings.shortcuts.remove(&action),
+    };
+    if let Err(error) = settings.set(new_settings) {
+        if let Some(shortcut) = shortcut.filter(|_| rebound) {
+            let _ = app_handle.global_shortcut().unregister(shortcut);
+        }
+        return Err(ShortcutError::Failed(error));
+    }
+    if let Some(old) = old.filter(|_| rebound) {
+        let _ = app_handle.global_shortcut().unregister(old);
+    }
+
+    let mut status = registry.status.locked();
+    match accelerator {
+        Some(accelerator) => status.insert(action, ShortcutStatus::registered(accelerator)),
+        None => status.remove(&action),
+    };
+    drop(status);
+    tray::update_tray_tooltip(&app_handle, &app_handle.state());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_session_press_and_release() {
+        let mut session = PushSession::default();
+        assert_eq!(session.press(), Some(0));
+        // Key repeat while held doesn't start another session
+        assert_eq!(session.press(), None);
+        assert!(session.release());
+        assert!(!session.release());
+        assert_eq!(session.press(), Some(1));
+    }
+
+    #[test]
+    fn push_session_expires_only_while_held() {
+        let mut session = PushSession::default();
+        let first = session.press().unwrap();
+        assert!(session.expire(first));
+        assert!(!session.release());
+
+        // The timeout of an earlier session doesn't end a later one
+        let second = session.press().unwrap();
+        assert!(!session.expire(first));
+        assert!(session.release());
+        assert!(!session.expire(second));
+    }
+
+    const WINDOW: Duration = Duration::from_millis(350);
+
+    #[test]
+    fn second_press_within_window_is_double() {
+        let mut detector = DoublePressDetector::default();
+        let start = Instant::now();
+        let first = detector.press(ShortcutAction::Region, start, WINDOW);
+        assert_eq!(first, Press::First(0));
+        assert_eq!(
+            detector.press(ShortcutAction::Region, start + WINDOW, WINDOW),
+            Press::Double
+        );
+        // The double press cancelled the first press's action
+        assert!(!detector.expire(ShortcutAction::Region, 0));
+    }
+
+    #[test]
+    fn late_second_press_starts_over() {
+        let mut detector = DoublePressDetector::default();
+        let start = Instant::now();
+        detector.press(ShortcutAction::Region, start, WINDOW);
+        let late = start + WINDOW + Duration::from_millis(1);
+        assert_eq!(
+            detector.press(ShortcutAction::Region, late, WINDOW),
+            Press::First(1)
+        );
+        assert!(!detector.expire(ShortcutAction::Region, 0));
+        assert!(detector.expire(ShortcutAction::Region, 1));
+    }
+
+    #[test]
+    fn presses_of_other_actions_are_independent() {
+        let mut detector = DoublePressDetector::default();
+        let start = Instant::now();
+        detector.press(ShortcutAction::Region, start, WINDOW);
+        assert_eq!(
+            detector.press(ShortcutAction::FullScreen, start, WINDOW),
+            Press::First(1)
+        );
+        assert!(detector.expire(ShortcutAction::Region, 0));
+        assert!(!detector.expire(ShortcutAction::Region, 0));
+        assert!(detector.expire(ShortcutAction::FullScreen, 1));
+    }
+
+    #[test]
+    fn press_after_expiry_is_first() {
+        let mut detector = DoublePressDetector::default();
+        let start = Instant::now();
+        detector.press(ShortcutAction::CopyLast, start, WINDOW);
+        assert!(detector.expire(ShortcutAction::CopyLast, 0));
+        assert_eq!(
+            detector.press(ShortcutAction::CopyLast, start, WINDOW),
+            Press::First(1)
+        );
+    }
+
+    #[test]
+    fn shortcut_action_names_round_trip() {
+        let actions = [
+            ShortcutAction::FullScreen,
+            ShortcutAction::Region,
+            ShortcutAction::ActiveWindow,
+            ShortcutAction::RepeatCapture,
+            ShortcutAction::CopyLast,
+            ShortcutAction::CaptureScreen(1),
+            ShortcutAction::CaptureScreen(MAX_SCREEN_SHORTCUTS),
+        ];
+        for action in actions {
+            let name = String::from(action);
+            assert_eq!(ShortcutAction::try_from(name.clone()), Ok(action));
+            assert_eq!(serde_json::to_value(action).unwrap(), name.as_str());
+        }
+        assert_eq!(
+            String::from(ShortcutAction::CaptureScreen(3)),
+            "captureScreen3"
+        );
+    }
+
+    #[test]
+    fn unknown_shortcut_actions_are_rejected() {
+        for name in [
+            "",
+            "FullScreen",
+            "captureScreen",
+            "captureScreen0",
+            "captureScreen10",
+            "captureScreenx",
+        ] {
+            assert!(ShortcutAction::try_from(name.to_string()).is_err());
+        }
+        assert!(serde_json::from_str::<ShortcutAction>("\"screenshot\"").is_err());
+    }
+}
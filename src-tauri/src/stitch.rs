@@ -0,0 +1,178 @@
+use screenshots::image::{imageops, RgbaImage};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Fewest rows two frames must share for the overlap to count as detected
+const MIN_OVERLAP_ROWS: u32 = 8;
+
+/// Result of stitching scroll frames into one tall image
+pub struct Stitched {
+    pub image: RgbaImage,
+    /// Number of frames that made it into `image`
+    pub frames_used: usize,
+    /// False when stitching stopped early because two consecutive frames shared no overlap
+    pub complete: bool,
+}
+
+/// Stitches frames of a vertically scrolled region into one tall image.
+///
+/// Each frame is matched against the previous one by comparing row hashes:
+/// the largest run of rows at the bottom of the previous frame that equals the
+/// top of the next frame is treated as the overlap, and only the rows below it
+/// are appended. Frames identical to the previous one (no scroll) are skipped.
+/// If a frame shares no detectable overlap, stitching stops and the partial
+/// result is returned with `complete` unset.
+pub fn stitch_vertical(frames: &[RgbaImage]) -> Result<Stitched, String> {
+    let first = frames
+        .first()
+        .ok_or_else(|| "No frames to stitch".to_string())?;
+    if frames.iter().any(|frame| frame.width() != first.width()) {
+        return Err("All frames must have the same width".to_string());
+    }
+
+    let mut slices: Vec<(usize, u32)> = vec![(0, 0)];
+    let mut previous = row_hashes(first);
+    let mut complete = true;
+
+    for (index, frame) in frames.iter().enumerate().skip(1) {
+        let hashes = row_hashes(frame);
+        match find_overlap(&previous, &hashes) {
+            Some(overlap) if overlap as usize == hashes.len() => continue,
+            Some(overlap) => slices.push((index, overlap)),
+            None => {
+                complete = false;
+                break;
+            }
+        }
+        previous = hashes;
+    }
+
+    let height = slices
+        .iter()
+        .map(|(index, skip)| frames[*index].height() - skip)
+        .sum();
+    let mut image = RgbaImage::new(first.width(), height);
+    let mut y = 0;
+    for (index, skip) in &slices {
+        let frame = &frames[*index];
+        let rows = frame.height() - skip;
+        let slice = imageops::crop_imm(frame, 0, *skip, frame.width(), rows).to_image();
+        imageops::replace(&mut image, &slice, 0, y as i64);
+        y += rows;
+    }
+
+    Ok(Stitched {
+        image,
+        frames_used: slices.len(),
+        complete,
+    })
+}
+
+/// Hashes each row of an image
+fn row_hashes(image: &RgbaImage) -> Vec<u64> {
+    let row_len = image.width() as usize * 4;
+    image
+        .as_raw()
+        .chunks_exact(row_len)
+        .map(|row| {
+            let mut hasher = DefaultHasher::new();
+            row.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// Finds the largest number of rows at the end of `previous` that equal the
+/// start of `next`, or `None` if fewer than `MIN_OVERLAP_ROWS` match
+fn find_overlap(previous: &[u64], next: &[u64]) -> Option<u32> {
+    let max = previous.len().min(next.len());
+    (MIN_OVERLAP_ROWS as usize..=max)
+        .rev()
+        .find(|&rows| previous[previous.len() - rows..] == next[..rows])
+        .map(|rows| rows as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screenshots::image::Rgba;
+
+    /// Builds a frame whose rows are the given values, so each value is one distinct row
+    fn frame(rows: impl IntoIterator<Item = u8>) -> RgbaImage {
+        let rows: Vec<u8> = rows.into_iter().collect();
+        RgbaImage::from_fn(4, rows.len() as u32, |_, y| {
+            let value = rows[y as usize];
+            Rgba([value, value, value, 255])
+        })
+    }
+
+    fn rows_of(image: &RgbaImage) -> Vec<u8> {
+        (0..image.height())
+            .map(|y| image.get_pixel(0, y)[0])
+            .collect()
+    }
+
+    #[test]
+    fn appends_only_rows_below_overlap() {
+        let frames = [frame(0..20), frame(10..30)];
+        let stitched = stitch_vertical(&frames).unwrap();
+        assert!(stitched.complete);
+        assert_eq!(stitched.frames_used, 2);
+        assert_eq!(rows_of(&stitched.image), (0..30).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn skips_identical_frames() {
+        let frames = [frame(0..20), frame(0..20), frame(12..32)];
+        let stitched = stitch_vertical(&frames).unwrap();
+        assert!(stitched.complete);
+        assert_eq!(stitched.frames_used, 2);
+        assert_eq!(rows_of(&stitched.image), (0..32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn stops_when_frames_share_no_overlap() {
+        let frames = [frame(0..20), frame(100..120), frame(110..130)];
+        let stitched = stitch_vertical(&frames).unwrap();
+        assert!(!stitched.complete);
+        assert_eq!(stitched.frames_used, 1);
+        assert_eq!(rows_of(&stitched.image), (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn rejects_mismatched_widths() {
+        let frames = [frame(0..20), RgbaImage::new(5, 20)];
+        assert!(stitch_vertical(&frames).is_err());
+    }
+
+    #[test]
+    fn rejects_empty_input() {
+        assert!(stitch_vertical(&[]).is_err());
+    }
+
+    #[test]
+    fn frames_shorter_than_min_overlap_never_match() {
+        let short = MIN_OVERLAP_ROWS as u8 - 1;
+        let frames = [frame(0..short), frame(0..short)];
+        let stitched = stitch_vertical(&frames).unwrap();
+        assert!(!stitched.complete);
+        assert_eq!(stitched.frames_used, 1);
+    }
+
+    #[test]
+    fn find_overlap_prefers_largest_run() {
+        let previous: Vec<u64> = (0..20).collect();
+        let next: Vec<u64> = (5..25).collect();
+        assert_eq!(find_overlap(&previous, &next), Some(15));
+        assert_eq!(find_overlap(&previous, &previous), Some(20));
+        let disjoint: Vec<u64> = (100..120).collect();
+        assert_eq!(find_overlap(&previous, &disjoint), None);
+    }
+
+    #[test]
+    fn find_overlap_requires_min_rows() {
+        let previous: Vec<u64> = (0..20).collect();
+        let next: Vec<u64> = (20 - MIN_OVERLAP_ROWS as u64 + 1..40).collect();
+        assert_eq!(find_overlap(&previous, &next), None);
+    }
+}
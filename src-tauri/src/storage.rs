@@ -0,0 +1,362 @@
+use crate::filename::FilenameTemplate;
+use crate::save::auto_save_dir;
+use crate::screenshot::OutputFormat;
+use crate::settings::SettingsState;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Folder created for screenshots inside the pictures, home or app data folder
+const SCREENSHOTS_DIR: &str = "Screenshots";
+
+/// Seconds in a day, for `Retention::max_age_days`
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Limits on the auto-save folder. Each unset limit is not enforced; files
+/// over any limit are deleted, oldest first.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Retention {
+    /// Number of most recent files kept
+    pub max_files: Option<usize>,
+    /// Total size of kept files in megabytes
+    pub max_total_mb: Option<u64>,
+    /// Age in days after which files are deleted
+    pub max_age_days: Option<u64>,
+}
+
+impl Retention {
+    /// Checks that no limit is zero, which would delete every auto-saved file
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_files == Some(0) || self.max_total_mb == Some(0) || self.max_age_days == Some(0)
+        {
+            return Err("Retention limits must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Default folder screenshots are saved to: `Screenshots` in the user's
+/// pictures folder, falling back to the home folder and then the app data
+/// folder where there's none (e.g. Linux without XDG user dirs). The folder
+/// isn't created here, only when the first file is saved into it.
+pub fn default_screenshot_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let path = app.path();
+    screenshot_dir_in([
+        path.picture_dir().ok(),
+        path.home_dir().ok(),
+        path.app_data_dir().ok(),
+    ])
+}
+
+/// `Screenshots` in the first of `folders` that was found
+fn screenshot_dir_in(folders: [Option<PathBuf>; 3]) -> Result<PathBuf, String> {
+    folders
+        .into_iter()
+        .flatten()
+        .next()
+        .map(|dir| dir.join(SCREENSHOTS_DIR))
+        .ok_or_else(|| "No folder found for screenshots, choose one in the settings".to_string())
+}
+
+/// Tauri command: Retrieves the folder screenshots are saved to when none is configured
+#[tauri::command]
+pub fn get_default_save_dir(app: AppHandle) -> Result<String, String> {
+    default_screenshot_dir(&app).map(|dir| dir.to_string_lossy().into_owned())
+}
+
+/// An auto-saved file considered for deletion
+struct SavedCapture {
+    path: PathBuf,
+    modified: SystemTime,
+    size: u64,
+}
+
+/// Files in `dir` named by `template` with an image extension, newest first
+fn saved_captures(dir: &Path, template: &FilenameTemplate) -> Vec<SavedCapture> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut captures: Vec<SavedCapture> = entries
+        .flatten()
+        .filter(|entry| {
+            let path = entry.path();
+            let is_image = path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| OutputFormat::from_extension(extension).is_some());
+            let is_ours = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|stem| template.matches(stem));
+            is_image && is_ours
+        })
+        .filter_map(|entry| {
+            let metadata = entry
+                .metadata()
+                .ok()
+                .filter(|metadata| metadata.is_file())?;
+            Some(SavedCapture {
+                path: entry.path(),
+                modified: metadata.modified().ok()?,
+                size: metadata.len(),
+            })
+        })
+        .collect();
+    captures.sort_by_key(|capture| Reverse(capture.modified));
+    captures
+}
+
+/// Deletes auto-saved files over the retention limits and emits
+/// `screenshots-pruned` with the number deleted. Only files named by the
+/// current filename template are touched, and nothing happens while
+/// auto-save is off. Runs on startup and after every auto-save.
+pub fn enforce_retention(app: &AppHandle) {
+    let settings = app.state::<SettingsState>().get();
+    let retention = &settings.retention;
+    if !settings.auto_save.enabled
+        || (retention.max_files.is_none()
+            && retention.max_total_mb.is_none()
+            && retention.max_age_days.is_none())
+    {
+        return;
+    }
+    let (Ok(dir), Ok(template)) = (
+        auto_save_dir(app, &settings.auto_save),
+        FilenameTemplate::parse(&settings.filename_template),
+    ) else {
+        return;
+    };
+
+    let pruned = prune(
+        &saved_captures(&dir, &template),
+        retention,
+        SystemTime::now(),
+        |path| fs::remove_file(path).is_ok(),
+    );
+    if pruned > 0 {
+        app.emit("screenshots-pruned", pruned);
+    }
+}
+
+/// Deletes the `captures`, newest first, that are over the `retention`
+/// limits with `remove` and returns how many were deleted. A capture that
+/// `remove` fails on counts as kept.
+fn prune(
+    captures: &[SavedCapture],
+    retention: &Retention,
+    now: SystemTime,
+    mut remove: impl FnMut(&Path) -> bool,
+) -> usize {
+    let max_bytes = retention.max_total_mb.map(|mb| mb * 1024 * 1024);
+    let max_age = retention
+        .max_age_days
+        .map(|days| Duration::from_secs(days * SECONDS_PER_DAY));
+    let mut kept = 0;
+    let mut kept_bytes = 0;
+    let mut pruned = 0;
+
+    for capture in captures {
+        let too_many = retention.max_files.is_some_and(|max| kept >= max);
+        // The newest file is always kept, even if it alone exceeds the size limit
+        let too_large = kept > 0 && max_bytes.is_some_and(|max| kept_bytes + capture.size > max);
+        let too_old = max_age.is_some_and(|max| {
+            now.duration_since(capture.modified)
+                .is_ok_and(|age| age > max)
+        });
+
+        if (too_many || too_large || too_old) && remove(&capture.path) {
+            pruned += 1;
+        } else {
+            kept += 1;
+            kept_bytes += capture.size;
+        }
+    }
+    pruned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screenshot_dir_prefers_pictures() {
+        let pictures = PathBuf::from("/home/me/Pictures");
+        let home = PathBuf::from("/home/me");
+        assert_eq!(
+            screenshot_dir_in([Some(pictures.clone()), Some(home.clone()), None]),
+            Ok(pictures.join("Screenshots"))
+        );
+        assert_eq!(
+            screenshot_dir_in([None, Some(home.clone()), None]),
+            Ok(home.join("Screenshots"))
+        );
+        let data = PathBuf::from("/data/app");
+        assert_eq!(
+            screenshot_dir_in([None, None, Some(data.clone())]),
+            Ok(data.join("Screenshots"))
+        );
+    }
+
+    #[test]
+    fn screenshot_dir_without_any_folder() {
+        assert!(screenshot_dir_in([None, None, None]).is_err());
+    }
+
+    const MB: u64 = 1024 * 1024;
+
+    /// Captures named `0.png`, `1.png`, ... newest first, each a day older than the last
+    fn captures(now: SystemTime, sizes: &[u64]) -> Vec<SavedCapture> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(index, &size)| SavedCapture {
+                path: PathBuf::from(format!("{}.png", index)),
+                modified: now - Duration::from_secs(index as u64 * SECONDS_PER_DAY),
+                size,
+            })
+            .collect()
+    }
+
+    /// Names of the captures `prune` deletes
+    fn pruned(captures: &[SavedCapture], retention: &Retention, now: SystemTime) -> Vec<String> {
+        let mut removed = Vec::new();
+        let count = prune(captures, retention, now, |path| {
+            removed.push(path.display().to_string());
+            true
+        });
+        assert_eq!(count, removed.len());
+        removed
+    }
+
+    #[test]
+    fn prune_keeps_newest_files() {
+        let now = SystemTime::now();
+        let retention = Retention {
+            max_files: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            pruned(&captures(now, &[1, 1, 1, 1]), &retention, now),
+            ["2.png", "3.png"]
+        );
+    }
+
+    #[test]
+    fn prune_keeps_total_size_but_always_the_newest() {
+        let now = SystemTime::now();
+        let retention = Retention {
+            max_total_mb: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(
+            pruned(&captures(now, &[MB, 2 * MB, MB, MB]), &retention, now),
+            ["2.png", "3.png"]
+        );
+        assert_eq!(
+            pruned(&captures(now, &[5 * MB, MB]), &retention, now),
+            ["1.png"]
+        );
+    }
+
+    #[test]
+    fn prune_deletes_old_files() {
+        let now = SystemTime::now();
+        let retention = Retention {
+            max_age_days: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            pruned(&captures(now, &[1, 1, 1, 1]), &retention, now),
+            ["3.png"]
+        );
+    }
+
+    #[test]
+    fn prune_counts_failed_deletions_as_kept() {
+        let now = SystemTime::now();
+        let retention = Retention {
+            max_files: Some(1),
+            ..Default::default()
+        };
+        let mut attempts = Vec::new();
+        let count = prune(&captures(now, &[1, 1, 1]), &retention, now, |path| {
+            attempts.push(path.display().to_string());
+            false
+        });
+        assert_eq!(count, 0);
+        assert_eq!(attempts, ["1.png", "2.png"]);
+    }
+
+    #[test]
+    fn prune_without_limits_keeps_everything() {
+        let now = SystemTime::now();
+        assert!(pruned(&captures(now, &[MB; 5]), &Retention::default(), now).is_empty());
+    }
+
+    #[test]
+    fn zero_retention_limits_are_rejected() {
+        assert!(Retention::default().validate().is_ok());
+        for retention in [
+            Retention {
+                max_files: Some(0),
+                ..Default::default()
+            },
+            Retention {
+                max_total_mb: Some(0),
+                ..Default::default()
+            },
+            Retention {
+                max_age_days: Some(0),
+                ..Default::default()
+            },
+        ] {
+            assert!(retention.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn saved_captures_lists_matching_images_newest_first() {
+        let dir =
+            std::env::temp_dir().join(format!("screenshot-maker-retention-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let now = SystemTime::now();
+        for (name, age_days) in [
+            ("Screenshot_2024-01-02_10-00-00.png", 1),
+            ("Screenshot_2024-01-01_10-00-00_2.jpg", 2),
+            ("Screenshot_2024-01-03_10-00-00.png", 0),
+            ("Screenshot_2024-01-03_10-00-00.txt", 0),
+            ("holiday.png", 0),
+        ] {
+            let file = fs::File::create(dir.join(name)).unwrap();
+            file.set_modified(now - Duration::from_secs(age_days * SECONDS_PER_DAY))
+                .unwrap();
+        }
+
+        let template = FilenameTemplate::parse(crate::filename::DEFAULT_FILENAME_TEMPLATE).unwrap();
+        let names: Vec<_> = saved_captures(&dir, &template)
+            .iter()
+            .map(|capture| {
+                capture
+                    .path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            names,
+            [
+                "Screenshot_2024-01-03_10-00-00.png",
+                "Screenshot_2024-01-02_10-00-00.png",
+                "Screenshot_2024-01-01_10-00-00_2.jpg",
+            ]
+        );
+    }
+}
@@ -1,18 +1,347 @@
+use crate::activity::{self, Activity};
+use crate::clipboard::{copy_current_screenshot, copy_last_screenshot};
+use crate::save::auto_save_dir;
+use crate::screenshot::{
+    self, local_time, mark_viewed, set_current_screenshot, show_editor_window,
+    trigger_region_capture, trigger_screenshot, LockExt, ScreenshotState, TriggerSource,
+};
+use crate::settings::SettingsState;
+use crate::shortcuts::{self, ShortcutAction};
+use crate::storage::default_screenshot_dir;
+use screenshots::image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, TryLockError};
+use std::time::{Duration, Instant};
 use tauri::{
     image::Image,
+    menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu},
     tray::TrayIconBuilder,
-    App, Manager,
+    AppHandle, Emitter, Manager, Theme, WindowEvent, Wry,
 };
+use tauri_plugin_opener::OpenerExt;
+
+/// Longest time quitting waits for captures and background writes to finish
+const QUIT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Name the tooltips start with
+const APP_NAME: &str = "Screenshot Maker";
+
+/// Id of the tray icon
+const TRAY_ID: &str = "main-tray";
+
+/// Tray icons shown while idle, a black glyph for light menu bars and
+/// taskbars and a white one for dark ones. macOS tints the black one itself,
+/// as a template image.
+const IDLE_LIGHT_ICON: &[u8] = include_bytes!("../icons/tray/idle-light.png");
+const IDLE_DARK_ICON: &[u8] = include_bytes!("../icons/tray/idle-dark.png");
+
+/// Tray icons shown while a capture is in progress, with a red badge
+const CAPTURING_LIGHT_ICON: &[u8] = include_bytes!("../icons/tray/capturing-light.png");
+const CAPTURING_DARK_ICON: &[u8] = include_bytes!("../icons/tray/capturing-dark.png");
+
+/// Tray icons shown after a capture failed, with an amber badge
+const ERROR_LIGHT_ICON: &[u8] = include_bytes!("../icons/tray/error-light.png");
+const ERROR_DARK_ICON: &[u8] = include_bytes!("../icons/tray/error-dark.png");
+
+/// Time the capturing icon stays up after a capture completes, so quick
+/// captures still show it
+const IDLE_DELAY: Duration = Duration::from_millis(600);
+
+/// Ids of the tray menu items
+const TAKE_SCREENSHOT_ID: &str = "take-screenshot";
+const CAPTURE_REGION_ID: &str = "capture-region";
+const COPY_LAST_ID: &str = "copy-last-screenshot";
+const OPEN_EDITOR_ID: &str = "open-editor";
+const RECENT_ID: &str = "recent";
+const OPEN_FOLDER_ID: &str = "open-screenshots-folder";
+const SUSPEND_SHORTCUTS_ID: &str = "suspend-shortcuts";
+const DISMISS_ERROR_ID: &str = "dismiss-error";
+const PAUSE_ID: &str = "pause";
+const SETTINGS_ID: &str = "settings";
+const QUIT_ID: &str = "quit";
+
+/// Prefix of the ids of the items in the Recent submenu, followed by the screenshot id
+const RECENT_ITEM_PREFIX: &str = "recent-";
+
+/// Number of screenshots listed in the Recent submenu
+const RECENT_COUNT: usize = 5;
+
+/// What a left click on the tray icon does; a double click always opens the editor
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TrayClickAction {
+    /// Shows the editor window, or hides it when shown
+    #[default]
+    ToggleWindow,
+    /// Takes a screenshot like the Take Screenshot menu item
+    Capture,
+    OpenEditor,
+    /// Opens the tray menu, like a right click. Not supported on Linux, where
+    /// any click opens it, nor for the middle click.
+    ShowMenu,
+    /// Copies the current screenshot to the clipboard
+    CopyCurrent,
+}
+
+/// What the tray icon shows the app is doing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrayState {
+    #[default]
+    Idle,
+    Capturing,
+    /// A capture failed; kept until the next successful capture or until
+    /// dismissed from the tray menu
+    Error,
+}
+
+impl TrayState {
+    /// Icon shown in this state on a light or `dark` background, decoded from
+    /// the PNG embedded in the binary
+    fn icon(self, dark: bool) -> RgbaImage {
+        let png = match (self, dark) {
+            (TrayState::Idle, false) => IDLE_LIGHT_ICON,
+            (TrayState::Idle, true) => IDLE_DARK_ICON,
+            (TrayState::Capturing, false) => CAPTURING_LIGHT_ICON,
+            (TrayState::Capturing, true) => CAPTURING_DARK_ICON,
+            (TrayState::Error, false) => ERROR_LIGHT_ICON,
+            (TrayState::Error, true) => ERROR_DARK_ICON,
+        };
+        screenshots::image::load_from_memory(png)
+            .expect("Embedded tray icons are valid PNGs")
+            .to_rgba8()
+    }
+
+    /// Tooltip shown in this state; see `idle_tooltip` for the idle one
+    fn tooltip(self, app: &AppHandle, state: &ScreenshotState) -> String {
+        match self {
+            TrayState::Idle => idle_tooltip(app, state),
+            TrayState::Capturing => format!("{} — capturing…", APP_NAME),
+            TrayState::Error => format!("{} — the last capture failed", APP_NAME),
+        }
+    }
+}
+
+/// Tooltip shown while idle: the last capture time and history size, or
+/// the capture shortcut before anything was captured
+fn idle_tooltip(app: &AppHandle, state: &ScreenshotState) -> String {
+    let (count, last_captured_at) = {
+        let screenshots = state.screenshots.locked();
+        let thumbnail_history = state.thumbnail_history.locked();
+        let last_captured_at = screenshots
+            .values()
+            .map(|stored| stored.captured_at)
+            .chain(thumbnail_history.values().map(|entry| entry.captured_at))
+            .max();
+        (
+            screenshots.len() + thumbnail_history.len(),
+            last_captured_at,
+        )
+    };
+
+    match last_captured_at {
+        Some(captured_at) => format!(
+            "{} — last capture {}, {} in history",
+            APP_NAME,
+            local_time(captured_at).format("%H:%M"),
+            count
+        ),
+        None => match shortcuts::active_accelerator(app, ShortcutAction::FullScreen) {
+            Some(accelerator) => format!(
+                "{} — press {} to take a screenshot",
+                APP_NAME,
+                display_accelerator(&accelerator)
+            ),
+            None => APP_NAME.to_string(),
+        },
+    }
+}
+
+/// Accelerator as shown to users, e.g. `Ctrl+Alt+S` for `ctrl+alt+s`
+fn display_accelerator(accelerator: &str) -> String {
+    accelerator
+        .split('+')
+        .map(|key| {
+            let mut chars = key.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<String>>()
+        .join("+")
+}
+
+/// Digits 0 to 9 and `+` of the badge font, 3 pixels wide and 5 high. Each
+/// row is a byte whose bit 2 is the leftmost pixel.
+const BADGE_GLYPHS: [[u8; 5]; 11] = [
+    [7, 5, 5, 5, 7],
+    [2, 6, 2, 2, 7],
+    [7, 1, 7, 4, 7],
+    [7, 1, 7, 1, 7],
+    [5, 5, 7, 1, 1],
+    [7, 4, 7, 1, 7],
+    [7, 4, 7, 5, 7],
+    [7, 1, 1, 1, 1],
+    [7, 5, 7, 5, 7],
+    [7, 5, 7, 1, 7],
+    [0, 2, 7, 2, 0],
+];
+
+/// Opacity the tray icon is faded to while paused
+const PAUSED_OPACITY: f32 = 0.4;
+
+/// Color of the unviewed captures badge
+const BADGE_COLOR: Rgba<u8> = Rgba([229, 57, 53, 255]);
+
+/// Draws `count` in a red badge over the top right corner of `icon`; counts
+/// above 9 read `9+` and 0 draws nothing
+fn draw_badge(icon: &mut RgbaImage, count: usize) {
+    if count == 0 {
+        return;
+    }
+    let text = if count > 9 {
+        "9+".to_string()
+    } else {
+        count.to_string()
+    };
+    let glyphs: Vec<[u8; 5]> = text
+        .chars()
+        .map(|c| match c.to_digit(10) {
+            Some(digit) => BADGE_GLYPHS[digit as usize],
+            None => BADGE_GLYPHS[10],
+        })
+        .collect();
+
+    // Font pixels are scaled up with the icon, 2 on the 32 pixel one
+    let scale = (icon.height() / 16).max(1);
+    let text_width = (glyphs.len() as u32 * 4 - 1) * scale;
+    let text_height = 5 * scale;
+    let height = text_height + 2 * scale;
+    let width = (text_width + 2 * scale).max(height);
+    let left = icon.width().saturating_sub(width);
+
+    // A circle for one digit, stretched into a pill for two
+    let radius = height as f32 / 2.0;
+    let first_center = left as f32 + radius;
+    let last_center = (left + width) as f32 - radius;
+    for y in 0..height.min(icon.height()) {
+        for x in left..icon.width() {
+            let px = x as f32 + 0.5;
+            let py = y as f32 + 0.5;
+            let dx = px - px.clamp(first_center, last_center);
+            let dy = py - radius;
+            if dx * dx + dy * dy <= radius * radius {
+                icon.put_pixel(x, y, BADGE_COLOR);
+            }
+        }
+    }
+
+    let text_left = left + (width - text_width) / 2;
+    let text_top = (height - text_height) / 2;
+    for (index, glyph) in glyphs.iter().enumerate() {
+        let glyph_left = text_left + index as u32 * 4 * scale;
+        for (row, bits) in glyph.iter().enumerate() {
+            for column in 0..3 {
+                if bits >> (2 - column) & 1 == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let x = glyph_left + column * scale + dx;
+                        let y = text_top + row as u32 * scale + dy;
+                        if x < icon.width() && y < icon.height() {
+                            icon.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
 
-/// Initializes the tray icon with click handlers
-pub fn initialize_tray(app: &App) -> Result<(), tauri::Error> {
-    app.handle();
-    // Build tray icon
-    let _tray = TrayIconBuilder::with_id("main-tray")
-        .icon(Image::from_path("icons/32x32.png").unwrap_or_else(|_| {
-            app.default_window_icon().unwrap().clone()
-        }))
-        .show_menu_on_left_click(true)
+/// Tray state shown, with a generation counter cancelling the pending return
+/// to idle when the state changes meanwhile
+#[derive(Default)]
+struct TrayFeedback {
+    state: Mutex<TrayState>,
+    generation: AtomicU64,
+    /// Bumped on every change to what the icon shows, so a redraw that raced
+    /// with a newer one draws again, see `show_current_tray_state`
+    revision: AtomicU64,
+    /// Whether the system theme is dark, picking the icon variants
+    dark: AtomicBool,
+}
+
+/// Errors returned by `retry_tray_init` and `get_tray_status`, and emitted
+/// with `tray-unavailable`.
+/// Serialized with a `kind` tag like `WindowError`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "camelCase")]
+pub enum TrayError {
+    /// The tray menu couldn't be built
+    Menu(String),
+    /// The tray icon couldn't be created, e.g. on Linux without a StatusNotifier host
+    Unavailable(String),
+}
+
+impl From<TrayError> for String {
+    fn from(error: TrayError) -> Self {
+        match error {
+            TrayError::Menu(message) | TrayError::Unavailable(message) => message,
+        }
+    }
+}
+
+/// Why the tray icon couldn't be created on the last attempt, `None` once
+/// it's shown. Kept for `get_tray_status`, since `tray-unavailable` is
+/// emitted on startup before the frontend may listen for it.
+#[derive(Default)]
+pub struct TrayStatus(Mutex<Option<TrayError>>);
+
+/// The tray menu, with the items whose state follows the app's
+struct TrayMenu {
+    menu: Menu<Wry>,
+    copy_last: MenuItem<Wry>,
+    recent: Submenu<Wry>,
+    suspend_shortcuts: CheckMenuItem<Wry>,
+    pause: CheckMenuItem<Wry>,
+    dismiss_error: MenuItem<Wry>,
+}
+
+/// Initializes the tray icon with click handlers and its menu, recording the
+/// outcome for `get_tray_status` and emitting `tray-unavailable` with the
+/// error when it fails. The menu is built only once, so this can run again
+/// after the icon couldn't be created; it does nothing once the icon exists.
+pub fn initialize_tray(app: &AppHandle) -> Result<(), TrayError> {
+    let result = create_tray(app);
+    *app.state::<TrayStatus>().0.locked() = result.clone().err();
+    if let Err(error) = &result {
+        app.emit("tray-unavailable", error.clone());
+    }
+    result
+}
+
+/// Creates the tray icon, see `initialize_tray`
+fn create_tray(app: &AppHandle) -> Result<(), TrayError> {
+    if app.tray_by_id(TRAY_ID).is_some() {
+        return Ok(());
+    }
+    if app.try_state::<TrayMenu>().is_none() {
+        build_menu(app).map_err(|e| TrayError::Menu(e.to_string()))?;
+    }
+
+    let settings = app.state::<SettingsState>().get();
+    let dark = app.state::<TrayFeedback>().dark.load(Ordering::SeqCst);
+    let _tray = TrayIconBuilder::with_id(TRAY_ID)
+        .icon(tray_image(TrayState::Idle.icon(dark)))
+        .icon_as_template(true)
+        .tooltip(idle_tooltip(app, &app.state()))
+        .menu(&app.state::<TrayMenu>().menu)
+        .show_menu_on_left_click(settings.tray_left_click_action == TrayClickAction::ShowMenu)
+        .on_menu_event(|app, event| handle_menu_event(app, event.id().as_ref()))
         .on_tray_icon_event(|tray, event| {
             use tauri::tray::TrayIconEvent;
             use tauri::tray::{MouseButton, MouseButtonState};
@@ -22,24 +351,624 @@ pub fn initialize_tray(app: &App) -> Result<(), tauri::Error> {
                     button_state: MouseButtonState::Up,
                     ..
                 } => {
-                    handle_tray_click(tray.app_handle());
+                    let app = tray.app_handle();
+                    handle_tray_click(
+                        app,
+                        app.state::<SettingsState>().get().tray_left_click_action,
+                    );
+                }
+                TrayIconEvent::Click {
+                    button: MouseButton::Middle,
+                    button_state: MouseButtonState::Up,
+                    ..
+                } => {
+                    let app = tray.app_handle();
+                    handle_tray_click(
+                        app,
+                        app.state::<SettingsState>().get().tray_middle_click_action,
+                    );
+                }
+                TrayIconEvent::DoubleClick {
+                    button: MouseButton::Left,
+                    ..
+                } => {
+                    let _ = show_editor_window(tray.app_handle().clone());
                 }
                 _ => {}
             }
         })
-        .build(app)?;
+        .build(app)
+        .map_err(|e| TrayError::Unavailable(e.to_string()))?;
 
+    // A retry may come after captures, pauses or failures
+    refresh_tray_icon(app);
     Ok(())
 }
 
-/// Handles the tray icon click event by toggling window visibility
-fn handle_tray_click(app: &tauri::AppHandle) {
-    if let Some(window) = app.get_webview_window("main") {
-        if window.is_visible().unwrap_or(false) {
-            window.hide();
-        } else {
-            window.show();
-            let _ = window.set_focus();
+/// Tauri command: Tries to create the tray icon again after it was
+/// unavailable on startup, e.g. once a tray extension is installed
+#[tauri::command]
+pub fn retry_tray_init(app: AppHandle) -> Result<(), TrayError> {
+    initialize_tray(&app)
+}
+
+/// Tauri command: Retrieves why the tray icon couldn't be created, `None` when
+/// it's shown. The frontend offers `retry_tray_init` while this is set.
+#[tauri::command]
+pub fn get_tray_status(status: tauri::State<TrayStatus>) -> Option<TrayError> {
+    status.0.locked().clone()
+}
+
+/// Builds the tray menu and the state behind the tray icon
+fn build_menu(app: &AppHandle) -> Result<(), tauri::Error> {
+    let settings = app.state::<SettingsState>().get();
+    let copy_last = MenuItem::with_id(
+        app,
+        COPY_LAST_ID,
+        "Copy Last Screenshot",
+        has_screenshot(app),
+        None::<&str>,
+    )?;
+    let recent = Submenu::with_id(app, RECENT_ID, "Recent", false)?;
+    let suspend_shortcuts = CheckMenuItem::with_id(
+        app,
+        SUSPEND_SHORTCUTS_ID,
+        "Suspend shortcuts",
+        true,
+        settings.shortcuts_suspended,
+        None::<&str>,
+    )?;
+    let pause = CheckMenuItem::with_id(app, PAUSE_ID, "Pause", true, false, None::<&str>)?;
+    let dismiss_error =
+        MenuItem::with_id(app, DISMISS_ERROR_ID, "Dismiss Error", false, None::<&str>)?;
+    let menu = Menu::with_items(
+        app,
+        &[
+            &MenuItem::with_id(
+                app,
+                TAKE_SCREENSHOT_ID,
+                "Take Screenshot",
+                true,
+                None::<&str>,
+            )?,
+            &MenuItem::with_id(app, CAPTURE_REGION_ID, "Capture Region", true, None::<&str>)?,
+            &copy_last,
+            &MenuItem::with_id(app, OPEN_EDITOR_ID, "Open Editor", true, None::<&str>)?,
+            &recent,
+            &MenuItem::with_id(
+                app,
+                OPEN_FOLDER_ID,
+                "Open Screenshots Folder",
+                true,
+                None::<&str>,
+            )?,
+            &suspend_shortcuts,
+            &pause,
+            &dismiss_error,
+            &PredefinedMenuItem::separator(app)?,
+            &MenuItem::with_id(app, SETTINGS_ID, "Settings…", true, None::<&str>)?,
+            &MenuItem::with_id(app, QUIT_ID, "Quit", true, None::<&str>)?,
+        ],
+    )?;
+    app.manage(TrayMenu {
+        menu,
+        copy_last,
+        recent,
+        suspend_shortcuts,
+        pause,
+        dismiss_error,
+    });
+    let main_window = app.get_webview_window("main");
+    let dark = main_window
+        .as_ref()
+        .and_then(|window| window.theme().ok())
+        .is_some_and(|theme| theme == Theme::Dark);
+    app.manage(TrayFeedback {
+        dark: AtomicBool::new(dark),
+        ..Default::default()
+    });
+    refresh_tray_menu(app);
+
+    // Swap the icon variant when the system theme changes
+    if let Some(window) = main_window {
+        let app_handle = app.clone();
+        window.on_window_event(move |event| {
+            if let WindowEvent::ThemeChanged(theme) = event {
+                set_tray_theme(&app_handle, *theme);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Runs the action of the tray menu item with id `id`
+fn handle_menu_event(app: &AppHandle, id: &str) {
+    match id {
+        TAKE_SCREENSHOT_ID => trigger_screenshot(app, true, TriggerSource::Tray),
+        CAPTURE_REGION_ID => trigger_region_capture(app, TriggerSource::Tray),
+        COPY_LAST_ID => copy_last_screenshot(app, TriggerSource::Tray),
+        OPEN_EDITOR_ID => {
+            let _ = show_editor_window(app.clone());
+        }
+        OPEN_FOLDER_ID => open_screenshots_folder(app),
+        SUSPEND_SHORTCUTS_ID => toggle_shortcuts(app),
+        PAUSE_ID => {
+            let paused = app.state::<ScreenshotState>().paused.load(Ordering::SeqCst);
+            screenshot::set_paused(app.clone(), app.state(), !paused);
+        }
+        DISMISS_ERROR_ID => set_tray_state(app, TrayState::Idle),
+        SETTINGS_ID => {
+            // There's no settings window; the editor shows its settings panel on this event
+            let _ = show_editor_window(app.clone());
+            app.emit_to("main", "open-settings", ());
+        }
+        QUIT_ID => quit(app),
+        _ => {
+            if let Some(id) = id
+                .strip_prefix(RECENT_ITEM_PREFIX)
+                .and_then(|id| id.parse().ok())
+            {
+                open_recent(app, id);
+            }
+        }
+    }
+}
+
+/// Shuts down cleanly: releases the global shortcuts, waits up to
+/// `QUIT_TIMEOUT` for a running capture and its auto-save and recovery
+/// writes, then exits. Quitting before they finish is logged as
+/// `QuitWithPendingWork`. Waits off the main thread, which captures need to
+/// hide and restore windows.
+fn quit(app: &AppHandle) {
+    shortcuts::unregister_all(app);
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let state = app.state::<ScreenshotState>();
+        // A lock poisoned by a panicked capture isn't held by anyone
+        let is_capturing = || {
+            state.trigger_in_progress.is_busy()
+                || matches!(state.capture_lock.try_lock(), Err(TryLockError::WouldBlock))
+        };
+        let started = Instant::now();
+        loop {
+            let pending_writes = state.pending_writes.load(Ordering::SeqCst);
+            let capturing = is_capturing();
+            if !capturing && pending_writes == 0 {
+                break;
+            }
+            if started.elapsed() >= QUIT_TIMEOUT {
+                eprintln!("Quitting with a capture or write still running");
+                activity::log_event(
+                    &app,
+                    None,
+                    Activity::QuitWithPendingWork {
+                        capturing,
+                        pending_writes,
+                    },
+                );
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        app.exit(0);
+    });
+}
+
+/// Makes screenshot `id` the current one, moving it to the back of the
+/// eviction order, and shows it in the editor
+fn open_recent(app: &AppHandle, id: u64) {
+    if let Err(error) = set_current_screenshot(app.clone(), app.state(), id, Some(true)) {
+        eprintln!("Could not open recent screenshot {}: {}", id, error);
+        return;
+    }
+    let _ = show_editor_window(app.clone());
+}
+
+/// Payload of the `open-folder-failed` event
+#[derive(Clone, Debug, Serialize)]
+pub struct OpenFolderFailed {
+    /// Folder that couldn't be opened, for the UI to show as copyable text;
+    /// `None` when no folder could be found
+    pub path: Option<String>,
+    pub error: String,
+}
+
+/// Opens the folder screenshots are saved to in the file manager: the
+/// auto-save folder while auto-save is on, else the default one. It's
+/// created first when missing. When it can't be found or opened, e.g. on
+/// Linux without `xdg-open`, emits `open-folder-failed`.
+fn open_screenshots_folder(app: &AppHandle) {
+    let settings = app.state::<SettingsState>().get();
+    let dir = if settings.auto_save.enabled {
+        auto_save_dir(app, &settings.auto_save)
+    } else {
+        default_screenshot_dir(app)
+    };
+    let dir = match dir {
+        Ok(dir) => dir,
+        Err(error) => {
+            eprintln!("Could not find the screenshots folder: {}", error);
+            app.emit("open-folder-failed", OpenFolderFailed { path: None, error });
+            return;
+        }
+    };
+
+    let opened = fs::create_dir_all(&dir)
+        .map_err(|e| e.to_string())
+        .and_then(|()| {
+            app.opener()
+                .open_path(dir.to_string_lossy(), None::<&str>)
+                .map_err(|e| e.to_string())
+        });
+    if let Err(error) = opened {
+        eprintln!("Could not open the screenshots folder: {}", error);
+        app.emit(
+            "open-folder-failed",
+            OpenFolderFailed {
+                path: Some(dir.to_string_lossy().into_owned()),
+                error,
+            },
+        );
+    }
+}
+
+/// Whether the history holds a screenshot with its full image, so there's
+/// one to copy
+fn has_screenshot(app: &AppHandle) -> bool {
+    let state = app.state::<ScreenshotState>();
+    let in_memory = !state.screenshots.locked().is_empty();
+    in_memory
+        || state
+            .thumbnail_history
+            .locked()
+            .values()
+            .any(|entry| entry.full_image)
+}
+
+/// Id and menu text of the newest screenshots with their full image, newest
+/// first. They're listed by label, or by capture time when unlabeled.
+fn recent_screenshots(app: &AppHandle) -> Vec<(u64, String)> {
+    let state = app.state::<ScreenshotState>();
+    let mut recent: Vec<(u64, u64, Option<String>)> = state
+        .screenshots
+        .locked()
+        .iter()
+        .map(|(id, stored)| (*id, stored.captured_at, stored.label.clone()))
+        .collect();
+    recent.extend(
+        state
+            .thumbnail_history
+            .locked()
+            .values()
+            .filter(|entry| entry.full_image)
+            .map(|entry| (entry.id, entry.captured_at, entry.label.clone())),
+    );
+    recent.sort_by_key(|(id, captured_at, _)| Reverse((*captured_at, *id)));
+    recent
+        .into_iter()
+        .take(RECENT_COUNT)
+        .map(|(id, captured_at, label)| {
+            let text = label.unwrap_or_else(|| {
+                local_time(captured_at)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            });
+            (id, text)
+        })
+        .collect()
+}
+
+/// Updates the tray menu items depending on the history, after a screenshot
+/// is taken, deleted or renamed. The Recent submenu's items are replaced in
+/// place, so the tray icon and its menu are kept.
+pub fn refresh_tray_menu(app: &AppHandle) {
+    let Some(menu) = app.try_state::<TrayMenu>() else {
+        return;
+    };
+    let _ = menu.copy_last.set_enabled(has_screenshot(app));
+
+    if let Ok(items) = menu.recent.items() {
+        for item in items {
+            let _ = menu.recent.remove(&item);
+        }
+    }
+    let recent = recent_screenshots(app);
+    for (id, text) in &recent {
+        let item = MenuItem::with_id(
+            app,
+            format!("{}{}", RECENT_ITEM_PREFIX, id),
+            text,
+            true,
+            None::<&str>,
+        );
+        if let Ok(item) = item {
+            let _ = menu.recent.append(&item);
+        }
+    }
+    let _ = menu.recent.set_enabled(!recent.is_empty());
+}
+
+/// Suspends the global shortcuts, or resumes them when suspended
+fn toggle_shortcuts(app: &AppHandle) {
+    let result = if shortcuts::suspended(app) {
+        shortcuts::resume(app)
+    } else {
+        shortcuts::suspend(app, false)
+    };
+    if let Err(error) = result {
+        eprintln!("Could not toggle the shortcuts: {}", error);
+    }
+    // The item checks itself when clicked, so keep it in line with the outcome
+    set_shortcuts_suspended(app, shortcuts::suspended(app));
+}
+
+/// Checks the tray menu's suspend item when the shortcuts are suspended
+pub fn set_shortcuts_suspended(app: &AppHandle, suspended: bool) {
+    if let Some(menu) = app.try_state::<TrayMenu>() {
+        let _ = menu.suspend_shortcuts.set_checked(suspended);
+    }
+    update_tray_tooltip(app, &app.state());
+}
+
+/// Checks the tray menu's pause item and fades the icon while paused
+pub fn set_paused(app: &AppHandle, paused: bool) {
+    if let Some(menu) = app.try_state::<TrayMenu>() {
+        let _ = menu.pause.set_checked(paused);
+    }
+    refresh_tray_icon(app);
+}
+
+/// Handles a tray icon click by running `action`, the setting of its button
+fn handle_tray_click(app: &tauri::AppHandle, action: TrayClickAction) {
+    match action {
+        TrayClickAction::ToggleWindow => {
+            if let Some(window) = app.get_webview_window("main") {
+                if window.is_visible().unwrap_or(false) {
+                    window.hide();
+                } else {
+                    window.show();
+                    let _ = window.set_focus();
+                    mark_viewed(app);
+                }
+            }
+        }
+        TrayClickAction::Capture => trigger_screenshot(app, true, TriggerSource::Tray),
+        TrayClickAction::OpenEditor => {
+            let _ = show_editor_window(app.clone());
+        }
+        // The menu opens by itself
+        TrayClickAction::ShowMenu => {}
+        TrayClickAction::CopyCurrent => copy_current_screenshot(app),
+    }
+}
+
+/// Swaps the tray icon and tooltip for those of `state`, cancelling a
+/// pending `finish_tray_state`
+pub fn set_tray_state(app: &AppHandle, state: TrayState) {
+    let Some(feedback) = app.try_state::<TrayFeedback>() else {
+        return;
+    };
+    {
+        let mut shown = feedback.state.locked();
+        feedback.generation.fetch_add(1, Ordering::SeqCst);
+        feedback.revision.fetch_add(1, Ordering::SeqCst);
+        *shown = state;
+    }
+    show_current_tray_state(app, &feedback);
+}
+
+/// Updates the idle tooltip after the history or the capture shortcut
+/// changed; the capturing and error tooltips are left alone
+pub fn update_tray_tooltip(app: &AppHandle, state: &ScreenshotState) {
+    let Some(feedback) = app.try_state::<TrayFeedback>() else {
+        return;
+    };
+    let revision = feedback.revision.load(Ordering::SeqCst);
+    if *feedback.state.locked() != TrayState::Idle {
+        return;
+    }
+    if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        let _ = tray.set_tooltip(Some(idle_tooltip(app, state)));
+    }
+    // The state changed while the tooltip was set, which may have overwritten its own
+    if feedback.revision.load(Ordering::SeqCst) != revision {
+        show_current_tray_state(app, &feedback);
+    }
+}
+
+/// Returns the tray icon to idle after `IDLE_DELAY`, once a capture
+/// completed, unless its state changes meanwhile
+pub fn finish_tray_state(app: &AppHandle) {
+    let Some(feedback) = app.try_state::<TrayFeedback>() else {
+        return;
+    };
+    let generation = feedback.generation.load(Ordering::SeqCst);
+    let app = app.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(IDLE_DELAY);
+        let feedback = app.state::<TrayFeedback>();
+        {
+            let mut shown = feedback.state.locked();
+            if feedback.generation.load(Ordering::SeqCst) != generation {
+                return;
+            }
+            feedback.revision.fetch_add(1, Ordering::SeqCst);
+            *shown = TrayState::Idle;
+        }
+        show_current_tray_state(&app, &feedback);
+    });
+}
+
+/// Shows the icon variant for `theme`, keeping the state shown
+fn set_tray_theme(app: &AppHandle, theme: Theme) {
+    let Some(feedback) = app.try_state::<TrayFeedback>() else {
+        return;
+    };
+    feedback.dark.store(theme == Theme::Dark, Ordering::SeqCst);
+    feedback.revision.fetch_add(1, Ordering::SeqCst);
+    show_current_tray_state(app, &feedback);
+}
+
+/// Redraws the tray icon in its current state, after the unviewed count or
+/// the pause changed
+pub fn refresh_tray_icon(app: &AppHandle) {
+    let Some(feedback) = app.try_state::<TrayFeedback>() else {
+        return;
+    };
+    feedback.revision.fetch_add(1, Ordering::SeqCst);
+    show_current_tray_state(app, &feedback);
+}
+
+/// Shows the state in `feedback` with `show_tray_state`, outside its lock:
+/// the tray calls block until the main thread runs them, and the main thread
+/// takes the lock itself in menu and theme handlers. Draws again if the state
+/// changed meanwhile, so the last icon drawn is never a stale one.
+fn show_current_tray_state(app: &AppHandle, feedback: &TrayFeedback) {
+    loop {
+        let revision = feedback.revision.load(Ordering::SeqCst);
+        let state = *feedback.state.locked();
+        show_tray_state(app, state);
+        if feedback.revision.load(Ordering::SeqCst) == revision {
+            break;
+        }
+    }
+}
+
+/// Tray image of an icon bitmap
+fn tray_image(icon: RgbaImage) -> Image<'static> {
+    let (width, height) = icon.dimensions();
+    Image::new_owned(icon.into_raw(), width, height)
+}
+
+/// Updates the tray icon, tooltip and Dismiss Error item to `state`, in the
+/// variant for the system theme, faded while paused and badged with the
+/// unviewed captures. Only
+/// the unbadged idle icon is a template image on macOS, so badges keep their
+/// color. The tooltip is set again after the icon, as some platforms drop it
+/// on a swap.
+fn show_tray_state(app: &AppHandle, state: TrayState) {
+    let dark = app
+        .try_state::<TrayFeedback>()
+        .is_some_and(|feedback| feedback.dark.load(Ordering::SeqCst));
+    let screenshot_state = app.state::<ScreenshotState>();
+    let unviewed = screenshot_state.unviewed.load(Ordering::SeqCst);
+    let paused = screenshot_state.paused.load(Ordering::SeqCst);
+    if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        let mut icon = state.icon(dark);
+        if paused {
+            for pixel in icon.pixels_mut() {
+                pixel[3] = (pixel[3] as f32 * PAUSED_OPACITY) as u8;
+            }
+        }
+        if unviewed > 0 {
+            draw_badge(&mut icon, unviewed);
+        }
+        let _ = tray.set_icon(Some(tray_image(icon)));
+        let _ = tray.set_icon_as_template(state == TrayState::Idle && unviewed == 0);
+        let _ = tray.set_tooltip(Some(state.tooltip(app, &app.state())));
+    }
+    if let Some(menu) = app.try_state::<TrayMenu>() {
+        let _ = menu.dismiss_error.set_enabled(state == TrayState::Error);
+    }
+}
+
+/// Makes the existing tray icon open its menu on left click, or not, after
+/// the `tray_left_click_action` setting changed
+pub fn apply_left_click_action(app: &AppHandle, action: TrayClickAction) {
+    if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        let _ = tray.set_show_menu_on_left_click(action == TrayClickAction::ShowMenu);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const CLEAR: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+    fn badged(size: u32, count: usize) -> RgbaImage {
+        let mut icon = RgbaImage::new(size, size);
+        draw_badge(&mut icon, count);
+        icon
+    }
+
+    #[test]
+    fn zero_draws_nothing() {
+        for size in [16, 32] {
+            assert_eq!(badged(size, 0), RgbaImage::new(size, size));
+        }
+    }
+
+    #[test]
+    fn single_digit_on_16px_icon() {
+        // 7x7 circle at x 9..16, "1" starting at (11, 1)
+        let icon = badged(16, 1);
+        assert_eq!(*icon.get_pixel(12, 1), WHITE);
+        assert_eq!(*icon.get_pixel(12, 5), WHITE);
+        assert_eq!(*icon.get_pixel(11, 1), BADGE_COLOR);
+        assert_eq!(*icon.get_pixel(12, 0), BADGE_COLOR);
+        assert_eq!(*icon.get_pixel(9, 0), CLEAR);
+        assert_eq!(*icon.get_pixel(8, 3), CLEAR);
+        assert_eq!(*icon.get_pixel(12, 7), CLEAR);
+        assert_eq!(*icon.get_pixel(0, 15), CLEAR);
+    }
+
+    #[test]
+    fn nine_on_16px_icon() {
+        let icon = badged(16, 9);
+        for x in 11..14 {
+            assert_eq!(*icon.get_pixel(x, 1), WHITE);
+        }
+        assert_eq!(*icon.get_pixel(11, 2), WHITE);
+        assert_eq!(*icon.get_pixel(12, 2), BADGE_COLOR);
+        assert_eq!(*icon.get_pixel(13, 2), WHITE);
+        assert_eq!(*icon.get_pixel(11, 4), BADGE_COLOR);
+        assert_eq!(*icon.get_pixel(8, 3), CLEAR);
+    }
+
+    #[test]
+    fn counts_above_nine_read_nine_plus_on_16px_icon() {
+        // 9x7 pill at x 7..16, "9" starting at (8, 1) and "+" at (12, 1)
+        let icon = badged(16, 10);
+        assert_eq!(*icon.get_pixel(8, 1), WHITE);
+        assert_eq!(*icon.get_pixel(10, 1), WHITE);
+        assert_eq!(*icon.get_pixel(12, 1), BADGE_COLOR);
+        assert_eq!(*icon.get_pixel(13, 2), WHITE);
+        for x in 12..15 {
+            assert_eq!(*icon.get_pixel(x, 3), WHITE);
+        }
+        assert_eq!(*icon.get_pixel(7, 3), BADGE_COLOR);
+        assert_eq!(*icon.get_pixel(6, 3), CLEAR);
+        assert_eq!(badged(16, 10), badged(16, 123));
+    }
+
+    #[test]
+    fn single_digit_on_32px_icon() {
+        // Scaled by 2: 14x14 circle at x 18..32, "1" starting at (22, 2)
+        let icon = badged(32, 1);
+        for (x, y) in [(24, 2), (25, 2), (24, 3), (25, 3)] {
+            assert_eq!(*icon.get_pixel(x, y), WHITE);
+        }
+        assert_eq!(*icon.get_pixel(22, 2), BADGE_COLOR);
+        assert_eq!(*icon.get_pixel(25, 13), BADGE_COLOR);
+        assert_eq!(*icon.get_pixel(25, 14), CLEAR);
+        assert_eq!(*icon.get_pixel(17, 7), CLEAR);
+    }
+
+    #[test]
+    fn nine_plus_on_32px_icon() {
+        // Scaled by 2: 18x14 pill at x 14..32, "9" at (16, 2) and "+" at (24, 2)
+        let icon = badged(32, 10);
+        for x in 16..22 {
+            assert_eq!(*icon.get_pixel(x, 2), WHITE);
+        }
+        for x in 24..30 {
+            assert_eq!(*icon.get_pixel(x, 6), WHITE);
+            assert_eq!(*icon.get_pixel(x, 7), WHITE);
         }
+        assert_eq!(*icon.get_pixel(24, 2), BADGE_COLOR);
+        assert_eq!(*icon.get_pixel(14, 7), BADGE_COLOR);
+        assert_eq!(*icon.get_pixel(13, 7), CLEAR);
     }
 }
@@ -0,0 +1,77 @@
+use crate::screenshot::Rect;
+use serde::Serialize;
+
+/// Titles of the app's own windows, excluded from window listings
+const OWN_WINDOW_TITLES: [&str; 3] = ["Screenshot Maker", "Screenshot Preview", "Select Region"];
+
+/// A visible top-level window that can be captured
+#[derive(Clone, Debug, Serialize)]
+pub struct WindowInfo {
+    /// Platform window identifier, passed back to `capture_window`
+    pub id: u32,
+    pub title: String,
+    pub app_name: String,
+    /// Window bounds on the virtual desktop
+    pub bounds: Rect,
+    pub is_minimized: bool,
+}
+
+/// Errors returned by the window enumeration commands.
+/// Serialized with a `kind` tag so the frontend can detect `unsupported`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "camelCase")]
+pub enum WindowError {
+    /// Window enumeration isn't available on this platform
+    #[allow(dead_code)]
+    Unsupported,
+    /// No window with the requested id exists
+    NotFound,
+    /// Enumeration or capture failed
+    Failed(String),
+}
+
+impl From<String> for WindowError {
+    fn from(message: String) -> Self {
+        Self::Failed(message)
+    }
+}
+
+/// Lists visible top-level windows, excluding the app's own windows.
+/// Minimized windows are included but flagged.
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+pub fn list_windows() -> Result<Vec<WindowInfo>, WindowError> {
+    let windows = xcap::Window::all().map_err(|e| WindowError::Failed(e.to_string()))?;
+
+    Ok(windows
+        .iter()
+        .filter(|window| !OWN_WINDOW_TITLES.contains(&window.title()))
+        .filter(|window| window.width() > 0 && window.height() > 0)
+        .map(|window| WindowInfo {
+            id: window.id(),
+            title: window.title().to_string(),
+            app_name: window.app_name().to_string(),
+            bounds: Rect {
+                x: window.x(),
+                y: window.y(),
+                width: window.width(),
+                height: window.height(),
+            },
+            is_minimized: window.is_minimized(),
+        })
+        .collect())
+}
+
+/// Lists visible top-level windows, excluding the app's own windows.
+/// Minimized windows are included but flagged.
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+pub fn list_windows() -> Result<Vec<WindowInfo>, WindowError> {
+    Err(WindowError::Unsupported)
+}
+
+/// Finds a capturable window by id
+pub fn find_window(id: u32) -> Result<WindowInfo, WindowError> {
+    list_windows()?
+        .into_iter()
+        .find(|window| window.id == id)
+        .ok_or(WindowError::NotFound)
+}
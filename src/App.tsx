@@ -2,9 +2,10 @@ import { useEffect, useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { Editor } from "./components/Editor";
 import { Preview } from "./components/Preview";
+import { Overlay } from "./components/Overlay";
 import "./App.css";
 
-type AppMode = "editor" | "preview";
+type AppMode = "editor" | "preview" | "overlay";
 
 function App() {
   const [mode, setMode] = useState<AppMode>("editor");
@@ -15,6 +16,8 @@ function App() {
     const urlMode = params.get("mode");
     if (urlMode === "preview") {
       setMode("preview");
+    } else if (urlMode === "overlay") {
+      setMode("overlay");
     }
   }, []);
 
@@ -24,6 +27,10 @@ function App() {
     await invoke("hide_preview_window");
   };
 
+  if (mode === "overlay") {
+    return <Overlay />;
+  }
+
   if (mode === "preview") {
     return <Preview onOpenEditor={handleOpenEditor} />;
   }
@@ -2,6 +2,7 @@ import { useEffect, useRef, useState, useCallback } from "react";
 import { Stage, Layer, Image as KonvaImage, Line, Rect, Circle, Arrow, Text, Transformer } from "react-konva";
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
+import type { ScreenshotPayload, ScreenshotTaken } from "../types";
 import { writeImage } from "@tauri-apps/plugin-clipboard-manager";
 import "./Editor.css";
 
@@ -43,13 +44,13 @@ export function Editor({ onClose }: EditorProps) {
 
   // Load screenshot
   useEffect(() => {
-    invoke<string | null>("get_current_screenshot").then((data) => {
-      if (data) {
-        loadImage(data);
+    invoke<ScreenshotPayload | null>("get_current_screenshot").then((payload) => {
+      if (payload) {
+        loadImage(payload);
       }
     });
 
-    const unlisten = listen<string>("screenshot-taken", (event) => {
+    const unlisten = listen<ScreenshotTaken>("screenshot-taken", (event) => {
       loadImage(event.payload);
       setShapes([]); // Clear previous shapes
     });
@@ -59,26 +60,29 @@ export function Editor({ onClose }: EditorProps) {
     };
   }, []);
 
-  const loadImage = (dataUrl: string) => {
+  const loadImage = ({ url, width, height }: { url: string; width: number; height: number }) => {
+    // Lay out the stage from the reported dimensions before the image decodes
+    if (containerRef.current) {
+      const containerWidth = containerRef.current.clientWidth - 40;
+      const containerHeight = containerRef.current.clientHeight - 40;
+      const scale = Math.min(
+        containerWidth / width,
+        containerHeight / height,
+        1
+      );
+      setStageSize({
+        width: width * scale,
+        height: height * scale,
+      });
+    }
+
     const img = document.createElement("img") as HTMLImageElement;
+    // Served from the screenshot protocol; keeps the canvas exportable
+    img.crossOrigin = "anonymous";
     img.onload = () => {
       setScreenshot(img);
-      // Calculate stage size to fit the container
-      if (containerRef.current) {
-        const containerWidth = containerRef.current.clientWidth - 40;
-        const containerHeight = containerRef.current.clientHeight - 40;
-        const scale = Math.min(
-          containerWidth / img.width,
-          containerHeight / img.height,
-          1
-        );
-        setStageSize({
-          width: img.width * scale,
-          height: img.height * scale,
-        });
-      }
     };
-    img.src = dataUrl;
+    img.src = url;
   };
 
   // Update transformer when selection changes
@@ -0,0 +1,106 @@
+import { useEffect, useRef, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import "./Overlay.css";
+
+interface Point {
+  x: number;
+  y: number;
+}
+
+interface Region {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+export function Overlay() {
+  const [frame, setFrame] = useState<string | null>(null);
+  const [start, setStart] = useState<Point | null>(null);
+  const [end, setEnd] = useState<Point | null>(null);
+  const selectionRef = useRef<Region | null>(null);
+
+  useEffect(() => {
+    // Get the frozen frame to select on
+    invoke<string>("get_frozen_frame").then(setFrame);
+
+    const handleKeyDown = (e: KeyboardEvent) => {
+      if (e.key === "Escape") {
+        invoke("cancel_frozen_capture");
+      }
+    };
+    window.addEventListener("keydown", handleKeyDown);
+
+    // Push-to-capture: releasing the shortcut captures the current selection
+    const unlisten = listen("push-to-capture-released", () => {
+      const selection = selectionRef.current;
+      if (selection && selection.width > 0 && selection.height > 0) {
+        invoke("confirm_frozen_selection", { ...selection });
+      } else {
+        invoke("cancel_frozen_capture");
+      }
+    });
+
+    return () => {
+      window.removeEventListener("keydown", handleKeyDown);
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  const selection: Region | null =
+    start && end
+      ? {
+          x: Math.min(start.x, end.x),
+          y: Math.min(start.y, end.y),
+          width: Math.abs(end.x - start.x),
+          height: Math.abs(end.y - start.y),
+        }
+      : null;
+  selectionRef.current = selection;
+
+  const handleMouseDown = (e: React.MouseEvent) => {
+    setStart({ x: e.clientX, y: e.clientY });
+    setEnd({ x: e.clientX, y: e.clientY });
+  };
+
+  const handleMouseMove = (e: React.MouseEvent) => {
+    if (start) {
+      setEnd({ x: e.clientX, y: e.clientY });
+    }
+  };
+
+  const handleMouseUp = async () => {
+    if (selection && selection.width > 0 && selection.height > 0) {
+      await invoke("confirm_frozen_selection", selection);
+    }
+    setStart(null);
+    setEnd(null);
+  };
+
+  if (!frame) {
+    return null;
+  }
+
+  return (
+    <div
+      className="overlay-container"
+      onMouseDown={handleMouseDown}
+      onMouseMove={handleMouseMove}
+      onMouseUp={handleMouseUp}
+    >
+      <img src={frame} alt="Frozen screen" className="overlay-frame" draggable={false} />
+      {selection && (
+        <div
+          className="overlay-selection"
+          style={{
+            left: selection.x,
+            top: selection.y,
+            width: selection.width,
+            height: selection.height,
+          }}
+        />
+      )}
+    </div>
+  );
+}
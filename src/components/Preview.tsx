@@ -1,6 +1,7 @@
 import { useEffect, useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
+import type { ScreenshotTaken } from "../types";
 import "./Preview.css";
 
 interface PreviewProps {
@@ -13,7 +14,7 @@ export function Preview({ onOpenEditor }: PreviewProps) {
 
   useEffect(() => {
     // Get current screenshot on mount
-    invoke<string | null>("get_current_screenshot").then((data) => {
+    invoke<string | null>("get_current_thumbnail").then((data) => {
       if (data) {
         setScreenshot(data);
         setIsVisible(true);
@@ -21,8 +22,8 @@ export function Preview({ onOpenEditor }: PreviewProps) {
     });
 
     // Listen for new screenshots
-    const unlisten = listen<string>("screenshot-taken", (event) => {
-      setScreenshot(event.payload);
+    const unlisten = listen<ScreenshotTaken>("screenshot-taken", (event) => {
+      setScreenshot(event.payload.thumbnail_data_url);
       setIsVisible(true);
     });
 
@@ -41,6 +42,11 @@ export function Preview({ onOpenEditor }: PreviewProps) {
     await invoke("hide_preview_window");
   };
 
+  const handleRetake = async (e: React.MouseEvent) => {
+    e.stopPropagation();
+    await invoke("retake_screenshot");
+  };
+
   if (!isVisible || !screenshot) {
     return null;
   }
@@ -50,6 +56,9 @@ export function Preview({ onOpenEditor }: PreviewProps) {
       <button className="preview-close" onClick={handleClose}>
         ×
       </button>
+      <button className="preview-retake" onClick={handleRetake} title="Retake">
+        ↻
+      </button>
       <img src={screenshot} alt="Screenshot preview" className="preview-image" />
       <div className="preview-hint">Click to edit</div>
     </div>
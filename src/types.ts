@@ -0,0 +1,45 @@
+/** Payload of the `screenshot-taken` event */
+export interface ScreenshotTaken {
+  id: number;
+  url: string;
+  thumbnail_data_url: string;
+  screen_index: number | null;
+  screen_id: number | null;
+  width: number;
+  height: number;
+  captured_at: number;
+  saved_path: string | null;
+  source: CaptureSource;
+  trigger: TriggerSource;
+}
+
+/** A rectangle in virtual-desktop coordinates */
+export interface Rect {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+/** How a screenshot was produced, tagged by `type` */
+export type CaptureSource =
+  | { type: "fullScreen"; screenId: number | null }
+  | { type: "region"; rect: Rect; screenId: number | null }
+  | { type: "window"; title: string }
+  | { type: "imported"; path: string }
+  | { type: "clipboard" };
+
+/** What started a capture */
+export type TriggerSource = "hotkey" | "tray" | "retake" | "ipc";
+
+/** Full-resolution screenshot returned by the capture and retrieval commands */
+export interface ScreenshotPayload {
+  id: number;
+  url: string;
+  data_url: string | null;
+  extension: string;
+  width: number;
+  height: number;
+  screen_id: number | null;
+  captured_at: number;
+}